@@ -0,0 +1,712 @@
+//! Sending large numbers of CIS-2 transfers, e.g., an airdrop to many holders.
+//!
+//! A single `transfer` transaction is limited by the maximum parameter size
+//! and by the energy that can be used by a single transaction. An [`Airdrop`]
+//! is therefore first planned using [`Cis2Contract::plan_airdrop`], which
+//! splits the transfers into chunks whose parameter fits the size limit, and
+//! dry-runs each chunk, splitting it further until the energy it uses is below
+//! the configured maximum. Transfers that are rejected on their own when
+//! dry-run are skipped.
+//!
+//! The resulting [`AirdropPlan`] is sent using
+//! [`Cis2Contract::execute_airdrop`], which sends one transaction for each
+//! chunk with sequential nonces, waits for all of them to be finalized, and
+//! returns an [`AirdropReport`] with the outcome for each recipient.
+//!
+//! Note that all chunks are dry-run against the same block, so the effects of
+//! earlier chunks are not taken into account when dry-running later ones. If,
+//! e.g., the sender's balance only covers part of the transfers, later chunks
+//! will be rejected when executed.
+use super::{Cis2Contract, Cis2DryRunError, Transfer};
+use crate::{
+    contract_client::ContractTransactionMetadata,
+    types::{hashes::BlockHash, transactions, RejectReason},
+    v2::{self, BlockIdentifier},
+};
+use concordium_base::{
+    base::{Energy, Nonce},
+    common::types::TransactionTime,
+    constants::MAX_PARAMETER_LEN,
+    contracts_common::{self, AccountAddress, Address, Amount, NewReceiveNameError},
+    hashes::TransactionHash,
+    smart_contracts::OwnedParameter,
+    transactions::send::GivenEnergy,
+};
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// The default maximum amount of energy a single transaction of an airdrop
+/// may use.
+pub const DEFAULT_MAX_CHUNK_ENERGY: Energy = Energy { energy: 500_000 };
+
+/// The number of bytes used to serialize the length of the list of transfers
+/// in the `transfer` parameter.
+const TRANSFERS_LENGTH_SIZE: usize = 2;
+
+/// A list of transfers to be sent by [`Cis2Contract::plan_airdrop`] and
+/// [`Cis2Contract::execute_airdrop`].
+#[derive(Debug, Clone)]
+pub struct Airdrop {
+    transfers:  Vec<Transfer>,
+    max_energy: Energy,
+    expiry:     Option<TransactionTime>,
+}
+
+impl Airdrop {
+    /// Construct an airdrop of the given transfers, which are sent in the
+    /// given order.
+    pub fn new(transfers: Vec<Transfer>) -> Self {
+        Self {
+            transfers,
+            max_energy: DEFAULT_MAX_CHUNK_ENERGY,
+            expiry: None,
+        }
+    }
+
+    /// Set the maximum amount of energy a single transaction may use,
+    /// including the margin added to the energy used when dry-running.
+    /// Defaults to [`DEFAULT_MAX_CHUNK_ENERGY`].
+    pub fn set_max_energy(self, max_energy: Energy) -> Self { Self { max_energy, ..self } }
+
+    /// Set the expiry time of the transactions. If not set the default is one
+    /// hour from the time the plan is executed.
+    pub fn set_expiry(self, expiry: TransactionTime) -> Self {
+        Self {
+            expiry: Some(expiry),
+            ..self
+        }
+    }
+
+    /// The transfers of the airdrop.
+    pub fn transfers(&self) -> &[Transfer] { &self.transfers }
+}
+
+/// An error that can occur when planning or executing an airdrop.
+#[derive(Debug, Error)]
+pub enum AirdropError {
+    /// The smart contract receive name is invalid.
+    #[error("Invalid receive name: {0}")]
+    InvalidReceiveName(#[from] NewReceiveNameError),
+    /// Dry-running a chunk failed for another reason than the transfers being
+    /// rejected.
+    #[error("Failed to dry-run the transfers: {0}")]
+    DryRun(#[from] Cis2DryRunError),
+    /// An error occurred when querying the node.
+    #[error("RPC error: {0}")]
+    Query(#[from] v2::QueryError),
+    /// The transfers of the chunk with the given index in the
+    /// [`AirdropPlan`] do not fit in a parameter.
+    #[error("The transfers of chunk {0} do not fit in a parameter.")]
+    ChunkTooLarge(usize),
+}
+
+/// The reason a transfer is not part of an [`AirdropPlan`].
+#[derive(Debug, Clone)]
+pub enum SkipReason {
+    /// The transfer was rejected when dry-run on its own.
+    Rejected(RejectReason),
+    /// The transfer uses more than the maximum energy on its own, including
+    /// the margin.
+    ExceedsMaxEnergy(Energy),
+    /// The serialized transfer does not fit in a parameter on its own.
+    ExceedsParameterSize(usize),
+}
+
+/// A transfer that is not part of an [`AirdropPlan`].
+#[derive(Debug, Clone)]
+pub struct SkippedTransfer {
+    /// The index of the transfer in the [`Airdrop`].
+    pub index:    usize,
+    /// The transfer.
+    pub transfer: Transfer,
+    /// The reason the transfer is skipped.
+    pub reason:   SkipReason,
+}
+
+/// Transfers that are sent in a single transaction.
+#[derive(Debug, Clone)]
+pub struct AirdropChunk {
+    /// The indices of the transfers in the [`Airdrop`].
+    pub indices:        Vec<usize>,
+    /// The transfers.
+    pub transfers:      Vec<Transfer>,
+    /// The size of the serialized parameter.
+    pub parameter_size: usize,
+    /// The energy used when dry-running the transfers.
+    pub used_energy:    Energy,
+    /// The energy allowed for executing the transfers, i.e., the energy used
+    /// when dry-running plus a margin of 10%, or at least 50.
+    pub energy:         Energy,
+}
+
+/// The result of [`Cis2Contract::plan_airdrop`].
+#[derive(Debug, Clone)]
+pub struct AirdropPlan {
+    /// The account that sends the transfers.
+    pub sender:     AccountAddress,
+    /// The block the chunks were dry-run against.
+    pub block_hash: BlockHash,
+    /// The chunks, in the order they are to be sent.
+    pub chunks:     Vec<AirdropChunk>,
+    /// The transfers that are not part of any chunk.
+    pub skipped:    Vec<SkippedTransfer>,
+    /// The expiry time of the transactions, if set.
+    pub expiry:     Option<TransactionTime>,
+}
+
+impl AirdropPlan {
+    /// The total energy allowed for executing all the chunks.
+    pub fn total_energy(&self) -> Energy {
+        self.chunks
+            .iter()
+            .fold(Energy::from(0), |acc, chunk| acc + chunk.energy)
+    }
+}
+
+/// The outcome of sending the transaction of an [`AirdropChunk`].
+#[derive(Debug)]
+pub enum ChunkOutcome {
+    /// The transaction was finalized and the transfers were executed.
+    Finalized {
+        transaction_hash: TransactionHash,
+        block_hash:       BlockHash,
+    },
+    /// The transaction was finalized, but rejected.
+    Rejected {
+        transaction_hash: TransactionHash,
+        block_hash:       BlockHash,
+        reason:           RejectReason,
+    },
+    /// The transaction could not be sent.
+    SendFailed(v2::RPCError),
+    /// The transaction was not sent since sending an earlier chunk failed.
+    NotSent,
+    /// The transaction was sent, but the status could not be determined.
+    Unknown {
+        transaction_hash: TransactionHash,
+        error:            v2::QueryError,
+    },
+}
+
+/// The result of executing an [`AirdropChunk`].
+#[derive(Debug)]
+pub struct ChunkResult {
+    /// The chunk.
+    pub chunk:   AirdropChunk,
+    /// The nonce of the transaction.
+    pub nonce:   Nonce,
+    /// The outcome of the transaction.
+    pub outcome: ChunkOutcome,
+}
+
+/// The status of a single transfer in an [`AirdropReport`].
+#[derive(Debug, Clone)]
+pub enum RecipientStatus {
+    /// The transfer was executed.
+    Transferred {
+        transaction_hash: TransactionHash,
+        block_hash:       BlockHash,
+    },
+    /// The transaction containing the transfer was rejected.
+    Rejected {
+        transaction_hash: TransactionHash,
+        reason:           RejectReason,
+    },
+    /// The transfer was skipped when planning.
+    Skipped(SkipReason),
+    /// The transaction containing the transfer was not sent.
+    NotSent,
+    /// The status of the transaction containing the transfer could not be
+    /// determined.
+    Unknown { transaction_hash: TransactionHash },
+}
+
+/// The outcome of a single transfer in an [`AirdropReport`].
+#[derive(Debug, Clone)]
+pub struct RecipientOutcome {
+    /// The index of the transfer in the [`Airdrop`].
+    pub index:    usize,
+    /// The transfer.
+    pub transfer: Transfer,
+    /// The status of the transfer.
+    pub status:   RecipientStatus,
+}
+
+/// The result of [`Cis2Contract::execute_airdrop`].
+#[derive(Debug)]
+pub struct AirdropReport {
+    /// The results of the chunks, in the order they were sent.
+    pub chunks:  Vec<ChunkResult>,
+    /// The transfers that were skipped when planning.
+    pub skipped: Vec<SkippedTransfer>,
+}
+
+impl AirdropReport {
+    /// Whether all transfers of the airdrop were executed.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty()
+            && self
+                .chunks
+                .iter()
+                .all(|chunk| matches!(chunk.outcome, ChunkOutcome::Finalized { .. }))
+    }
+
+    /// The outcome of each transfer, in the order of the [`Airdrop`].
+    pub fn recipients(&self) -> Vec<RecipientOutcome> {
+        let mut outcomes: Vec<RecipientOutcome> = self
+            .skipped
+            .iter()
+            .map(|skipped| RecipientOutcome {
+                index:    skipped.index,
+                transfer: skipped.transfer.clone(),
+                status:   RecipientStatus::Skipped(skipped.reason.clone()),
+            })
+            .collect();
+        for result in &self.chunks {
+            let status = match &result.outcome {
+                ChunkOutcome::Finalized {
+                    transaction_hash,
+                    block_hash,
+                } => RecipientStatus::Transferred {
+                    transaction_hash: *transaction_hash,
+                    block_hash:       *block_hash,
+                },
+                ChunkOutcome::Rejected {
+                    transaction_hash,
+                    reason,
+                    ..
+                } => RecipientStatus::Rejected {
+                    transaction_hash: *transaction_hash,
+                    reason:           reason.clone(),
+                },
+                ChunkOutcome::SendFailed(_) | ChunkOutcome::NotSent => RecipientStatus::NotSent,
+                ChunkOutcome::Unknown {
+                    transaction_hash, ..
+                } => RecipientStatus::Unknown {
+                    transaction_hash: *transaction_hash,
+                },
+            };
+            outcomes.extend(
+                result
+                    .chunk
+                    .indices
+                    .iter()
+                    .zip(&result.chunk.transfers)
+                    .map(|(index, transfer)| RecipientOutcome {
+                        index:    *index,
+                        transfer: transfer.clone(),
+                        status:   status.clone(),
+                    }),
+            );
+        }
+        outcomes.sort_by_key(|outcome| outcome.index);
+        outcomes
+    }
+}
+
+/// Transfers that are yet to be dry-run when planning an airdrop.
+struct PendingChunk {
+    indices:        Vec<usize>,
+    transfers:      Vec<Transfer>,
+    parameter_size: usize,
+    sizes:          Vec<usize>,
+}
+
+impl PendingChunk {
+    fn new() -> Self {
+        Self {
+            indices:        Vec::new(),
+            transfers:      Vec::new(),
+            parameter_size: TRANSFERS_LENGTH_SIZE,
+            sizes:          Vec::new(),
+        }
+    }
+
+    fn push(&mut self, index: usize, transfer: Transfer, size: usize) {
+        self.indices.push(index);
+        self.transfers.push(transfer);
+        self.parameter_size += size;
+        self.sizes.push(size);
+    }
+
+    /// Split the chunk into two halves.
+    fn split(self) -> (Self, Self) {
+        let mid = self.transfers.len() / 2;
+        let mut first = Self::new();
+        let mut second = Self::new();
+        for (i, ((index, transfer), size)) in self
+            .indices
+            .into_iter()
+            .zip(self.transfers)
+            .zip(self.sizes)
+            .enumerate()
+        {
+            if i < mid {
+                first.push(index, transfer, size);
+            } else {
+                second.push(index, transfer, size);
+            }
+        }
+        (first, second)
+    }
+}
+
+/// Split the transfers into chunks whose parameter is at most `max_len` bytes.
+/// Transfers that do not fit in a parameter on their own are skipped.
+fn chunk_by_size(
+    transfers: &[Transfer],
+    max_len: usize,
+) -> (VecDeque<PendingChunk>, Vec<SkippedTransfer>) {
+    let mut pending = VecDeque::new();
+    let mut skipped = Vec::new();
+    let mut current = PendingChunk::new();
+    for (index, transfer) in transfers.iter().enumerate() {
+        let size = contracts_common::to_bytes(transfer).len();
+        if TRANSFERS_LENGTH_SIZE + size > max_len {
+            skipped.push(SkippedTransfer {
+                index,
+                transfer: transfer.clone(),
+                reason: SkipReason::ExceedsParameterSize(size),
+            });
+            continue;
+        }
+        if current.parameter_size + size > max_len {
+            pending.push_back(std::mem::replace(&mut current, PendingChunk::new()));
+        }
+        current.push(index, transfer.clone(), size);
+    }
+    if !current.transfers.is_empty() {
+        pending.push_back(current);
+    }
+    (pending, skipped)
+}
+
+/// The energy allowed for a transaction that used the given energy when
+/// dry-run. This is the same margin as used by
+/// [`ContractUpdateBuilder`](crate::contract_client::ContractUpdateBuilder).
+fn with_margin(used_energy: Energy) -> Energy {
+    used_energy + Energy::from(std::cmp::max(50, used_energy.energy / 10))
+}
+
+/// Wait until the transaction of a chunk is finalized and return the outcome.
+async fn wait_for_chunk(
+    client: &mut v2::Client,
+    transaction_hash: TransactionHash,
+) -> ChunkOutcome {
+    match client.wait_until_finalized(&transaction_hash).await {
+        Ok((block_hash, summary)) => match summary.is_rejected_account_transaction() {
+            Some(reason) => ChunkOutcome::Rejected {
+                transaction_hash,
+                block_hash,
+                reason: reason.clone(),
+            },
+            None => ChunkOutcome::Finalized {
+                transaction_hash,
+                block_hash,
+            },
+        },
+        Err(error) => ChunkOutcome::Unknown {
+            transaction_hash,
+            error,
+        },
+    }
+}
+
+impl Cis2Contract {
+    /// Plan the airdrop by splitting the transfers into chunks that each fit
+    /// in a single transaction sent by the given account. See the [module
+    /// documentation](super::airdrop) for details.
+    ///
+    /// The chunks are dry-run against the last finalized block at the time of
+    /// the call.
+    pub async fn plan_airdrop(
+        &mut self,
+        sender: AccountAddress,
+        airdrop: &Airdrop,
+    ) -> Result<AirdropPlan, AirdropError> {
+        let block_hash = self.client.get_consensus_info().await?.last_finalized_block;
+        let (mut pending, mut skipped) = chunk_by_size(&airdrop.transfers, MAX_PARAMETER_LEN);
+
+        // Dry-run the chunks, splitting those that are rejected or use too
+        // much energy until the offending transfers are isolated.
+        let mut chunks = Vec::new();
+        while let Some(chunk) = pending.pop_front() {
+            let result = self
+                .transfer_dry_run(
+                    BlockIdentifier::Given(block_hash),
+                    Address::Account(sender),
+                    chunk.transfers.clone(),
+                )
+                .await;
+            let reason = match result {
+                Ok(used_energy) if with_margin(used_energy) <= airdrop.max_energy => {
+                    chunks.push(AirdropChunk {
+                        indices: chunk.indices,
+                        transfers: chunk.transfers,
+                        parameter_size: chunk.parameter_size,
+                        used_energy,
+                        energy: with_margin(used_energy),
+                    });
+                    continue;
+                }
+                Ok(used_energy) => SkipReason::ExceedsMaxEnergy(with_margin(used_energy)),
+                Err(Cis2DryRunError::NodeRejected(reason)) => SkipReason::Rejected(reason),
+                Err(e) => return Err(e.into()),
+            };
+            if chunk.transfers.len() > 1 {
+                let (first, second) = chunk.split();
+                pending.push_front(second);
+                pending.push_front(first);
+            } else {
+                skipped.extend(chunk.indices.into_iter().zip(chunk.transfers).map(
+                    |(index, transfer)| SkippedTransfer {
+                        index,
+                        transfer,
+                        reason: reason.clone(),
+                    },
+                ));
+            }
+        }
+
+        Ok(AirdropPlan {
+            sender,
+            block_hash,
+            chunks,
+            skipped,
+            expiry: airdrop.expiry,
+        })
+    }
+
+    /// Send a transaction for each chunk of the plan, using sequential nonces
+    /// starting from the next nonce of the sender, and wait until all of them
+    /// are finalized. Note that this can potentially wait indefinitely.
+    ///
+    /// If sending a transaction fails, the remaining chunks are not sent. An
+    /// error is only returned if the nonce of the sender cannot be queried,
+    /// or if a transaction cannot be constructed, e.g., because the transfers
+    /// of a chunk do not fit in a parameter, in which case no transactions are
+    /// sent.
+    pub async fn execute_airdrop(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        plan: AirdropPlan,
+    ) -> Result<AirdropReport, AirdropError> {
+        let mut nonce = self
+            .client
+            .get_next_account_sequence_number(&plan.sender)
+            .await?
+            .nonce;
+        let expiry = plan
+            .expiry
+            .unwrap_or_else(|| TransactionTime::hours_after(1));
+
+        let mut txs = Vec::with_capacity(plan.chunks.len());
+        for (i, chunk) in plan.chunks.into_iter().enumerate() {
+            let parameter = super::TransferParams::new(chunk.transfers.clone())
+                .ok()
+                .and_then(|params| OwnedParameter::from_serial(&params).ok())
+                .ok_or(AirdropError::ChunkTooLarge(i))?;
+            let metadata = ContractTransactionMetadata {
+                sender_address: plan.sender,
+                nonce,
+                expiry,
+                energy: GivenEnergy::Add(chunk.energy),
+                amount: Amount::zero(),
+            };
+            let tx =
+                self.make_update_raw::<AirdropError>(signer, &metadata, "transfer", parameter)?;
+            txs.push((chunk, nonce, tx));
+            nonce.next_mut();
+        }
+
+        let mut sent = Vec::with_capacity(txs.len());
+        let mut send_failed = false;
+        for (chunk, nonce, tx) in txs {
+            let outcome = if send_failed {
+                Err(ChunkOutcome::NotSent)
+            } else {
+                match self.client.send_account_transaction(tx).await {
+                    Ok(transaction_hash) => Ok(transaction_hash),
+                    Err(e) => {
+                        send_failed = true;
+                        Err(ChunkOutcome::SendFailed(e))
+                    }
+                }
+            };
+            sent.push((chunk, nonce, outcome));
+        }
+
+        let chunks = futures::future::join_all(sent.into_iter().map(|(chunk, nonce, sent)| {
+            let mut client = self.client.clone();
+            async move {
+                let outcome = match sent {
+                    Ok(transaction_hash) => wait_for_chunk(&mut client, transaction_hash).await,
+                    Err(outcome) => outcome,
+                };
+                ChunkResult {
+                    chunk,
+                    nonce,
+                    outcome,
+                }
+            }
+        }))
+        .await;
+
+        Ok(AirdropReport {
+            chunks,
+            skipped: plan.skipped,
+        })
+    }
+
+    /// Plan the airdrop using [`plan_airdrop`](Self::plan_airdrop), and
+    /// execute it using [`execute_airdrop`](Self::execute_airdrop). The
+    /// signer must hold the keys of the `sender` account.
+    pub async fn airdrop(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        sender: AccountAddress,
+        airdrop: &Airdrop,
+    ) -> Result<AirdropReport, AirdropError> {
+        let plan = self.plan_airdrop(sender, airdrop).await?;
+        self.execute_airdrop(signer, plan).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cis2::{AdditionalData, Receiver, TokenAmount, TokenId};
+
+    fn transfer(token_id: Vec<u8>) -> Transfer {
+        Transfer {
+            token_id: TokenId::new_unchecked(token_id),
+            amount:   TokenAmount(1u32.into()),
+            from:     Address::Account(AccountAddress([0u8; 32])),
+            to:       Receiver::Account(AccountAddress([1u8; 32])),
+            data:     AdditionalData::empty(),
+        }
+    }
+
+    #[test]
+    /// Test that transfers are chunked in order by the size of the parameter,
+    /// and that transfers that do not fit on their own are skipped.
+    fn chunk_transfers_by_size() {
+        let mut transfers: Vec<_> = (0..5).map(|i| transfer(vec![i])).collect();
+        transfers.insert(2, transfer(vec![0u8; 100]));
+        let size = contracts_common::to_bytes(&transfers[0]).len();
+        let max_len = TRANSFERS_LENGTH_SIZE + 2 * size;
+
+        let (pending, skipped) = chunk_by_size(&transfers, max_len);
+        let indices: Vec<_> = pending.iter().map(|chunk| chunk.indices.clone()).collect();
+        assert_eq!(indices, vec![vec![0, 1], vec![3, 4], vec![5]]);
+        for chunk in &pending {
+            assert_eq!(
+                chunk.parameter_size,
+                TRANSFERS_LENGTH_SIZE + chunk.transfers.len() * size
+            );
+            assert!(chunk.parameter_size <= max_len);
+        }
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].index, 2);
+        assert!(matches!(
+            skipped[0].reason,
+            SkipReason::ExceedsParameterSize(n) if n > max_len - TRANSFERS_LENGTH_SIZE
+        ));
+    }
+
+    #[test]
+    /// Test that splitting a chunk keeps the transfers in order and updates
+    /// the parameter sizes.
+    fn split_chunk() {
+        let mut chunk = PendingChunk::new();
+        for i in 0..5 {
+            chunk.push(usize::from(i) * 2, transfer(vec![i]), 10 + usize::from(i));
+        }
+        let (first, second) = chunk.split();
+        assert_eq!(first.indices, vec![0, 2]);
+        assert_eq!(second.indices, vec![4, 6, 8]);
+        let token_ids: Vec<_> = first.transfers.iter().map(|t| t.token_id.clone()).collect();
+        assert_eq!(token_ids, vec![
+            TokenId::new_unchecked(vec![0]),
+            TokenId::new_unchecked(vec![1])
+        ]);
+        assert_eq!(first.sizes, vec![10, 11]);
+        assert_eq!(second.sizes, vec![12, 13, 14]);
+        assert_eq!(first.parameter_size, TRANSFERS_LENGTH_SIZE + 21);
+        assert_eq!(second.parameter_size, TRANSFERS_LENGTH_SIZE + 39);
+
+        let (empty, single) = second.split().0.split();
+        assert!(empty.transfers.is_empty());
+        assert_eq!(empty.parameter_size, TRANSFERS_LENGTH_SIZE);
+        assert_eq!(single.indices, vec![4]);
+    }
+
+    #[test]
+    /// Test the energy margin added to the energy used when dry-running.
+    fn energy_margin() {
+        assert_eq!(with_margin(Energy::from(0)), Energy::from(50));
+        assert_eq!(with_margin(Energy::from(400)), Energy::from(450));
+        assert_eq!(with_margin(Energy::from(10_000)), Energy::from(11_000));
+    }
+
+    #[test]
+    /// Test that the outcomes of the recipients are reported in the order of
+    /// the airdrop.
+    fn report_recipients() {
+        let chunk = |indices: Vec<usize>| AirdropChunk {
+            transfers: indices.iter().map(|i| transfer(vec![*i as u8])).collect(),
+            indices,
+            parameter_size: 0,
+            used_energy: Energy::from(0),
+            energy: Energy::from(50),
+        };
+        let transaction_hash = TransactionHash::new([1u8; 32]);
+        let block_hash = BlockHash::new([2u8; 32]);
+        let report = AirdropReport {
+            chunks:  vec![
+                ChunkResult {
+                    chunk:   chunk(vec![0, 2]),
+                    nonce:   Nonce { nonce: 1 },
+                    outcome: ChunkOutcome::Finalized {
+                        transaction_hash,
+                        block_hash,
+                    },
+                },
+                ChunkResult {
+                    chunk:   chunk(vec![3]),
+                    nonce:   Nonce { nonce: 2 },
+                    outcome: ChunkOutcome::NotSent,
+                },
+            ],
+            skipped: vec![SkippedTransfer {
+                index:    1,
+                transfer: transfer(vec![1]),
+                reason:   SkipReason::ExceedsMaxEnergy(Energy::from(1_000_000)),
+            }],
+        };
+        assert!(!report.is_complete());
+
+        let recipients = report.recipients();
+        let indices: Vec<_> = recipients.iter().map(|r| r.index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        for recipient in &recipients {
+            assert_eq!(
+                recipient.transfer.token_id,
+                TokenId::new_unchecked(vec![recipient.index as u8])
+            );
+        }
+        assert!(matches!(
+            recipients[0].status,
+            RecipientStatus::Transferred { transaction_hash: t, .. } if t == transaction_hash
+        ));
+        assert!(matches!(
+            recipients[1].status,
+            RecipientStatus::Skipped(SkipReason::ExceedsMaxEnergy(_))
+        ));
+        assert!(matches!(
+            recipients[2].status,
+            RecipientStatus::Transferred { .. }
+        ));
+        assert!(matches!(recipients[3].status, RecipientStatus::NotSent));
+    }
+}
@@ -0,0 +1,596 @@
+//! Indexing of CIS-2 token balances.
+//!
+//! The [`Cis2EventIndexer`] is an [`Indexer`] that extracts the CIS-2 events
+//! logged by a set of contracts in each finalized block. The events of each
+//! block are applied, in order of increasing height, to a [`Cis2Ledger`], which
+//! maintains the balance of each holder and the total supply of each token, as
+//! well as the current operators and token metadata. The ledger keeps the
+//! history of balances, so that [`Cis2Snapshot`]s of all holders can be taken
+//! at any height that has been applied, and the balances can be cross-checked
+//! against the `balanceOf` function of the contracts using
+//! [`Cis2Ledger::verify`].
+//!
+//! Note that events that are not CIS-2 events, or that cannot be parsed, are
+//! ignored, and that the balances are only correct if the indexer starts at or
+//! before the height at which the contracts were initialized.
+use super::{
+    BalanceOfQuery, Cis2Contract, Cis2QueryError, Event, MetadataUrl, OperatorUpdate, TokenAmount,
+    TokenId,
+};
+use crate::{
+    indexer::{async_trait, Indexer, TransactionIndexer, TraverseError},
+    types::{
+        queries::BlockInfo, smart_contracts::ContractEvent, AccountTransactionEffects,
+        BlockItemSummary, BlockItemSummaryDetails,
+    },
+    v2::{self, FinalizedBlockInfo, QueryResult},
+};
+use concordium_base::{
+    base::AbsoluteBlockHeight,
+    contracts_common::{self, Address, ContractAddress},
+    hashes::TransactionHash,
+};
+use num_bigint::BigUint;
+use num_traits::Zero;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The maximum number of queries in a single `balanceOf` invocation made by
+/// [`Cis2Ledger::verify`].
+const BALANCE_OF_CHUNK_SIZE: usize = 200;
+
+/// A CIS-2 event logged by a contract, as produced by the
+/// [`Cis2EventIndexer`].
+#[derive(Debug, Clone)]
+pub struct Cis2EventRecord {
+    /// The hash of the transaction that logged the event.
+    pub transaction_hash: TransactionHash,
+    /// The contract that logged the event.
+    pub address:          ContractAddress,
+    /// The event.
+    pub event:            Event,
+}
+
+/// An indexer that retrieves the CIS-2 events logged by a set of contracts,
+/// both when initialized and when updated, in the order they were logged.
+///
+/// The [`on_connect`](Indexer::on_connect) and
+/// [`on_failure`](Indexer::on_failure) methods of the [`Indexer`] trait only
+/// log the events on `info` and `warn` levels, respectively, using the
+/// [`tracing`](https://docs.rs/tracing/latest/tracing/) crate. The [target](https://docs.rs/tracing/latest/tracing/struct.Metadata.html#method.target)
+/// of the log is `ccd_indexer` which may be used to filter the logs.
+pub struct Cis2EventIndexer {
+    /// The contracts to track. If empty, the events of all contracts are
+    /// retrieved.
+    pub contracts: BTreeSet<ContractAddress>,
+}
+
+impl Cis2EventIndexer {
+    fn tracks(&self, address: &ContractAddress) -> bool {
+        self.contracts.is_empty() || self.contracts.contains(address)
+    }
+}
+
+/// The events logged by instances in the transaction, including the events
+/// logged when initializing an instance.
+fn logged_events(summary: &BlockItemSummary) -> Vec<(ContractAddress, &[ContractEvent])> {
+    let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+        return Vec::new();
+    };
+    match &at.effects {
+        AccountTransactionEffects::ContractInitialized { data } => {
+            vec![(data.address, &data.events[..])]
+        }
+        _ => summary
+            .contract_update_logs()
+            .map(|logs| logs.collect())
+            .unwrap_or_default(),
+    }
+}
+
+#[async_trait]
+impl Indexer for Cis2EventIndexer {
+    type Context = ();
+    type Data = (BlockInfo, Vec<Cis2EventRecord>);
+
+    async fn on_connect<'a>(
+        &mut self,
+        endpoint: v2::Endpoint,
+        client: &'a mut v2::Client,
+    ) -> QueryResult<()> {
+        TransactionIndexer.on_connect(endpoint, client).await
+    }
+
+    async fn on_finalized<'a>(
+        &self,
+        client: v2::Client,
+        ctx: &'a (),
+        fbi: FinalizedBlockInfo,
+    ) -> QueryResult<Self::Data> {
+        let (bi, summaries) = TransactionIndexer.on_finalized(client, ctx, fbi).await?;
+        let mut records = Vec::new();
+        for summary in &summaries {
+            for (address, events) in logged_events(summary) {
+                if !self.tracks(&address) {
+                    continue;
+                }
+                for event in events {
+                    if let Ok(event) = contracts_common::from_bytes(event.as_ref()) {
+                        records.push(Cis2EventRecord {
+                            transaction_hash: summary.hash,
+                            address,
+                            event,
+                        });
+                    }
+                }
+            }
+        }
+        Ok((bi, records))
+    }
+
+    async fn on_failure(
+        &mut self,
+        endpoint: v2::Endpoint,
+        successive_failures: u64,
+        err: TraverseError,
+    ) -> bool {
+        TransactionIndexer
+            .on_failure(endpoint, successive_failures, err)
+            .await
+    }
+}
+
+/// The history of a single token.
+#[derive(Debug, Clone, Default)]
+struct TokenHistory {
+    /// The balance of each holder at each height where it changed.
+    balances:     BTreeMap<Address, BTreeMap<AbsoluteBlockHeight, BigUint>>,
+    /// The total supply at each height where it changed.
+    total_supply: BTreeMap<AbsoluteBlockHeight, BigUint>,
+    /// The current metadata URL of the token, if any was logged.
+    metadata_url: Option<MetadataUrl>,
+}
+
+/// The value of a history at the given height.
+fn value_at(
+    history: &BTreeMap<AbsoluteBlockHeight, BigUint>,
+    height: AbsoluteBlockHeight,
+) -> BigUint {
+    history
+        .range(..=height)
+        .next_back()
+        .map_or_else(BigUint::zero, |(_, value)| value.clone())
+}
+
+/// Add the difference to the latest value of the history and record the
+/// result at the given height. Returns [`false`] if the value would become
+/// negative, in which case it is set to zero.
+fn update_at(
+    history: &mut BTreeMap<AbsoluteBlockHeight, BigUint>,
+    height: AbsoluteBlockHeight,
+    add: Option<&BigUint>,
+    sub: Option<&BigUint>,
+) -> bool {
+    let mut value = history
+        .values()
+        .next_back()
+        .cloned()
+        .unwrap_or_else(BigUint::zero);
+    if let Some(add) = add {
+        value += add;
+    }
+    let mut ok = true;
+    if let Some(sub) = sub {
+        if value >= *sub {
+            value -= sub;
+        } else {
+            value = BigUint::zero();
+            ok = false;
+        }
+    }
+    history.insert(height, value);
+    ok
+}
+
+/// The balances of CIS-2 tokens derived from the events retrieved by the
+/// [`Cis2EventIndexer`]. See the [module documentation](self) for details.
+#[derive(Debug, Clone, Default)]
+pub struct Cis2Ledger {
+    tokens:      BTreeMap<(ContractAddress, TokenId), TokenHistory>,
+    /// The current operators of each owner in each contract.
+    operators:   BTreeMap<(ContractAddress, Address), BTreeSet<Address>>,
+    last_height: Option<AbsoluteBlockHeight>,
+}
+
+impl Cis2Ledger {
+    /// Construct an empty ledger.
+    pub fn new() -> Self { Self::default() }
+
+    /// The height of the last block applied to the ledger.
+    pub fn last_height(&self) -> Option<AbsoluteBlockHeight> { self.last_height }
+
+    /// Apply the events of the block at the given height. Blocks must be
+    /// applied in order of increasing height.
+    ///
+    /// Events that would make a balance or the total supply negative, which
+    /// means that the ledger has missed events, are logged on `warn` level and
+    /// the balance is set to zero.
+    pub fn apply(&mut self, height: AbsoluteBlockHeight, records: &[Cis2EventRecord]) {
+        self.last_height = Some(height);
+        for record in records {
+            let address = record.address;
+            let mut ok = true;
+            match &record.event {
+                Event::Mint {
+                    token_id,
+                    amount,
+                    owner,
+                } => {
+                    let token = self.token_mut(address, token_id);
+                    let balance = token.balances.entry(*owner).or_default();
+                    update_at(balance, height, Some(&amount.0), None);
+                    update_at(&mut token.total_supply, height, Some(&amount.0), None);
+                }
+                Event::Burn {
+                    token_id,
+                    amount,
+                    owner,
+                } => {
+                    let token = self.token_mut(address, token_id);
+                    let balance = token.balances.entry(*owner).or_default();
+                    ok &= update_at(balance, height, None, Some(&amount.0));
+                    ok &= update_at(&mut token.total_supply, height, None, Some(&amount.0));
+                }
+                Event::Transfer {
+                    token_id,
+                    amount,
+                    from,
+                    to,
+                } => {
+                    let token = self.token_mut(address, token_id);
+                    let balance = token.balances.entry(*from).or_default();
+                    ok &= update_at(balance, height, None, Some(&amount.0));
+                    let balance = token.balances.entry(*to).or_default();
+                    update_at(balance, height, Some(&amount.0), None);
+                }
+                Event::UpdateOperator {
+                    update,
+                    owner,
+                    operator,
+                } => {
+                    let operators = self.operators.entry((address, *owner)).or_default();
+                    match update {
+                        OperatorUpdate::Add => operators.insert(*operator),
+                        OperatorUpdate::Remove => operators.remove(operator),
+                    };
+                }
+                Event::TokenMetadata {
+                    token_id,
+                    metadata_url,
+                } => {
+                    self.token_mut(address, token_id).metadata_url = Some(metadata_url.clone());
+                }
+                _ => (),
+            }
+            if !ok {
+                tracing::warn!(
+                    target: "ccd_indexer",
+                    "Event {:?} of contract {address} in transaction {} exceeds the derived balance.",
+                    record.event,
+                    record.transaction_hash
+                );
+            }
+        }
+    }
+
+    fn token_mut(&mut self, address: ContractAddress, token_id: &TokenId) -> &mut TokenHistory {
+        self.tokens.entry((address, token_id.clone())).or_default()
+    }
+
+    /// The tokens that have been seen in events, ordered by contract and token
+    /// ID.
+    pub fn tokens(&self) -> impl Iterator<Item = &(ContractAddress, TokenId)> { self.tokens.keys() }
+
+    /// The current balance of the holder.
+    pub fn balance(
+        &self,
+        address: ContractAddress,
+        token_id: &TokenId,
+        holder: &Address,
+    ) -> TokenAmount {
+        let balance = self
+            .tokens
+            .get(&(address, token_id.clone()))
+            .and_then(|token| token.balances.get(holder))
+            .and_then(|history| history.values().next_back().cloned())
+            .unwrap_or_else(BigUint::zero);
+        TokenAmount(balance)
+    }
+
+    /// The current total supply of the token.
+    pub fn total_supply(&self, address: ContractAddress, token_id: &TokenId) -> TokenAmount {
+        let supply = self
+            .tokens
+            .get(&(address, token_id.clone()))
+            .and_then(|token| token.total_supply.values().next_back().cloned())
+            .unwrap_or_else(BigUint::zero);
+        TokenAmount(supply)
+    }
+
+    /// The current metadata URL of the token, if any was logged.
+    pub fn metadata_url(
+        &self,
+        address: ContractAddress,
+        token_id: &TokenId,
+    ) -> Option<&MetadataUrl> {
+        self.tokens
+            .get(&(address, token_id.clone()))?
+            .metadata_url
+            .as_ref()
+    }
+
+    /// The current operators of the owner in the contract.
+    pub fn operators(&self, address: ContractAddress, owner: &Address) -> BTreeSet<Address> {
+        self.operators
+            .get(&(address, *owner))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// The balances of all holders and the total supply of each token at the
+    /// end of the block at the given height. Holders with a zero balance are
+    /// not included.
+    pub fn snapshot(&self, height: AbsoluteBlockHeight) -> Cis2Snapshot {
+        let mut tokens = BTreeMap::new();
+        for (key, token) in &self.tokens {
+            let holders: BTreeMap<_, _> = token
+                .balances
+                .iter()
+                .map(|(holder, history)| (*holder, value_at(history, height)))
+                .filter(|(_, balance)| !balance.is_zero())
+                .map(|(holder, balance)| (holder, TokenAmount(balance)))
+                .collect();
+            let total_supply = value_at(&token.total_supply, height);
+            if holders.is_empty() && total_supply.is_zero() {
+                continue;
+            }
+            tokens.insert(key.clone(), TokenSnapshot {
+                total_supply: TokenAmount(total_supply),
+                holders,
+            });
+        }
+        Cis2Snapshot { height, tokens }
+    }
+
+    /// Check the balances of all holders of the tokens of the contract at the
+    /// given height against the balances returned by the `balanceOf` function
+    /// of the contract. This includes holders whose balance is zero according
+    /// to the ledger. Returns the balances that differ.
+    pub async fn verify(
+        &self,
+        client: v2::Client,
+        address: ContractAddress,
+        height: AbsoluteBlockHeight,
+    ) -> Result<Vec<Cis2BalanceDiscrepancy>, Cis2QueryError> {
+        let mut contract = Cis2Contract::create(client, address).await?;
+        let expected: Vec<_> = self
+            .tokens
+            .iter()
+            .filter(|((contract, _), _)| *contract == address)
+            .flat_map(|((_, token_id), token)| {
+                token.balances.iter().map(move |(holder, history)| {
+                    (token_id.clone(), *holder, value_at(history, height))
+                })
+            })
+            .collect();
+
+        let mut discrepancies = Vec::new();
+        for chunk in expected.chunks(BALANCE_OF_CHUNK_SIZE) {
+            let queries = chunk
+                .iter()
+                .map(|(token_id, holder, _)| BalanceOfQuery {
+                    token_id: token_id.clone(),
+                    address:  *holder,
+                })
+                .collect();
+            let actual = contract.balance_of(height, queries).await?.0;
+            if actual.len() != chunk.len() {
+                return Err(contracts_common::ParseError {}.into());
+            }
+            for ((token_id, holder, expected), actual) in chunk.iter().zip(actual) {
+                if *expected != actual.0 {
+                    discrepancies.push(Cis2BalanceDiscrepancy {
+                        address,
+                        token_id: token_id.clone(),
+                        holder: *holder,
+                        expected: TokenAmount(expected.clone()),
+                        actual,
+                    });
+                }
+            }
+        }
+        Ok(discrepancies)
+    }
+}
+
+/// The balances of a token at a given height.
+#[derive(Debug, Clone)]
+pub struct TokenSnapshot {
+    /// The total supply of the token.
+    pub total_supply: TokenAmount,
+    /// The holders with a non-zero balance.
+    pub holders:      BTreeMap<Address, TokenAmount>,
+}
+
+/// The balances of all tokens in a [`Cis2Ledger`] at a given height, as
+/// returned by [`Cis2Ledger::snapshot`].
+#[derive(Debug, Clone)]
+pub struct Cis2Snapshot {
+    /// The height of the block at the end of which the balances are taken.
+    pub height: AbsoluteBlockHeight,
+    /// The balances of each token with a non-zero supply or holders, by
+    /// contract and token ID.
+    pub tokens: BTreeMap<(ContractAddress, TokenId), TokenSnapshot>,
+}
+
+/// A mismatch between the balance derived by the [`Cis2Ledger`] and the
+/// balance returned by the `balanceOf` function of the contract.
+#[derive(Debug, Clone)]
+pub struct Cis2BalanceDiscrepancy {
+    /// The contract.
+    pub address:  ContractAddress,
+    /// The token.
+    pub token_id: TokenId,
+    /// The holder.
+    pub holder:   Address,
+    /// The balance derived by the ledger.
+    pub expected: TokenAmount,
+    /// The balance returned by the contract.
+    pub actual:   TokenAmount,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::contracts_common::AccountAddress;
+
+    const CONTRACT: ContractAddress = ContractAddress {
+        index:    1,
+        subindex: 0,
+    };
+
+    fn account(n: u8) -> Address { Address::Account(AccountAddress([n; 32])) }
+
+    fn token() -> TokenId { TokenId::new_unchecked(vec![1]) }
+
+    fn amount(n: u32) -> TokenAmount { TokenAmount(big(n)) }
+
+    fn big(n: u32) -> BigUint { BigUint::from(n) }
+
+    fn record(event: Event) -> Cis2EventRecord {
+        Cis2EventRecord {
+            transaction_hash: TransactionHash::new([0u8; 32]),
+            address: CONTRACT,
+            event,
+        }
+    }
+
+    fn height(height: u64) -> AbsoluteBlockHeight { AbsoluteBlockHeight { height } }
+
+    #[test]
+    /// Test that minting, transferring and burning update the balances and the
+    /// total supply, and that snapshots reflect the balances at each height.
+    fn apply_and_snapshot() {
+        let mut ledger = Cis2Ledger::new();
+        ledger.apply(height(1), &[record(Event::Mint {
+            token_id: token(),
+            amount:   amount(100),
+            owner:    account(1),
+        })]);
+        ledger.apply(height(3), &[
+            record(Event::Transfer {
+                token_id: token(),
+                amount:   amount(30),
+                from:     account(1),
+                to:       account(2),
+            }),
+            record(Event::Transfer {
+                token_id: token(),
+                amount:   amount(30),
+                from:     account(2),
+                to:       account(3),
+            }),
+        ]);
+        ledger.apply(height(4), &[record(Event::Burn {
+            token_id: token(),
+            amount:   amount(70),
+            owner:    account(1),
+        })]);
+
+        assert_eq!(ledger.last_height(), Some(height(4)));
+        assert_eq!(ledger.tokens().collect::<Vec<_>>(), vec![&(
+            CONTRACT,
+            token()
+        )]);
+        assert_eq!(ledger.balance(CONTRACT, &token(), &account(1)).0, big(0));
+        assert_eq!(ledger.balance(CONTRACT, &token(), &account(3)).0, big(30));
+        assert_eq!(ledger.total_supply(CONTRACT, &token()).0, big(30));
+
+        assert!(ledger.snapshot(height(0)).tokens.is_empty());
+        let balances = |h: u64| -> Vec<(Address, BigUint)> {
+            let snapshot = ledger.snapshot(height(h));
+            assert_eq!(snapshot.height, height(h));
+            snapshot.tokens[&(CONTRACT, token())]
+                .holders
+                .iter()
+                .map(|(holder, amount)| (*holder, amount.0.clone()))
+                .collect()
+        };
+        assert_eq!(balances(1), vec![(account(1), big(100))]);
+        assert_eq!(balances(2), balances(1));
+        // Holders whose balance returned to zero are not included.
+        assert_eq!(balances(3), vec![
+            (account(1), big(70)),
+            (account(3), big(30))
+        ]);
+        assert_eq!(balances(4), vec![(account(3), big(30))]);
+        let supply = |h: u64| {
+            ledger.snapshot(height(h)).tokens[&(CONTRACT, token())]
+                .total_supply
+                .0
+                .clone()
+        };
+        assert_eq!(supply(3), big(100));
+        assert_eq!(supply(4), big(30));
+    }
+
+    #[test]
+    /// Test that events exceeding the derived balance set it to zero.
+    fn apply_missed_events() {
+        let mut ledger = Cis2Ledger::new();
+        ledger.apply(height(1), &[
+            record(Event::Mint {
+                token_id: token(),
+                amount:   amount(10),
+                owner:    account(1),
+            }),
+            record(Event::Transfer {
+                token_id: token(),
+                amount:   amount(15),
+                from:     account(1),
+                to:       account(2),
+            }),
+            record(Event::Burn {
+                token_id: token(),
+                amount:   amount(20),
+                owner:    account(2),
+            }),
+        ]);
+        assert_eq!(ledger.balance(CONTRACT, &token(), &account(1)).0, big(0));
+        assert_eq!(ledger.balance(CONTRACT, &token(), &account(2)).0, big(0));
+        assert_eq!(ledger.total_supply(CONTRACT, &token()).0, big(0));
+        assert!(ledger.snapshot(height(1)).tokens.is_empty());
+    }
+
+    #[test]
+    /// Test that operators are added and removed.
+    fn apply_operator_updates() {
+        let mut ledger = Cis2Ledger::new();
+        let update = |update, operator| {
+            record(Event::UpdateOperator {
+                update,
+                owner: account(1),
+                operator,
+            })
+        };
+        ledger.apply(height(1), &[
+            update(OperatorUpdate::Add, account(2)),
+            update(OperatorUpdate::Add, account(3)),
+            update(OperatorUpdate::Remove, account(2)),
+        ]);
+        assert_eq!(
+            ledger.operators(CONTRACT, &account(1)),
+            BTreeSet::from([account(3)])
+        );
+        assert!(ledger.operators(CONTRACT, &account(2)).is_empty());
+    }
+}
@@ -0,0 +1,462 @@
+//! Resolving the metadata of CIS-2 tokens.
+//!
+//! The `tokenMetadata` function of a CIS-2 contract only returns a
+//! [`MetadataUrl`], i.e., a URL and an optional SHA-256 checksum of the
+//! content found at the URL. The [`MetadataResolver`] fetches the content
+//! using a [`MetadataFetcher`], verifies the checksum if one is present, and
+//! parses the content as [`TokenMetadata`] as specified in the [CIS-2 standard](https://proposals.concordium.software/CIS/cis-2.html#token-metadata-json).
+//!
+//! Two fetchers are provided: `HttpFetcher` which fetches the metadata over
+//! HTTP(S), and [`FileMapFetcher`] which serves the metadata from a fixed map
+//! of URLs to contents, e.g., for testing. The `HttpFetcher` is only available
+//! with the `metadata-http` feature, which adds a dependency on `reqwest`.
+//! Resolved metadata is cached by URL and checksum, so that tokens sharing the
+//! same metadata only result in a single request.
+use super::{Cis2Contract, Cis2QueryError, MetadataUrl, TokenId};
+use crate::v2::IntoBlockIdentifier;
+use async_trait::async_trait;
+use sha2::Digest;
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use thiserror::Error;
+
+/// A SHA-256 checksum of metadata content.
+pub type Checksum = [u8; 32];
+
+/// A way of retrieving the content found at a metadata URL.
+#[async_trait]
+pub trait MetadataFetcher: Send + Sync {
+    /// Retrieve the content found at the given URL.
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>>;
+}
+
+/// A [`MetadataFetcher`] that retrieves the content using HTTP(S) `GET`
+/// requests. Responses with a non-success status code result in an error.
+#[cfg(feature = "metadata-http")]
+#[derive(Debug, Clone, Default)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "metadata-http")]
+impl HttpFetcher {
+    /// Construct a fetcher using a client with the default configuration.
+    pub fn new() -> Self { Self::default() }
+
+    /// Construct a fetcher using the given client, e.g., to configure
+    /// timeouts or proxies.
+    pub fn from_client(client: reqwest::Client) -> Self { Self { client } }
+}
+
+#[cfg(feature = "metadata-http")]
+#[async_trait]
+impl MetadataFetcher for HttpFetcher {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let response = self.client.get(url).send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+}
+
+/// A [`MetadataFetcher`] that serves the content from a fixed map of URLs to
+/// contents. Fetching any other URL results in an error.
+#[derive(Debug, Clone, Default)]
+pub struct FileMapFetcher {
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl FileMapFetcher {
+    /// Construct a fetcher without any contents.
+    pub fn new() -> Self { Self::default() }
+
+    /// Serve the given contents at the URL, replacing any existing contents.
+    pub fn insert(&mut self, url: impl Into<String>, contents: impl Into<Vec<u8>>) {
+        self.files.insert(url.into(), contents.into());
+    }
+
+    /// Serve the contents of the local file at the URL, replacing any
+    /// existing contents.
+    pub fn insert_file(
+        &mut self,
+        url: impl Into<String>,
+        path: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        let contents = std::fs::read(path)?;
+        self.insert(url, contents);
+        Ok(())
+    }
+}
+
+impl<U: Into<String>, C: Into<Vec<u8>>> FromIterator<(U, C)> for FileMapFetcher {
+    fn from_iter<T: IntoIterator<Item = (U, C)>>(iter: T) -> Self {
+        Self {
+            files: iter
+                .into_iter()
+                .map(|(url, contents)| (url.into(), contents.into()))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl MetadataFetcher for FileMapFetcher {
+    async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.files
+            .get(url)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("No contents for the URL {url}."))
+    }
+}
+
+/// The metadata of a CIS-2 token, as specified in the [CIS-2 standard](https://proposals.concordium.software/CIS/cis-2.html#token-metadata-json).
+/// All fields are optional.
+#[derive(Debug, Clone, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenMetadata {
+    /// The name to display for the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name:         Option<String>,
+    /// A short symbol to display for the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol:       Option<String>,
+    /// Whether the token is unique, i.e., has a total supply of at most one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unique:       Option<bool>,
+    /// The number of decimals used when displaying token amounts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals:     Option<u8>,
+    /// A description of the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description:  Option<String>,
+    /// An image to display as a small icon for the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thumbnail:    Option<MetadataLink>,
+    /// An image to display as the asset of the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display:      Option<MetadataLink>,
+    /// A media file of the asset of the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact:     Option<MetadataLink>,
+    /// The metadata of the assets the token is composed of.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assets:       Vec<TokenMetadata>,
+    /// Additional attributes of the token.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes:   Vec<TokenAttribute>,
+    /// Links to the metadata in other languages, keyed by language code, e.g.,
+    /// `en-US`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub localization: BTreeMap<String, MetadataLink>,
+}
+
+/// A link in [`TokenMetadata`], with an optional checksum of the content found
+/// at the URL.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MetadataLink {
+    /// The URL.
+    pub url:  String,
+    /// The hex-encoded SHA-256 checksum of the content found at the URL.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl MetadataLink {
+    /// The decoded checksum, if present. Fails if the checksum is not a
+    /// hex-encoded SHA-256 hash.
+    pub fn checksum(&self) -> Result<Option<Checksum>, MetadataError> {
+        self.hash
+            .as_deref()
+            .map(|hash| {
+                let mut checksum = [0u8; 32];
+                hex::decode_to_slice(hash, &mut checksum).map_err(|_| {
+                    MetadataError::InvalidChecksum {
+                        url: self.url.clone(),
+                    }
+                })?;
+                Ok(checksum)
+            })
+            .transpose()
+    }
+}
+
+/// An attribute in [`TokenMetadata`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TokenAttribute {
+    /// The type of the value, e.g., `string`, `number` or `date`.
+    #[serde(rename = "type")]
+    pub attribute_type: String,
+    /// The name of the attribute.
+    pub name:           String,
+    /// The value of the attribute.
+    pub value:          String,
+}
+
+/// An error that can occur when resolving token metadata.
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    /// Querying the metadata URL from the contract failed.
+    #[error("Failed to query the metadata URL: {0}")]
+    Query(#[from] Cis2QueryError),
+    /// Fetching the content failed.
+    #[error("Failed to fetch the metadata at {url}: {source}")]
+    Fetch {
+        url:    String,
+        #[source]
+        source: anyhow::Error,
+    },
+    /// The checksum of the content does not match the expected checksum.
+    #[error("The checksum of the metadata at {url} is {}, but {} was expected.", hex::encode(.actual), hex::encode(.expected))]
+    ChecksumMismatch {
+        url:      String,
+        expected: Checksum,
+        actual:   Checksum,
+    },
+    /// The checksum of a link is not a hex-encoded SHA-256 hash.
+    #[error("The checksum of the link to {url} is not a valid SHA-256 hash.")]
+    InvalidChecksum { url: String },
+    /// The content is not valid token metadata.
+    #[error("Failed to parse the metadata at {url}: {source}")]
+    Parse {
+        url:    String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Resolves [`MetadataUrl`]s to [`TokenMetadata`] using a [`MetadataFetcher`].
+/// Successfully resolved metadata is cached by URL and checksum.
+///
+/// Note that the resolver is safe to share between multiple tasks, but
+/// concurrent requests for metadata that is not yet cached may each result in
+/// a fetch.
+pub struct MetadataResolver<F> {
+    fetcher: F,
+    cache:   Mutex<BTreeMap<(String, Option<Checksum>), Arc<TokenMetadata>>>,
+}
+
+#[cfg(feature = "metadata-http")]
+impl Default for MetadataResolver<HttpFetcher> {
+    fn default() -> Self { Self::new(HttpFetcher::new()) }
+}
+
+impl<F: MetadataFetcher> MetadataResolver<F> {
+    /// Construct a resolver using the given fetcher.
+    pub fn new(fetcher: F) -> Self {
+        Self {
+            fetcher,
+            cache: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The fetcher used by the resolver.
+    pub fn fetcher(&self) -> &F { &self.fetcher }
+
+    /// Remove all metadata from the cache.
+    pub fn clear_cache(&self) { self.cache().clear() }
+
+    /// Fetch the metadata at the URL, verify the checksum if present, and
+    /// parse it.
+    pub async fn resolve(&self, url: &MetadataUrl) -> Result<Arc<TokenMetadata>, MetadataError> {
+        self.resolve_checked(url.url(), url.hash()).await
+    }
+
+    /// Like [`resolve`](Self::resolve), except for a link found in token
+    /// metadata.
+    pub async fn resolve_link(
+        &self,
+        link: &MetadataLink,
+    ) -> Result<Arc<TokenMetadata>, MetadataError> {
+        self.resolve_checked(&link.url, link.checksum()?).await
+    }
+
+    /// Resolve the metadata localized to the given language, e.g., `en-US`.
+    /// Returns [`None`] if the metadata has no localization for the language.
+    pub async fn resolve_localized(
+        &self,
+        metadata: &TokenMetadata,
+        language: &str,
+    ) -> Result<Option<Arc<TokenMetadata>>, MetadataError> {
+        match metadata.localization.get(language) {
+            Some(link) => Ok(Some(self.resolve_link(link).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Fetch the metadata at the URL, verify the checksum if provided, and
+    /// parse it, using the cache if possible.
+    pub async fn resolve_checked(
+        &self,
+        url: &str,
+        checksum: Option<Checksum>,
+    ) -> Result<Arc<TokenMetadata>, MetadataError> {
+        let key = (url.to_owned(), checksum);
+        let cached = self.cache().get(&key).cloned();
+        if let Some(metadata) = cached {
+            return Ok(metadata);
+        }
+        let contents = self
+            .fetcher
+            .fetch(url)
+            .await
+            .map_err(|source| MetadataError::Fetch {
+                url: url.to_owned(),
+                source,
+            })?;
+        if let Some(expected) = checksum {
+            let actual: Checksum = sha2::Sha256::digest(&contents).into();
+            if actual != expected {
+                return Err(MetadataError::ChecksumMismatch {
+                    url: url.to_owned(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+        let metadata: TokenMetadata =
+            serde_json::from_slice(&contents).map_err(|source| MetadataError::Parse {
+                url: url.to_owned(),
+                source,
+            })?;
+        let metadata = Arc::new(metadata);
+        self.cache().insert(key, metadata.clone());
+        Ok(metadata)
+    }
+
+    fn cache(
+        &self,
+    ) -> std::sync::MutexGuard<'_, BTreeMap<(String, Option<Checksum>), Arc<TokenMetadata>>> {
+        self.cache.lock().expect("Metadata cache is not poisoned.")
+    }
+}
+
+impl Cis2Contract {
+    /// Query the metadata URL of the token using
+    /// [`token_metadata_single`](Self::token_metadata_single), and resolve it
+    /// using the resolver.
+    pub async fn resolve_token_metadata<F: MetadataFetcher>(
+        &mut self,
+        bi: impl IntoBlockIdentifier,
+        token_id: TokenId,
+        resolver: &MetadataResolver<F>,
+    ) -> Result<Arc<TokenMetadata>, MetadataError> {
+        let url = self.token_metadata_single(bi, token_id).await?;
+        resolver.resolve(&url).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const URL: &str = "https://example.com/token.json";
+    const CONTENTS: &str = r#"{"name": "Token", "symbol": "TKN", "decimals": 6}"#;
+
+    /// A fetcher that counts the number of fetches.
+    struct CountingFetcher {
+        inner:   FileMapFetcher,
+        fetches: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl MetadataFetcher for CountingFetcher {
+        async fn fetch(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            self.inner.fetch(url).await
+        }
+    }
+
+    fn resolver() -> MetadataResolver<CountingFetcher> {
+        MetadataResolver::new(CountingFetcher {
+            inner:   [(URL, CONTENTS)].into_iter().collect(),
+            fetches: AtomicUsize::new(0),
+        })
+    }
+
+    fn checksum() -> Checksum { sha2::Sha256::digest(CONTENTS.as_bytes()).into() }
+
+    #[tokio::test]
+    /// Test that the content is parsed, and that the checksum is verified if
+    /// present.
+    async fn verify_checksum() {
+        let resolver = resolver();
+        let metadata = resolver
+            .resolve_checked(URL, Some(checksum()))
+            .await
+            .expect("Checksum matches.");
+        assert_eq!(metadata.name.as_deref(), Some("Token"));
+        assert_eq!(metadata.symbol.as_deref(), Some("TKN"));
+        assert_eq!(metadata.decimals, Some(6));
+        assert!(resolver.resolve_checked(URL, None).await.is_ok());
+
+        let expected = [0u8; 32];
+        match resolver.resolve_checked(URL, Some(expected)).await {
+            Err(MetadataError::ChecksumMismatch {
+                url,
+                expected: e,
+                actual,
+            }) => {
+                assert_eq!(url, URL);
+                assert_eq!(e, expected);
+                assert_eq!(actual, checksum());
+            }
+            other => panic!("Expected a checksum mismatch, got {other:?}."),
+        }
+        assert!(matches!(
+            resolver
+                .resolve_checked("https://example.com/missing.json", None)
+                .await,
+            Err(MetadataError::Fetch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    /// Test that resolved metadata is cached by URL and checksum, and that
+    /// failed resolutions are not cached.
+    async fn cache_by_url_and_checksum() {
+        let resolver = resolver();
+        let fetches = || resolver.fetcher().fetches.load(Ordering::SeqCst);
+        let first = resolver.resolve_checked(URL, None).await.unwrap();
+        let second = resolver.resolve_checked(URL, None).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(fetches(), 1);
+
+        resolver
+            .resolve_checked(URL, Some(checksum()))
+            .await
+            .unwrap();
+        assert_eq!(fetches(), 2);
+
+        for _ in 0..2 {
+            assert!(resolver
+                .resolve_checked(URL, Some([0u8; 32]))
+                .await
+                .is_err());
+        }
+        assert_eq!(fetches(), 4);
+
+        resolver.clear_cache();
+        resolver.resolve_checked(URL, None).await.unwrap();
+        assert_eq!(fetches(), 5);
+    }
+
+    #[test]
+    /// Test decoding the checksum of a link.
+    fn link_checksum() {
+        let link = |hash: Option<String>| MetadataLink {
+            url: URL.into(),
+            hash,
+        };
+        assert_eq!(link(None).checksum().unwrap(), None);
+        assert_eq!(
+            link(Some(hex::encode(checksum()))).checksum().unwrap(),
+            Some(checksum())
+        );
+        assert!(matches!(
+            link(Some("abcd".into())).checksum(),
+            Err(MetadataError::InvalidChecksum { .. })
+        ));
+    }
+}
@@ -4,6 +4,9 @@
 //! The type [`Cis2Contract`](crate::cis2::Cis2Contract) act as a wrapper around
 //! the [Client](crate::v2::Client) and a contract address providing
 //! functions for querying and making transactions to smart contract.
+pub mod airdrop;
+pub mod indexer;
+pub mod metadata;
 mod types;
 
 use crate::{contract_client::*, types as sdk_types, v2::IntoBlockIdentifier};
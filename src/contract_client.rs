@@ -18,6 +18,7 @@ use crate::{
         BlockIdentifier, Client,
     },
 };
+use build_info::VersionedBuildInfo;
 use concordium_base::{
     base::{Energy, Nonce},
     common::types::{self, TransactionTime},
@@ -41,6 +42,14 @@ use serde_json::Value;
 use std::{fmt, marker::PhantomData, sync::Arc};
 use v2::{QueryError, RPCError};
 
+pub mod build_info;
+pub mod codegen;
+pub mod events;
+pub mod history;
+pub mod profiler;
+pub mod state;
+pub mod upgrade;
+
 /// A contract client that handles some of the boilerplate such as serialization
 /// and parsing of responses when sending transactions, or invoking smart
 /// contracts.
@@ -57,6 +66,10 @@ pub struct ContractClient<Type> {
     pub contract_name: Arc<contracts_common::OwnedContractName>,
     /// The schema of the contract at the address.
     pub schema:        Arc<Option<VersionedModuleSchema>>,
+    /// The build information embedded in the module of the contract at the
+    /// time the client was constructed using [`create`](Self::create), if the
+    /// module is verifiably built.
+    pub build_info:    Arc<Option<VersionedBuildInfo>>,
     phantom:           PhantomData<Type>,
 }
 
@@ -68,6 +81,7 @@ impl<Type> Clone for ContractClient<Type> {
             contract_name: self.contract_name.clone(),
             phantom:       PhantomData,
             schema:        self.schema.clone(),
+            build_info:    self.build_info.clone(),
         }
     }
 }
@@ -101,12 +115,34 @@ pub enum ViewError {
     NetworkError(#[from] v2::QueryError),
     #[error("Parameter is too large: {0}")]
     ParameterError(#[from] ExceedsParameterSize),
+    #[error("Could not convert using the schema: {0}")]
+    SchemaError(#[from] SchemaJsonError),
 }
 
 impl From<RejectReason> for ViewError {
     fn from(value: RejectReason) -> Self { Self::QueryFailed(value) }
 }
 
+#[derive(Debug, thiserror::Error)]
+/// An error converting between JSON and the binary representation of
+/// parameters and return values using the schema of the contract, as done by
+/// the [`view_json`](ContractClient::view_json) family of functions.
+pub enum SchemaJsonError {
+    #[error("The contract client has no schema.")]
+    NoSchema,
+    #[error("The schema has no {kind} type for the entrypoint {entrypoint}.")]
+    MissingType {
+        kind:       &'static str,
+        entrypoint: String,
+    },
+    #[error("The JSON value does not match the parameter type: {0}")]
+    Serialize(String),
+    #[error("The return value does not match the return value type: {0}")]
+    Deserialize(String),
+    #[error("The entrypoint did not produce a return value.")]
+    NoReturnValue,
+}
+
 /// A builder of transactions out of minimal data typically obtained by
 /// dry-running.
 ///
@@ -1133,6 +1169,16 @@ pub fn decode_smart_contract_revert(
     }
 }
 
+/// Extract the schema embedded in the custom section of the given module, if
+/// any. Returns [`None`] if the module has no embedded schema, or if the schema
+/// cannot be parsed.
+pub(crate) fn get_embedded_schema(wasm_module: &WasmModule) -> Option<VersionedModuleSchema> {
+    match wasm_module.version {
+        WasmVersion::V0 => utils::get_embedded_schema_v0(wasm_module.source.as_ref()).ok(),
+        WasmVersion::V1 => utils::get_embedded_schema_v1(wasm_module.source.as_ref()).ok(),
+    }
+}
+
 impl<Type> ContractClient<Type> {
     /// Construct a [`ContractClient`] by looking up metadata from the chain
     /// (such as the contract_name and the embedded schema).
@@ -1158,10 +1204,8 @@ impl<Type> ContractClient<Type> {
             .response;
 
         // Get the schema associated to the contract instance.
-        let schema = match wasm_module.version {
-            WasmVersion::V0 => utils::get_embedded_schema_v0(wasm_module.source.as_ref()).ok(),
-            WasmVersion::V1 => utils::get_embedded_schema_v1(wasm_module.source.as_ref()).ok(),
-        };
+        let schema = get_embedded_schema(&wasm_module);
+        let build_info = build_info::get_build_info(&wasm_module).ok().flatten();
 
         Ok(Self {
             client,
@@ -1169,6 +1213,7 @@ impl<Type> ContractClient<Type> {
             contract_name: Arc::new(contract_name),
             phantom: PhantomData,
             schema: Arc::new(schema),
+            build_info: Arc::new(build_info),
         })
     }
 
@@ -1190,6 +1235,7 @@ impl<Type> ContractClient<Type> {
             contract_name: Arc::new(contract_name),
             phantom: PhantomData,
             schema: Arc::new(None),
+            build_info: Arc::new(None),
         }
     }
 
@@ -1221,6 +1267,7 @@ impl<Type> ContractClient<Type> {
             contract_name: Arc::new(contract_name),
             phantom: PhantomData,
             schema: Arc::new(Some(schema)),
+            build_info: Arc::new(None),
         }
     }
 
@@ -1278,6 +1325,82 @@ impl<Type> ContractClient<Type> {
         }
     }
 
+    /// Like [`view`](Self::view) but takes the parameter as JSON and returns
+    /// the response as JSON. The parameter is serialized, and the response
+    /// deserialized, using the schema of the client, which must contain the
+    /// parameter and return value types of the entrypoint.
+    ///
+    /// For a general contract [`ViewError`] can be used as a concrete error
+    /// type `E`.
+    pub async fn view_json<E>(
+        &mut self,
+        entrypoint: &str,
+        parameter: &Value,
+        bi: impl v2::IntoBlockIdentifier,
+    ) -> Result<Value, E>
+    where
+        E: From<NewReceiveNameError>
+            + From<RejectReason>
+            + From<v2::QueryError>
+            + From<ExceedsParameterSize>
+            + From<SchemaJsonError>, {
+        let parameter = self.parameter_from_json::<E>(entrypoint, parameter)?;
+        let ir = self
+            .invoke_raw::<E>(entrypoint, Amount::zero(), None, parameter, bi)
+            .await?;
+        match ir {
+            smart_contracts::InvokeContractResult::Success { return_value, .. } => {
+                let Some(return_value) = return_value else {
+                    return Err(SchemaJsonError::NoReturnValue.into());
+                };
+                Ok(self.return_value_to_json(entrypoint, &return_value)?)
+            }
+            smart_contracts::InvokeContractResult::Failure { reason, .. } => Err(reason.into()),
+        }
+    }
+
+    /// Serialize a JSON parameter of the entrypoint using the schema of the
+    /// client.
+    pub fn parameter_from_json<E>(
+        &self,
+        entrypoint: &str,
+        parameter: &Value,
+    ) -> Result<OwnedParameter, E>
+    where
+        E: From<ExceedsParameterSize> + From<SchemaJsonError>, {
+        let schema = (*self.schema).as_ref().ok_or(SchemaJsonError::NoSchema)?;
+        let contract_name = self.contract_name.as_contract_name().contract_name();
+        let ty = schema
+            .get_receive_param_schema(contract_name, entrypoint)
+            .map_err(|_| SchemaJsonError::MissingType {
+                kind:       "parameter",
+                entrypoint: entrypoint.into(),
+            })?;
+        let bytes = ty
+            .serial_value(parameter)
+            .map_err(|e| SchemaJsonError::Serialize(e.to_string()))?;
+        Ok(OwnedParameter::try_from(bytes)?)
+    }
+
+    /// Deserialize a return value of the entrypoint to JSON using the schema
+    /// of the client.
+    pub fn return_value_to_json(
+        &self,
+        entrypoint: &str,
+        return_value: &ReturnValue,
+    ) -> Result<Value, SchemaJsonError> {
+        let schema = (*self.schema).as_ref().ok_or(SchemaJsonError::NoSchema)?;
+        let contract_name = self.contract_name.as_contract_name().contract_name();
+        let ty = schema
+            .get_receive_return_value_schema(contract_name, entrypoint)
+            .map_err(|_| SchemaJsonError::MissingType {
+                kind:       "return value",
+                entrypoint: entrypoint.into(),
+            })?;
+        ty.to_json(&mut Cursor::new(&return_value.value))
+            .map_err(|e| SchemaJsonError::Deserialize(e.to_string()))
+    }
+
     /// Invoke a contract instance and return the response without any
     /// processing.
     pub async fn invoke_raw<E>(
@@ -1360,6 +1483,36 @@ impl<Type> ContractClient<Type> {
             .await
     }
 
+    /// Like [`dry_run_update`](Self::dry_run_update) but takes the parameter
+    /// as JSON, which is serialized using the schema of the client. If the
+    /// schema contains the return value type of the entrypoint, the return
+    /// value is also available as JSON via
+    /// [`return_value_json`](ContractUpdateBuilder::return_value_json).
+    pub async fn dry_run_update_json<E>(
+        &mut self,
+        entrypoint: &str,
+        amount: Amount,
+        sender: AccountAddress,
+        message: &Value,
+    ) -> Result<ContractUpdateBuilder, E>
+    where
+        E: From<NewReceiveNameError>
+            + From<RejectReason>
+            + From<v2::QueryError>
+            + From<ExceedsParameterSize>
+            + From<SchemaJsonError>, {
+        let message = self.parameter_from_json::<E>(entrypoint, message)?;
+        let mut builder = self
+            .dry_run_update_raw::<E>(entrypoint, amount, sender, message)
+            .await?;
+        builder.inner.return_value_json = builder
+            .inner
+            .return_value
+            .as_ref()
+            .and_then(|rv| self.return_value_to_json(entrypoint, rv).ok());
+        Ok(builder)
+    }
+
     /// Like [`dry_run_update`](Self::dry_run_update) but expects an already
     /// formed parameter.
     pub async fn dry_run_update_raw<E>(
@@ -1407,6 +1560,7 @@ impl<Type> ContractClient<Type> {
                 transactions::Payload::Update { payload },
                 ContractUpdateInner {
                     return_value,
+                    return_value_json: None,
                     events,
                 },
             )),
@@ -1455,6 +1609,7 @@ impl<Type> ContractClient<Type> {
                 transactions::Payload::Update { payload },
                 ContractUpdateInner {
                     return_value,
+                    return_value_json: None,
                     events,
                 },
             ))),
@@ -1509,6 +1664,25 @@ impl<Type> ContractClient<Type> {
             .await
     }
 
+    /// Like [`update`](Self::update) but takes the parameter as JSON, which is
+    /// serialized using the schema of the client.
+    pub async fn update_json<E>(
+        &mut self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+        metadata: &ContractTransactionMetadata,
+        entrypoint: &str,
+        message: &Value,
+    ) -> Result<TransactionHash, E>
+    where
+        E: From<NewReceiveNameError>
+            + From<v2::RPCError>
+            + From<ExceedsParameterSize>
+            + From<SchemaJsonError>, {
+        let message = self.parameter_from_json::<E>(entrypoint, message)?;
+        self.update_raw::<E>(signer, metadata, entrypoint, message)
+            .await
+    }
+
     /// Like [`update`](Self::update) but expects a serialized parameter.
     pub async fn update_raw<E>(
         &mut self,
@@ -1560,8 +1734,9 @@ impl<Type> ContractClient<Type> {
 /// A helper type to construct [`ContractUpdateBuilder`].
 /// Users do not directly interact with values of this type.
 pub struct ContractUpdateInner {
-    return_value: Option<ReturnValue>,
-    events:       Vec<ContractTraceElement>,
+    return_value:      Option<ReturnValue>,
+    return_value_json: Option<Value>,
+    events:            Vec<ContractTraceElement>,
 }
 
 /// A builder to simplify sending smart contract updates.
@@ -1574,9 +1749,11 @@ impl ContractUpdateBuilder {
         self,
         signer: &impl transactions::ExactSizeTransactionSigner,
     ) -> v2::QueryResult<ContractUpdateHandle> {
+        let return_value = self.inner.return_value.clone();
         self.send_inner(signer, |tx_hash, client| ContractUpdateHandle {
             tx_hash,
             client,
+            return_value,
         })
         .await
     }
@@ -1584,6 +1761,12 @@ impl ContractUpdateBuilder {
     /// Get the return value from dry-running.
     pub fn return_value(&self) -> Option<&ReturnValue> { self.inner.return_value.as_ref() }
 
+    /// Get the return value from dry-running as JSON. This is only available
+    /// if the update was dry-run with
+    /// [`dry_run_update_json`](ContractClient::dry_run_update_json) and the
+    /// schema contains the return value type of the entrypoint.
+    pub fn return_value_json(&self) -> Option<&Value> { self.inner.return_value_json.as_ref() }
+
     /// Get the events generated from the dry-run.
     pub fn events(&self) -> &[ContractTraceElement] { &self.inner.events }
 }
@@ -1594,8 +1777,10 @@ impl ContractUpdateBuilder {
 /// Note that this handle retains a connection to the node. So if it is not
 /// going to be used it should be dropped.
 pub struct ContractUpdateHandle {
-    tx_hash: TransactionHash,
-    client:  v2::Client,
+    tx_hash:      TransactionHash,
+    client:       v2::Client,
+    /// The return value from dry-running the update, if any.
+    return_value: Option<ReturnValue>,
 }
 
 /// The [`Display`](std::fmt::Display) implementation displays the hash of the
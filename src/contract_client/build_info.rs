@@ -0,0 +1,140 @@
+//! Checking that deployed modules are verifiably built.
+//!
+//! Modules built with `cargo concordium build --verifiable` embed information
+//! about the build in a custom section of the Wasm module: the hash of the
+//! source archive the module was built from, the container image and the
+//! command used for the build, and optionally a link to the archive.
+//!
+//! [`verify_build`] fetches a module from the chain and compares its build
+//! information against a local source archive, or a module built locally from
+//! it, and reports the result as a [`BuildVerification`].
+//! [`ContractClient::create`](super::ContractClient::create) looks up the build
+//! information of the module of the instance, which is available in the
+//! [`build_info`](super::ContractClient::build_info) field of the client.
+use crate::v2::{self, IntoBlockIdentifier};
+use concordium_base::{
+    hashes,
+    smart_contracts::{ModuleReference, WasmModule},
+};
+use concordium_smart_contract_engine::utils::{self, CustomSectionLookupError};
+pub use concordium_smart_contract_engine::utils::{BuildInfo, VersionedBuildInfo};
+use sha2::Digest;
+use std::path::{Path, PathBuf};
+
+/// An error that occurred while verifying the build of a module.
+#[derive(thiserror::Error, Debug)]
+pub enum VerifyBuildError {
+    #[error("Query failed: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Could not read a file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The build information of the module is malformed.")]
+    MalformedBuildInfo,
+}
+
+/// The local artifact to compare a deployed module against.
+#[derive(Debug, Clone)]
+pub enum BuildSource {
+    /// The source archive the module is claimed to be built from, as produced
+    /// by `cargo concordium build --verifiable`.
+    Archive(Vec<u8>),
+    /// A path to the source archive.
+    ArchiveFile(PathBuf),
+    /// A module built locally from the source archive.
+    Module(WasmModule),
+    /// A path to a `.wasm.v1` module built locally from the source archive.
+    ModuleFile(PathBuf),
+}
+
+impl BuildSource {
+    /// Construct a [`BuildSource`] from a path, which is treated as a module if
+    /// its extension is `v1` or `wasm`, and as a source archive otherwise.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("v1" | "wasm") => Self::ModuleFile(path.to_path_buf()),
+            _ => Self::ArchiveFile(path.to_path_buf()),
+        }
+    }
+}
+
+/// The result of [`verify_build`].
+#[derive(Debug, Clone)]
+pub struct BuildVerification {
+    /// The reference of the deployed module.
+    pub module_ref:   ModuleReference,
+    /// The build information embedded in the deployed module, if any.
+    pub build_info:   Option<VersionedBuildInfo>,
+    /// The hash of the local source archive, if an archive was used.
+    pub archive_hash: Option<hashes::Hash>,
+    /// The reference of the local module, if a module was used.
+    pub local_module: Option<ModuleReference>,
+    /// Whether the build information of the deployed module matches the
+    /// local artifact. For an archive the hash of the archive must match the
+    /// embedded hash. For a module the modules must be identical, which
+    /// includes the embedded build information.
+    pub matches:      bool,
+}
+
+impl BuildVerification {
+    /// Whether the deployed module is verifiably built from the local
+    /// artifact, i.e., it has embedded build information and it matches.
+    pub fn is_verified(&self) -> bool { self.build_info.is_some() && self.matches }
+
+    fn check_archive(&mut self, archive: &[u8]) {
+        let hash = archive_hash(archive);
+        self.archive_hash = Some(hash);
+        self.matches = matches!(
+            &self.build_info,
+            Some(VersionedBuildInfo::V0(info)) if info.archive_hash == hash
+        );
+    }
+
+    fn check_module(&mut self, module: &WasmModule) {
+        let local_ref = module.get_module_ref();
+        self.local_module = Some(local_ref);
+        self.matches = local_ref == self.module_ref;
+    }
+}
+
+/// Extract the build information embedded in a module. Returns [`None`] if
+/// the module has no build information, and an error if it is malformed.
+pub fn get_build_info(module: &WasmModule) -> Result<Option<VersionedBuildInfo>, VerifyBuildError> {
+    match utils::get_build_info(module.source.as_ref()) {
+        Ok(info) => Ok(Some(info)),
+        Err(CustomSectionLookupError::Missing) => Ok(None),
+        Err(_) => Err(VerifyBuildError::MalformedBuildInfo),
+    }
+}
+
+/// The hash of a source archive, as embedded in the build information of
+/// modules built from it.
+pub fn archive_hash(archive: &[u8]) -> hashes::Hash {
+    hashes::Hash::new(sha2::Sha256::digest(archive).into())
+}
+
+/// Fetch the module with the given reference in the given block, and check
+/// whether it is verifiably built from the local artifact.
+pub async fn verify_build(
+    client: &mut v2::Client,
+    module_ref: &ModuleReference,
+    source: BuildSource,
+    bi: impl IntoBlockIdentifier,
+) -> Result<BuildVerification, VerifyBuildError> {
+    let module = client.get_module_source(module_ref, bi).await?.response;
+    let build_info = get_build_info(&module)?;
+    let mut verification = BuildVerification {
+        module_ref: *module_ref,
+        build_info,
+        archive_hash: None,
+        local_module: None,
+        matches: false,
+    };
+    match source {
+        BuildSource::Archive(archive) => verification.check_archive(&archive),
+        BuildSource::ArchiveFile(path) => verification.check_archive(&std::fs::read(path)?),
+        BuildSource::Module(module) => verification.check_module(&module),
+        BuildSource::ModuleFile(path) => verification.check_module(&WasmModule::from_file(&path)?),
+    }
+    Ok(verification)
+}
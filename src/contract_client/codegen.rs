@@ -0,0 +1,1031 @@
+//! Generation of typed contract clients from module schemas.
+//!
+//! A [`BindingsGenerator`] emits Rust source code for a strongly typed client
+//! of a contract, in the style of [`Cis2Contract`](crate::cis2::Cis2Contract).
+//! The generated code consists of
+//!
+//!  * a marker type `{Name}Type` and a client `{Name}Contract` wrapping a
+//!    [`ContractClient<{Name}Type>`](super::ContractClient). The client
+//!    dereferences to the wrapped [`ContractClient`](super::ContractClient), so
+//!    that its generic methods are also available;
+//!  * parameter, return value and error types for each entrypoint, together
+//!    with [`Serial`](concordium_base::contracts_common::Serial) and
+//!    [`Deserial`](concordium_base::contracts_common::Deserial) implementations
+//!    matching the schema;
+//!  * an `impl` block with methods for each entrypoint. For an entrypoint
+//!    `someEntrypoint` these are `some_entrypoint_dry_run` and
+//!    `some_entrypoint_update`, as well as `some_entrypoint` for querying the
+//!    entrypoint if the schema contains its return value type. If any of these
+//!    names is already used by a method of
+//!    [`ContractClient`](super::ContractClient) or by another entrypoint, the
+//!    name of the entrypoint is suffixed with `_entrypoint`, e.g., the methods
+//!    for an entrypoint `view` are `view_entrypoint`, `view_entrypoint_dry_run`
+//!    and `view_entrypoint_update`.
+//!
+//! The generator is intended to be used from a build script, and the
+//! generated code included in the crate using it:
+//!
+//! ```no_run
+//! // build.rs
+//! use concordium_rust_sdk::contract_client::codegen::BindingsGenerator;
+//!
+//! fn main() {
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     BindingsGenerator::from_module_file("my_contract.wasm.v1".as_ref(), "my_contract")
+//!         .unwrap()
+//!         .write_to(std::path::Path::new(&out_dir).join("my_contract.rs"))
+//!         .unwrap();
+//!     println!("cargo:rerun-if-changed=my_contract.wasm.v1");
+//! }
+//! ```
+//!
+//! ```ignore
+//! // src/lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/my_contract.rs"));
+//! ```
+//!
+//! A procedural macro can use [`BindingsGenerator::generate`] in the same way
+//! and parse the result into a token stream.
+use super::get_embedded_schema;
+use concordium_base::{
+    contracts_common::schema::{
+        Fields, FunctionV1, FunctionV2, SizeLength, Type, VersionedModuleSchema,
+    },
+    smart_contracts::WasmModule,
+};
+use std::{collections::BTreeSet, fmt::Write, path::Path};
+
+/// The names of the methods available on the generated client, either directly
+/// or through dereferencing to [`ContractClient`](super::ContractClient).
+/// Methods generated for entrypoints must not use these names, since they
+/// would either conflict with or shadow the existing methods.
+const RESERVED_METHODS: &[&str] = &[
+    "clone",
+    "create",
+    "deref",
+    "deref_mut",
+    "dry_run_update",
+    "dry_run_update_json",
+    "dry_run_update_raw",
+    "dry_run_update_raw_with_reject_reason_info",
+    "dry_run_update_with_reject_reason_info",
+    "fmt",
+    "from",
+    "into",
+    "invoke_raw",
+    "make_update",
+    "make_update_raw",
+    "new",
+    "new_with_schema",
+    "parameter_from_json",
+    "return_value_to_json",
+    "state_inspector",
+    "subscribe_events",
+    "update",
+    "update_json",
+    "update_raw",
+    "upgrade",
+    "view",
+    "view_changes",
+    "view_history",
+    "view_json",
+    "view_raw",
+];
+
+/// An error that occurred while generating bindings.
+#[derive(thiserror::Error, Debug)]
+pub enum CodegenError {
+    #[error("Could not read or write a file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("The module has no embedded schema.")]
+    NoSchema,
+    #[error("The schema has no contract named {0}.")]
+    UnknownContract(String),
+    #[error("The type of {item} uses {ty}, which is not supported.")]
+    Unsupported { item: String, ty: &'static str },
+}
+
+/// Generates a typed client of a contract from the schema of its module. See
+/// the [module documentation](self) for an overview.
+#[derive(Debug, Clone)]
+pub struct BindingsGenerator {
+    schema:        VersionedModuleSchema,
+    contract_name: String,
+    type_name:     String,
+    sdk_path:      String,
+}
+
+/// The schema of a single entrypoint, independent of the schema version.
+pub(crate) struct EntrypointSchema {
+    pub(crate) name:         String,
+    pub(crate) parameter:    Option<Type>,
+    pub(crate) return_value: Option<Type>,
+    pub(crate) error:        Option<Type>,
+}
+
+impl BindingsGenerator {
+    /// Construct a generator for the contract with the given name, e.g.,
+    /// `my_contract`, using the schema. The generated types are named after
+    /// the contract, see [`set_type_name`](Self::set_type_name).
+    pub fn new(schema: VersionedModuleSchema, contract_name: impl Into<String>) -> Self {
+        let contract_name = contract_name.into();
+        Self {
+            schema,
+            type_name: camel_case(&contract_name),
+            contract_name,
+            sdk_path: "::concordium_rust_sdk".into(),
+        }
+    }
+
+    /// Construct a generator using the schema embedded in the module.
+    pub fn from_module(
+        module: &WasmModule,
+        contract_name: impl Into<String>,
+    ) -> Result<Self, CodegenError> {
+        let schema = get_embedded_schema(module).ok_or(CodegenError::NoSchema)?;
+        Ok(Self::new(schema, contract_name))
+    }
+
+    /// Construct a generator using the schema embedded in the module at the
+    /// given path, e.g., a `.wasm.v1` file produced by `cargo concordium`.
+    pub fn from_module_file(
+        path: &Path,
+        contract_name: impl Into<String>,
+    ) -> Result<Self, CodegenError> {
+        let module = WasmModule::from_file(path)?;
+        Self::from_module(&module, contract_name)
+    }
+
+    /// Set the name used for the generated marker type `{name}Type` and the
+    /// client alias `{name}Contract`. Defaults to the contract name in camel
+    /// case.
+    pub fn set_type_name(self, type_name: impl Into<String>) -> Self {
+        Self {
+            type_name: type_name.into(),
+            ..self
+        }
+    }
+
+    /// Set the path of this crate used in the generated code. Defaults to
+    /// `::concordium_rust_sdk`, which should be changed if the crate is
+    /// renamed in the dependencies of the crate including the bindings.
+    pub fn set_sdk_path(self, sdk_path: impl Into<String>) -> Self {
+        Self {
+            sdk_path: sdk_path.into(),
+            ..self
+        }
+    }
+
+    /// Write the generated bindings to a file.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), CodegenError> {
+        std::fs::write(path, self.generate()?)?;
+        Ok(())
+    }
+
+    /// Generate the bindings as Rust source code.
+    pub fn generate(&self) -> Result<String, CodegenError> {
+        let entrypoints = self.entrypoints()?;
+        let sdk = &self.sdk_path;
+        let mut emitter = Emitter {
+            cc:    format!("{sdk}::base::contracts_common"),
+            items: String::new(),
+            names: BTreeSet::new(),
+        };
+        let marker = format!("{}Type", self.type_name);
+        let alias = format!("{}Contract", self.type_name);
+        emitter.names.insert(marker.clone());
+        emitter.names.insert(alias.clone());
+
+        let mut methods = String::new();
+        let mut used_methods: BTreeSet<String> = RESERVED_METHODS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        for ep in &entrypoints {
+            let type_prefix = camel_case(&ep.name);
+            let method = method_name(&snake_case(&ep.name), &mut used_methods);
+            let parameter = match &ep.parameter {
+                Some(ty) => emitter.top_level(ty, &format!("{type_prefix}Parameter"), &ep.name)?,
+                None => "()".into(),
+            };
+            if let Some(ty) = &ep.error {
+                emitter.top_level(ty, &format!("{type_prefix}Error"), &ep.name)?;
+            }
+            let entrypoint = &ep.name;
+            if let Some(ty) = &ep.return_value {
+                let return_value =
+                    emitter.top_level(ty, &format!("{type_prefix}ReturnValue"), &ep.name)?;
+                let _ = writeln!(
+                    methods,
+                    "    /// Invoke the `{entrypoint}` entrypoint without creating a transaction, \
+                     and return its response.
+    pub async fn {method}(
+        &mut self,
+        parameter: &{parameter},
+        bi: impl {sdk}::v2::IntoBlockIdentifier,
+    ) -> Result<{return_value}, {sdk}::contract_client::ViewError> {{
+        self.inner.view({entrypoint:?}, parameter, bi).await
+    }}
+"
+                );
+            }
+            let _ = writeln!(
+                methods,
+                "    /// Dry-run an update of the `{entrypoint}` entrypoint. If it succeeds, the \
+                 result can be used to send the transaction.
+    pub async fn {method}_dry_run(
+        &mut self,
+        sender: {cc}::AccountAddress,
+        amount: {cc}::Amount,
+        parameter: &{parameter},
+    ) -> Result<
+        {sdk}::contract_client::ContractUpdateBuilder,
+        {sdk}::contract_client::ViewError,
+    > {{
+        self.inner.dry_run_update({entrypoint:?}, amount, sender, parameter).await
+    }}
+
+    /// Send a transaction updating the `{entrypoint}` entrypoint.
+    pub async fn {method}_update<E>(
+        &mut self,
+        signer: &impl {sdk}::types::transactions::ExactSizeTransactionSigner,
+        metadata: &{sdk}::contract_client::ContractTransactionMetadata,
+        parameter: &{parameter},
+    ) -> Result<{sdk}::types::hashes::TransactionHash, E>
+    where
+        E: From<{cc}::NewReceiveNameError>
+            + From<{sdk}::v2::RPCError>
+            + From<{sdk}::base::smart_contracts::ExceedsParameterSize>, {{
+        self.inner.update::<_, E>(signer, metadata, {entrypoint:?}, parameter).await
+    }}
+",
+                cc = emitter.cc,
+            );
+        }
+
+        let mut out = format!(
+            "// Generated from the schema of the contract `{contract}`. Do not edit.
+
+/// A marker type to indicate that a [`ContractClient`]({sdk}::contract_client::ContractClient) is \
+             a client for the `{contract}` contract.
+#[derive(Debug, Clone, Copy)]
+pub enum {marker} {{}}
+
+/// A client for the `{contract}` contract. It dereferences to the wrapped \
+             [`ContractClient`]({sdk}::contract_client::ContractClient), so that its generic \
+             methods are also available.
+#[derive(Debug, Clone)]
+pub struct {alias} {{
+    /// The wrapped client.
+    pub inner: {sdk}::contract_client::ContractClient<{marker}>,
+}}
+
+impl From<{sdk}::contract_client::ContractClient<{marker}>> for {alias} {{
+    fn from(inner: {sdk}::contract_client::ContractClient<{marker}>) -> Self {{ Self {{ inner }} }}
+}}
+
+impl std::ops::Deref for {alias} {{
+    type Target = {sdk}::contract_client::ContractClient<{marker}>;
+
+    fn deref(&self) -> &Self::Target {{ &self.inner }}
+}}
+
+impl std::ops::DerefMut for {alias} {{
+    fn deref_mut(&mut self) -> &mut Self::Target {{ &mut self.inner }}
+}}
+",
+            contract = self.contract_name,
+        );
+        out.push_str(&emitter.items);
+        let _ = write!(
+            out,
+            "
+impl {alias} {{
+    /// Construct a client for the instance at the given address, using the schema embedded in the \
+             module of the instance.
+    pub async fn create(
+        client: {sdk}::v2::Client,
+        address: {cc}::ContractAddress,
+    ) -> {sdk}::v2::QueryResult<Self> {{
+        {sdk}::contract_client::ContractClient::create(client, address).await.map(Self::from)
+    }}
+
+{}}}
+",
+            methods.trim_end_matches('\n').to_string() + "\n",
+            cc = emitter.cc,
+        );
+        Ok(out)
+    }
+
+    /// Collect the entrypoints of the contract from the schema.
+    fn entrypoints(&self) -> Result<Vec<EntrypointSchema>, CodegenError> {
+        entrypoint_schemas(&self.schema, &self.contract_name)
+            .ok_or_else(|| CodegenError::UnknownContract(self.contract_name.clone()))
+    }
+}
+
+/// Collect the schemas of the entrypoints of a contract, or [`None`] if the
+/// schema has no contract with the given name.
+pub(crate) fn entrypoint_schemas(
+    schema: &VersionedModuleSchema,
+    contract_name: &str,
+) -> Option<Vec<EntrypointSchema>> {
+    let from_v1 = |name: &String, f: &FunctionV1| EntrypointSchema {
+        name:         name.clone(),
+        parameter:    f.parameter().cloned(),
+        return_value: f.return_value().cloned(),
+        error:        None,
+    };
+    let from_v2 = |name: &String, f: &FunctionV2| EntrypointSchema {
+        name:         name.clone(),
+        parameter:    f.parameter.clone(),
+        return_value: f.return_value.clone(),
+        error:        f.error.clone(),
+    };
+    let entrypoints = match schema {
+        VersionedModuleSchema::V0(module) => module
+            .contracts
+            .get(contract_name)?
+            .receive
+            .iter()
+            .map(|(name, ty)| EntrypointSchema {
+                name:         name.clone(),
+                parameter:    Some(ty.clone()),
+                return_value: None,
+                error:        None,
+            })
+            .collect(),
+        VersionedModuleSchema::V1(module) => module
+            .contracts
+            .get(contract_name)?
+            .receive
+            .iter()
+            .map(|(n, f)| from_v1(n, f))
+            .collect(),
+        VersionedModuleSchema::V2(module) => module
+            .contracts
+            .get(contract_name)?
+            .receive
+            .iter()
+            .map(|(n, f)| from_v2(n, f))
+            .collect(),
+        VersionedModuleSchema::V3(module) => module
+            .contracts
+            .get(contract_name)?
+            .receive
+            .iter()
+            .map(|(n, f)| from_v2(n, f))
+            .collect(),
+    };
+    Some(entrypoints)
+}
+
+/// Accumulates the generated type definitions.
+struct Emitter {
+    /// The path of the `contracts_common` crate in the generated code.
+    cc:    String,
+    items: String,
+    names: BTreeSet<String>,
+}
+
+impl Emitter {
+    /// Get the Rust type of a parameter, return value or error of an
+    /// entrypoint. Types that do not directly correspond to a Rust type
+    /// implementing `Serial` and `Deserial` according to the schema are
+    /// generated as items named after the hint.
+    fn top_level(&mut self, ty: &Type, hint: &str, item: &str) -> Result<String, CodegenError> {
+        match ty {
+            Type::Struct(_) | Type::Enum(_) | Type::TaggedEnum(_) => self.rust_type(ty, hint, item),
+            ty if self.is_builtin(ty) => self.rust_type(ty, hint, item),
+            ty => self.rust_type(&Type::Struct(Fields::Unnamed(vec![ty.clone()])), hint, item),
+        }
+    }
+
+    /// Whether the Rust type of the schema type is serialized according to
+    /// the schema by its `Serial` and `Deserial` implementations.
+    fn is_builtin(&self, ty: &Type) -> bool {
+        matches!(
+            ty,
+            Type::Unit
+                | Type::Bool
+                | Type::U8
+                | Type::U16
+                | Type::U32
+                | Type::U64
+                | Type::U128
+                | Type::I8
+                | Type::I16
+                | Type::I32
+                | Type::I64
+                | Type::I128
+                | Type::Amount
+                | Type::AccountAddress
+                | Type::ContractAddress
+                | Type::Timestamp
+                | Type::Duration
+        )
+    }
+
+    /// Get the Rust type of the schema type, generating items for structs and
+    /// enums.
+    fn rust_type(&mut self, ty: &Type, hint: &str, item: &str) -> Result<String, CodegenError> {
+        let cc = self.cc.clone();
+        let rust_type = match ty {
+            Type::Unit => "()".into(),
+            Type::Bool => "bool".into(),
+            Type::U8 => "u8".into(),
+            Type::U16 => "u16".into(),
+            Type::U32 => "u32".into(),
+            Type::U64 => "u64".into(),
+            Type::U128 => "u128".into(),
+            Type::I8 => "i8".into(),
+            Type::I16 => "i16".into(),
+            Type::I32 => "i32".into(),
+            Type::I64 => "i64".into(),
+            Type::I128 => "i128".into(),
+            Type::Amount => format!("{cc}::Amount"),
+            Type::AccountAddress => format!("{cc}::AccountAddress"),
+            Type::ContractAddress => format!("{cc}::ContractAddress"),
+            Type::Timestamp => format!("{cc}::Timestamp"),
+            Type::Duration => format!("{cc}::Duration"),
+            Type::Pair(a, b) => format!(
+                "({}, {})",
+                self.rust_type(a, &format!("{hint}First"), item)?,
+                self.rust_type(b, &format!("{hint}Second"), item)?
+            ),
+            Type::List(_, ty) => {
+                format!("Vec<{}>", self.rust_type(ty, &format!("{hint}Item"), item)?)
+            }
+            Type::Set(_, ty) => format!(
+                "std::collections::BTreeSet<{}>",
+                self.rust_type(ty, &format!("{hint}Item"), item)?
+            ),
+            Type::Map(_, k, v) => format!(
+                "std::collections::BTreeMap<{}, {}>",
+                self.rust_type(k, &format!("{hint}Key"), item)?,
+                self.rust_type(v, &format!("{hint}Value"), item)?
+            ),
+            Type::Array(n, ty) => {
+                format!(
+                    "[{}; {n}]",
+                    self.rust_type(ty, &format!("{hint}Item"), item)?
+                )
+            }
+            Type::String(_) | Type::ContractName(_) | Type::ReceiveName(_) => "String".into(),
+            Type::ByteList(_) => "Vec<u8>".into(),
+            Type::ByteArray(n) => format!("[u8; {n}]"),
+            Type::Struct(fields) => self.emit_struct(fields, hint, item)?,
+            Type::Enum(variants) => {
+                let variants: Vec<_> = variants
+                    .iter()
+                    .enumerate()
+                    .map(|(tag, (name, fields))| (tag as u32, name, fields))
+                    .collect();
+                let tag_type = if variants.len() <= 256 { "u8" } else { "u16" };
+                self.emit_enum(&variants, tag_type, hint, item)?
+            }
+            Type::TaggedEnum(variants) => {
+                let variants: Vec<_> = variants
+                    .iter()
+                    .map(|(tag, (name, fields))| (u32::from(*tag), name, fields))
+                    .collect();
+                self.emit_enum(&variants, "u8", hint, item)?
+            }
+            Type::ULeb128(_) | Type::ILeb128(_) => {
+                return Err(CodegenError::Unsupported {
+                    item: item.into(),
+                    ty:   "a LEB128 integer",
+                })
+            }
+        };
+        Ok(rust_type)
+    }
+
+    /// Reserve a unique name for a generated item.
+    fn fresh_name(&mut self, hint: &str) -> String {
+        let mut name = hint.to_string();
+        let mut i = 1;
+        while !self.names.insert(name.clone()) {
+            i += 1;
+            name = format!("{hint}{i}");
+        }
+        name
+    }
+
+    /// Generate the field declarations, the pattern binding the fields to
+    /// `field0`, `field1`, ..., and the field types.
+    fn fields<'a>(
+        &mut self,
+        fields: &'a Fields,
+        hint: &str,
+        item: &str,
+    ) -> Result<(String, String, Vec<(String, &'a Type)>), CodegenError> {
+        let mut bound = Vec::new();
+        let (decl, pattern) = match fields {
+            Fields::Named(fields) => {
+                let mut decl = Vec::new();
+                let mut pattern = Vec::new();
+                let mut used = BTreeSet::new();
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    let mut ident = snake_case(name);
+                    if !used.insert(ident.clone()) {
+                        ident = format!("{ident}_{i}");
+                    }
+                    let rust_type =
+                        self.rust_type(ty, &format!("{hint}{}", camel_case(name)), item)?;
+                    decl.push(format!("    pub {ident}: {rust_type},\n"));
+                    pattern.push(format!("{ident}: field{i}"));
+                    bound.push((format!("field{i}"), ty));
+                }
+                (
+                    format!(" {{\n{}}}", decl.concat()),
+                    format!(" {{ {} }}", pattern.join(", ")),
+                )
+            }
+            Fields::Unnamed(fields) => {
+                let mut decl = Vec::new();
+                let mut pattern = Vec::new();
+                for (i, ty) in fields.iter().enumerate() {
+                    decl.push(format!(
+                        "pub {}",
+                        self.rust_type(ty, &format!("{hint}{i}"), item)?
+                    ));
+                    pattern.push(format!("field{i}"));
+                    bound.push((format!("field{i}"), ty));
+                }
+                (
+                    format!("({})", decl.join(", ")),
+                    format!("({})", pattern.join(", ")),
+                )
+            }
+            Fields::None => (String::new(), String::new()),
+        };
+        Ok((decl, pattern, bound))
+    }
+
+    /// Generate the expression constructing the fields from deserialized
+    /// values.
+    fn fields_deserial(&self, fields: &Fields) -> String {
+        match fields {
+            Fields::Named(fields) => {
+                let mut used = BTreeSet::new();
+                let inits: Vec<_> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (name, ty))| {
+                        let mut ident = snake_case(name);
+                        if !used.insert(ident.clone()) {
+                            ident = format!("{ident}_{i}");
+                        }
+                        format!("{ident}: {}", self.deserial(ty, 0))
+                    })
+                    .collect();
+                format!(" {{ {} }}", inits.join(", "))
+            }
+            Fields::Unnamed(fields) => {
+                let inits: Vec<_> = fields.iter().map(|ty| self.deserial(ty, 0)).collect();
+                format!("({})", inits.join(", "))
+            }
+            Fields::None => String::new(),
+        }
+    }
+
+    fn emit_struct(
+        &mut self,
+        fields: &Fields,
+        hint: &str,
+        item: &str,
+    ) -> Result<String, CodegenError> {
+        let name = self.fresh_name(hint);
+        let (decl, pattern, bound) = self.fields(fields, &name, item)?;
+        let deserial = self.fields_deserial(fields);
+        let cc = &self.cc;
+        let terminator = if matches!(fields, Fields::Named(_)) {
+            ""
+        } else {
+            ";"
+        };
+        let serial: String = bound.iter().map(|(v, ty)| self.serial(ty, v, 0)).collect();
+        let _ = write!(
+            self.items,
+            "
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct {name}{decl}{terminator}
+
+impl {cc}::Serial for {name} {{
+    fn serial<W: {cc}::Write>(&self, out: &mut W) -> Result<(), W::Err> {{
+        let Self{pattern} = self;
+        {serial}Ok(())
+    }}
+}}
+
+impl {cc}::Deserial for {name} {{
+    fn deserial<R: {cc}::Read>(source: &mut R) -> {cc}::ParseResult<Self> {{
+        Ok(Self{deserial})
+    }}
+}}
+"
+        );
+        Ok(name)
+    }
+
+    fn emit_enum(
+        &mut self,
+        variants: &[(u32, &String, &Fields)],
+        tag_type: &str,
+        hint: &str,
+        item: &str,
+    ) -> Result<String, CodegenError> {
+        let name = self.fresh_name(hint);
+        let mut decl = String::new();
+        let mut serial_arms = String::new();
+        let mut deserial_arms = String::new();
+        let mut used = BTreeSet::new();
+        for (tag, variant, fields) in variants {
+            let mut ident = camel_case(variant);
+            if !used.insert(ident.clone()) {
+                ident = format!("{ident}{tag}");
+            }
+            let (fields_decl, pattern, bound) =
+                self.fields(fields, &format!("{name}{ident}"), item)?;
+            let cc = &self.cc;
+            let fields_decl = fields_decl
+                .replace("    pub ", "        ")
+                .replace("pub ", "");
+            let fields_decl = fields_decl.replace("\n}", "\n    }");
+            let _ = writeln!(decl, "    {ident}{fields_decl},");
+            let serial: String = bound.iter().map(|(v, ty)| self.serial(ty, v, 0)).collect();
+            let _ = writeln!(
+                serial_arms,
+                "            Self::{ident}{pattern} => {{
+                {cc}::Serial::serial(&{tag}{tag_type}, out)?;
+                {serial}}}"
+            );
+            let _ = writeln!(
+                deserial_arms,
+                "            {tag} => Ok(Self::{ident}{}),",
+                self.fields_deserial(fields)
+            );
+        }
+        let cc = &self.cc;
+        let _ = write!(
+            self.items,
+            "
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum {name} {{
+{decl}}}
+
+impl {cc}::Serial for {name} {{
+    fn serial<W: {cc}::Write>(&self, out: &mut W) -> Result<(), W::Err> {{
+        match self {{
+{serial_arms}        }}
+        Ok(())
+    }}
+}}
+
+impl {cc}::Deserial for {name} {{
+    fn deserial<R: {cc}::Read>(source: &mut R) -> {cc}::ParseResult<Self> {{
+        match <{tag_type} as {cc}::Deserial>::deserial(source)? {{
+{deserial_arms}            _ => Err({cc}::ParseError::default()),
+        }}
+    }}
+}}
+"
+        );
+        Ok(name)
+    }
+
+    /// Generate statements serializing the value, a reference to a value of
+    /// the Rust type of `ty`, to `out`. Generated items, and builtin types
+    /// are serialized using their `Serial` implementation.
+    fn serial(&self, ty: &Type, value: &str, depth: usize) -> String {
+        let cc = &self.cc;
+        let d = depth;
+        match ty {
+            Type::Pair(a, b) => format!(
+                "{{ let (fst{d}, snd{d}) = {value}; {}{}}}\n",
+                self.serial(a, &format!("fst{d}"), d + 1),
+                self.serial(b, &format!("snd{d}"), d + 1)
+            ),
+            Type::List(len, ty) | Type::Set(len, ty) => format!(
+                "{{ {}for elem{d} in {value}.iter() {{ {}}} }}\n",
+                self.serial_length(len, &format!("{value}.len()")),
+                self.serial(ty, &format!("elem{d}"), d + 1)
+            ),
+            Type::Map(len, k, v) => format!(
+                "{{ {}for (key{d}, value{d}) in {value}.iter() {{ {}{}}} }}\n",
+                self.serial_length(len, &format!("{value}.len()")),
+                self.serial(k, &format!("key{d}"), d + 1),
+                self.serial(v, &format!("value{d}"), d + 1)
+            ),
+            Type::Array(_, ty) => format!(
+                "for elem{d} in {value}.iter() {{ {}}}\n",
+                self.serial(ty, &format!("elem{d}"), d + 1)
+            ),
+            Type::String(len) | Type::ContractName(len) | Type::ReceiveName(len) => format!(
+                "{}{cc}::Write::write_all(out, {value}.as_bytes())?;\n",
+                self.serial_length(len, &format!("{value}.len()"))
+            ),
+            Type::ByteList(len) => format!(
+                "{}{cc}::Write::write_all(out, &{value}[..])?;\n",
+                self.serial_length(len, &format!("{value}.len()"))
+            ),
+            Type::ByteArray(_) => format!("{cc}::Write::write_all(out, &{value}[..])?;\n"),
+            _ => format!("{cc}::Serial::serial({value}, out)?;\n"),
+        }
+    }
+
+    /// Generate a statement serializing a length prefix.
+    fn serial_length(&self, len: &SizeLength, value: &str) -> String {
+        let cc = &self.cc;
+        let ty = length_type(len);
+        format!(
+            "{cc}::Serial::serial(&<{ty}>::try_from({value}).map_err(|_| W::Err::default())?, \
+             out)?; "
+        )
+    }
+
+    /// Generate an expression deserializing a value of the Rust type of `ty`
+    /// from `source`.
+    fn deserial(&self, ty: &Type, depth: usize) -> String {
+        let cc = &self.cc;
+        let d = depth;
+        let bytes = |len: String| {
+            format!(
+                "let mut bytes{d} = vec![0u8; {len}]; {cc}::Read::read_exact(source, &mut \
+                 bytes{d})?;"
+            )
+        };
+        match ty {
+            Type::Pair(a, b) => {
+                format!("({}, {})", self.deserial(a, d + 1), self.deserial(b, d + 1))
+            }
+            Type::List(len, ty) => format!(
+                "{{ let len{d} = {}; let mut elems{d} = Vec::with_capacity(len{d}.min(4096)); for \
+                 _ in 0..len{d} {{ elems{d}.push({}); }} elems{d} }}",
+                self.deserial_length(len),
+                self.deserial(ty, d + 1)
+            ),
+            Type::Set(len, ty) => format!(
+                "{{ let len{d} = {}; let mut elems{d} = std::collections::BTreeSet::new(); for _ \
+                 in 0..len{d} {{ elems{d}.insert({}); }} elems{d} }}",
+                self.deserial_length(len),
+                self.deserial(ty, d + 1)
+            ),
+            Type::Map(len, k, v) => format!(
+                "{{ let len{d} = {}; let mut elems{d} = std::collections::BTreeMap::new(); for _ \
+                 in 0..len{d} {{ let key{d} = {}; elems{d}.insert(key{d}, {}); }} elems{d} }}",
+                self.deserial_length(len),
+                self.deserial(k, d + 1),
+                self.deserial(v, d + 1)
+            ),
+            Type::Array(n, ty) => format!(
+                "{{ let mut elems{d} = Vec::with_capacity({n}); for _ in 0..{n} {{ \
+                 elems{d}.push({}); }} elems{d}.try_into().map_err(|_| \
+                 {cc}::ParseError::default())? }}",
+                self.deserial(ty, d + 1)
+            ),
+            Type::String(len) | Type::ContractName(len) | Type::ReceiveName(len) => format!(
+                "{{ {} String::from_utf8(bytes{d}).map_err(|_| {cc}::ParseError::default())? }}",
+                bytes(self.deserial_length(len))
+            ),
+            Type::ByteList(len) => {
+                format!("{{ {} bytes{d} }}", bytes(self.deserial_length(len)))
+            }
+            Type::ByteArray(n) => format!(
+                "{{ let mut bytes{d} = [0u8; {n}]; {cc}::Read::read_exact(source, &mut \
+                 bytes{d})?; bytes{d} }}"
+            ),
+            _ => format!("{cc}::Deserial::deserial(source)?"),
+        }
+    }
+
+    /// Generate an expression deserializing a length prefix as a `usize`.
+    fn deserial_length(&self, len: &SizeLength) -> String {
+        let cc = &self.cc;
+        let ty = length_type(len);
+        format!(
+            "usize::try_from(<{ty} as {cc}::Deserial>::deserial(source)?).map_err(|_| \
+             {cc}::ParseError::default())?"
+        )
+    }
+}
+
+fn length_type(len: &SizeLength) -> &'static str {
+    match len {
+        SizeLength::U8 => "u8",
+        SizeLength::U16 => "u16",
+        SizeLength::U32 => "u32",
+        SizeLength::U64 => "u64",
+    }
+}
+
+/// Split a name into words at non-alphanumeric characters and lower-to-upper
+/// case transitions.
+fn words(name: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut prev_lower = false;
+    for c in name.chars() {
+        if !c.is_ascii_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            prev_lower = false;
+            continue;
+        }
+        if c.is_ascii_uppercase() && prev_lower && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        current.push(c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+/// Convert a name from the schema to an identifier in camel case, e.g.,
+/// `balance_of` to `BalanceOf`.
+fn camel_case(name: &str) -> String {
+    let ident: String = words(name)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => {
+                    first.to_ascii_uppercase().to_string() + &chars.as_str().to_ascii_lowercase()
+                }
+                None => String::new(),
+            }
+        })
+        .collect();
+    match ident.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => ident,
+        _ => format!("T{ident}"),
+    }
+}
+
+/// Choose the name of the methods generated for an entrypoint whose name in
+/// snake case is `name`, such that none of the methods use a name that is
+/// already used. The chosen names are marked as used.
+fn method_name(name: &str, used: &mut BTreeSet<String>) -> String {
+    let mut method = name.to_string();
+    let mut i = 1;
+    loop {
+        let names = [
+            method.clone(),
+            format!("{method}_dry_run"),
+            format!("{method}_update"),
+        ];
+        if names.iter().all(|n| !used.contains(n)) {
+            used.extend(names);
+            return method;
+        }
+        method = if i == 1 {
+            format!("{name}_entrypoint")
+        } else {
+            format!("{name}_entrypoint{i}")
+        };
+        i += 1;
+    }
+}
+
+/// Convert a name from the schema to an identifier in snake case, e.g.,
+/// `balanceOf` to `balance_of`.
+fn snake_case(name: &str) -> String {
+    let ident = words(name)
+        .iter()
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    match ident.chars().next() {
+        // Keywords are suffixed rather than made raw identifiers, since the
+        // identifier is also used as a prefix of method names.
+        Some(c) if c.is_ascii_alphabetic() && is_keyword(&ident) => format!("{ident}_"),
+        Some(c) if c.is_ascii_alphabetic() => ident,
+        _ => format!("_{ident}"),
+    }
+}
+
+fn is_keyword(ident: &str) -> bool {
+    matches!(
+        ident,
+        "as" | "async"
+            | "await"
+            | "break"
+            | "const"
+            | "continue"
+            | "crate"
+            | "dyn"
+            | "else"
+            | "enum"
+            | "extern"
+            | "false"
+            | "fn"
+            | "for"
+            | "if"
+            | "impl"
+            | "in"
+            | "let"
+            | "loop"
+            | "match"
+            | "mod"
+            | "move"
+            | "mut"
+            | "pub"
+            | "ref"
+            | "return"
+            | "self"
+            | "static"
+            | "struct"
+            | "super"
+            | "trait"
+            | "true"
+            | "type"
+            | "unsafe"
+            | "use"
+            | "where"
+            | "while"
+            | "yield"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::contracts_common::schema::{ContractV3, FunctionV2, ModuleV3};
+    use std::collections::BTreeMap;
+
+    /// The bindings generated from [`view_test_schema`]. They are included here
+    /// so that building the tests checks that the generated code compiles.
+    #[allow(dead_code)]
+    mod generated {
+        include!("codegen/view_test.rs");
+    }
+
+    /// A schema with entrypoints named like methods of `ContractClient`.
+    fn view_test_schema() -> VersionedModuleSchema {
+        let mut receive = BTreeMap::new();
+        receive.insert("view".to_string(), FunctionV2 {
+            parameter:    Some(Type::U32),
+            error:        None,
+            return_value: Some(Type::Struct(Fields::Named(vec![
+                ("owner".into(), Type::AccountAddress),
+                ("count".into(), Type::U64),
+            ]))),
+        });
+        receive.insert("update".to_string(), FunctionV2 {
+            parameter:    Some(Type::List(SizeLength::U16, Box::new(Type::U8))),
+            error:        None,
+            return_value: None,
+        });
+        let mut contracts = BTreeMap::new();
+        contracts.insert("view_test".to_string(), ContractV3 {
+            init: None,
+            receive,
+            event: None,
+        });
+        VersionedModuleSchema::V3(ModuleV3 { contracts })
+    }
+
+    #[test]
+    fn generated_bindings_match_fixture() {
+        let generated = BindingsGenerator::new(view_test_schema(), "view_test")
+            .set_sdk_path("crate")
+            .generate()
+            .expect("Bindings can be generated.");
+        assert_eq!(generated, include_str!("codegen/view_test.rs"));
+    }
+
+    #[test]
+    fn method_names_avoid_used_names() {
+        let mut used = RESERVED_METHODS
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        assert_eq!(method_name("transfer", &mut used), "transfer");
+        assert_eq!(method_name("view", &mut used), "view_entrypoint");
+        assert_eq!(method_name("dry_run", &mut used), "dry_run_entrypoint");
+        assert_eq!(
+            method_name("transfer_dry_run", &mut used),
+            "transfer_dry_run_entrypoint"
+        );
+        assert_eq!(
+            method_name("view_entrypoint", &mut used),
+            "view_entrypoint_entrypoint"
+        );
+        assert_eq!(method_name("view", &mut used), "view_entrypoint2");
+    }
+
+    #[test]
+    fn identifier_case_conversion() {
+        assert_eq!(snake_case("balanceOf"), "balance_of");
+        assert_eq!(snake_case("type"), "type_");
+        assert_eq!(snake_case("1st"), "_1st");
+        assert_eq!(camel_case("balance_of"), "BalanceOf");
+        assert_eq!(camel_case("1st"), "T1st");
+    }
+}
@@ -0,0 +1,138 @@
+// Generated from the schema of the contract `view_test`. Do not edit.
+
+/// A marker type to indicate that a [`ContractClient`](crate::contract_client::ContractClient) is a client for the `view_test` contract.
+#[derive(Debug, Clone, Copy)]
+pub enum ViewTestType {}
+
+/// A client for the `view_test` contract. It dereferences to the wrapped [`ContractClient`](crate::contract_client::ContractClient), so that its generic methods are also available.
+#[derive(Debug, Clone)]
+pub struct ViewTestContract {
+    /// The wrapped client.
+    pub inner: crate::contract_client::ContractClient<ViewTestType>,
+}
+
+impl From<crate::contract_client::ContractClient<ViewTestType>> for ViewTestContract {
+    fn from(inner: crate::contract_client::ContractClient<ViewTestType>) -> Self { Self { inner } }
+}
+
+impl std::ops::Deref for ViewTestContract {
+    type Target = crate::contract_client::ContractClient<ViewTestType>;
+
+    fn deref(&self) -> &Self::Target { &self.inner }
+}
+
+impl std::ops::DerefMut for ViewTestContract {
+    fn deref_mut(&mut self) -> &mut Self::Target { &mut self.inner }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UpdateParameter(pub Vec<u8>);
+
+impl crate::base::contracts_common::Serial for UpdateParameter {
+    fn serial<W: crate::base::contracts_common::Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        let Self(field0) = self;
+        { crate::base::contracts_common::Serial::serial(&<u16>::try_from(field0.len()).map_err(|_| W::Err::default())?, out)?; for elem0 in field0.iter() { crate::base::contracts_common::Serial::serial(elem0, out)?;
+} }
+Ok(())
+    }
+}
+
+impl crate::base::contracts_common::Deserial for UpdateParameter {
+    fn deserial<R: crate::base::contracts_common::Read>(source: &mut R) -> crate::base::contracts_common::ParseResult<Self> {
+        Ok(Self({ let len0 = usize::try_from(<u16 as crate::base::contracts_common::Deserial>::deserial(source)?).map_err(|_| crate::base::contracts_common::ParseError::default())?; let mut elems0 = Vec::with_capacity(len0.min(4096)); for _ in 0..len0 { elems0.push(crate::base::contracts_common::Deserial::deserial(source)?); } elems0 }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ViewReturnValue {
+    pub owner: crate::base::contracts_common::AccountAddress,
+    pub count: u64,
+}
+
+impl crate::base::contracts_common::Serial for ViewReturnValue {
+    fn serial<W: crate::base::contracts_common::Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        let Self { owner: field0, count: field1 } = self;
+        crate::base::contracts_common::Serial::serial(field0, out)?;
+crate::base::contracts_common::Serial::serial(field1, out)?;
+Ok(())
+    }
+}
+
+impl crate::base::contracts_common::Deserial for ViewReturnValue {
+    fn deserial<R: crate::base::contracts_common::Read>(source: &mut R) -> crate::base::contracts_common::ParseResult<Self> {
+        Ok(Self { owner: crate::base::contracts_common::Deserial::deserial(source)?, count: crate::base::contracts_common::Deserial::deserial(source)? })
+    }
+}
+
+impl ViewTestContract {
+    /// Construct a client for the instance at the given address, using the schema embedded in the module of the instance.
+    pub async fn create(
+        client: crate::v2::Client,
+        address: crate::base::contracts_common::ContractAddress,
+    ) -> crate::v2::QueryResult<Self> {
+        crate::contract_client::ContractClient::create(client, address).await.map(Self::from)
+    }
+
+    /// Dry-run an update of the `update` entrypoint. If it succeeds, the result can be used to send the transaction.
+    pub async fn update_entrypoint_dry_run(
+        &mut self,
+        sender: crate::base::contracts_common::AccountAddress,
+        amount: crate::base::contracts_common::Amount,
+        parameter: &UpdateParameter,
+    ) -> Result<
+        crate::contract_client::ContractUpdateBuilder,
+        crate::contract_client::ViewError,
+    > {
+        self.inner.dry_run_update("update", amount, sender, parameter).await
+    }
+
+    /// Send a transaction updating the `update` entrypoint.
+    pub async fn update_entrypoint_update<E>(
+        &mut self,
+        signer: &impl crate::types::transactions::ExactSizeTransactionSigner,
+        metadata: &crate::contract_client::ContractTransactionMetadata,
+        parameter: &UpdateParameter,
+    ) -> Result<crate::types::hashes::TransactionHash, E>
+    where
+        E: From<crate::base::contracts_common::NewReceiveNameError>
+            + From<crate::v2::RPCError>
+            + From<crate::base::smart_contracts::ExceedsParameterSize>, {
+        self.inner.update::<_, E>(signer, metadata, "update", parameter).await
+    }
+
+    /// Invoke the `view` entrypoint without creating a transaction, and return its response.
+    pub async fn view_entrypoint(
+        &mut self,
+        parameter: &u32,
+        bi: impl crate::v2::IntoBlockIdentifier,
+    ) -> Result<ViewReturnValue, crate::contract_client::ViewError> {
+        self.inner.view("view", parameter, bi).await
+    }
+
+    /// Dry-run an update of the `view` entrypoint. If it succeeds, the result can be used to send the transaction.
+    pub async fn view_entrypoint_dry_run(
+        &mut self,
+        sender: crate::base::contracts_common::AccountAddress,
+        amount: crate::base::contracts_common::Amount,
+        parameter: &u32,
+    ) -> Result<
+        crate::contract_client::ContractUpdateBuilder,
+        crate::contract_client::ViewError,
+    > {
+        self.inner.dry_run_update("view", amount, sender, parameter).await
+    }
+
+    /// Send a transaction updating the `view` entrypoint.
+    pub async fn view_entrypoint_update<E>(
+        &mut self,
+        signer: &impl crate::types::transactions::ExactSizeTransactionSigner,
+        metadata: &crate::contract_client::ContractTransactionMetadata,
+        parameter: &u32,
+    ) -> Result<crate::types::hashes::TransactionHash, E>
+    where
+        E: From<crate::base::contracts_common::NewReceiveNameError>
+            + From<crate::v2::RPCError>
+            + From<crate::base::smart_contracts::ExceedsParameterSize>, {
+        self.inner.update::<_, E>(signer, metadata, "view", parameter).await
+    }
+}
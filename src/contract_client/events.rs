@@ -0,0 +1,379 @@
+//! Decoding of the events and return values of contract updates.
+//!
+//! [`ContractUpdateHandle::wait_for_finalization_decoded`] waits for an update
+//! to be finalized, and decodes the events logged by each instance involved in
+//! the update using the schema embedded in the module of the instance, as well
+//! as the return value of the update. For contracts without schemas, or for
+//! decoding into Rust types, [`ContractUpdateInfo::events_as`] deserializes the
+//! events of an instance into a type implementing
+//! [`Deserial`](contracts_common::Deserial).
+//!
+//! [`ContractClient::subscribe_events`] follows the chain from a given height
+//! and produces a stream of the events logged by an instance.
+use super::{get_embedded_schema, ContractClient, ContractUpdateError, ContractUpdateHandle};
+use crate::{
+    cis2, cis4,
+    indexer::{ContractUpdateInfo, DecodedValue},
+    types::{
+        execution_tree, smart_contracts::ReturnValue, AccountTransactionEffects,
+        BlockItemSummaryDetails,
+    },
+    v2::{self, FinalizedBlockInfo, FinalizedBlocksStream, QueryResult},
+};
+use concordium_base::{
+    base::AbsoluteBlockHeight,
+    contracts_common::{
+        self,
+        schema::{self, VersionedModuleSchema},
+        ContractAddress, OwnedContractName, OwnedEntrypointName,
+    },
+    hashes::{BlockHash, TransactionHash},
+    smart_contracts::ModuleReference,
+};
+use futures::{Stream, TryStreamExt};
+use std::collections::{BTreeMap, VecDeque};
+
+/// An event logged during a contract update.
+#[derive(Debug, Clone)]
+pub struct DecodedContractEvent {
+    /// The address of the instance that logged the event.
+    pub address:    ContractAddress,
+    /// The entrypoint the instance was executing when it logged the event.
+    pub entrypoint: OwnedEntrypointName,
+    /// The event, decoded using the event type in the schema of the
+    /// instance if available.
+    pub event:      DecodedValue,
+}
+
+/// The result of a contract update with decoded events and return value, as
+/// returned by
+/// [`wait_for_finalization_decoded`](ContractUpdateHandle::wait_for_finalization_decoded).
+pub struct DecodedContractUpdateInfo {
+    /// The result of the update.
+    pub info:         ContractUpdateInfo,
+    /// The events logged by all instances involved in the update, in the order
+    /// they were logged.
+    pub events:       Vec<DecodedContractEvent>,
+    /// The return value of the top-level call, decoded using the return value
+    /// type of the entrypoint if available. Since return values are not part
+    /// of the outcomes of finalized transactions, this is the return value
+    /// from dry-running the update, and is [`None`] if the update was not
+    /// sent from a [`ContractUpdateBuilder`](super::ContractUpdateBuilder).
+    pub return_value: Option<DecodedValue>,
+}
+
+impl DecodedContractUpdateInfo {
+    /// Deserialize the return value of the top-level call.
+    pub fn return_value_as<A: contracts_common::Deserial>(
+        &self,
+    ) -> Option<Result<A, contracts_common::ParseError>> {
+        self.return_value
+            .as_ref()
+            .map(|rv| contracts_common::from_bytes(&rv.raw))
+    }
+}
+
+impl ContractUpdateInfo {
+    /// Deserialize the events logged by the instance at the given address, in
+    /// the order they were logged, together with the entrypoint the instance
+    /// was executing when logging them. This fails if any of the events cannot
+    /// be deserialized.
+    pub fn events_as<A: contracts_common::Deserial>(
+        &self,
+        address: ContractAddress,
+    ) -> Result<Vec<(OwnedEntrypointName, A)>, contracts_common::ParseError> {
+        let mut out = Vec::new();
+        for (event_address, entrypoint, events) in self.execution_tree.events() {
+            if event_address != address {
+                continue;
+            }
+            for event in events {
+                out.push((
+                    entrypoint.to_owned(),
+                    contracts_common::from_bytes(event.as_ref())?,
+                ));
+            }
+        }
+        Ok(out)
+    }
+}
+
+impl ContractUpdateHandle {
+    /// Like [`wait_for_finalization`](Self::wait_for_finalization), but
+    /// additionally decodes the events and the return value using the schemas
+    /// embedded in the modules of the instances, as they are in the block in
+    /// which the update is finalized. Note that the events of an instance that
+    /// is upgraded by the update are decoded using the schema of the new
+    /// module.
+    pub async fn wait_for_finalization_decoded(
+        self,
+    ) -> Result<DecodedContractUpdateInfo, ContractUpdateError> {
+        let mut client = self.client.clone();
+        let tx_hash = self.tx_hash;
+        let return_value = self.return_value.clone();
+        let info = self.wait_for_finalization().await?;
+        // The transaction is finalized, so this returns immediately.
+        let (block, _) = client.wait_until_finalized(&tx_hash).await?;
+
+        let mut schemas = SchemaCache::new(block);
+        let mut events = Vec::new();
+        for (address, entrypoint, logged) in info.execution_tree.events() {
+            let schema = schemas.get(&mut client, address).await?;
+            let ty = schema.and_then(|(name, schema)| {
+                schema
+                    .get_event_schema(name.as_contract_name().contract_name())
+                    .ok()
+            });
+            for event in logged {
+                let mut event = DecodedValue::new(event);
+                event.decode_with(ty.clone());
+                events.push(DecodedContractEvent {
+                    address,
+                    entrypoint: entrypoint.to_owned(),
+                    event,
+                });
+            }
+        }
+
+        let return_value = match return_value {
+            Some(ReturnValue { value }) => {
+                let address = info.execution_tree.address();
+                let entrypoint = info.execution_tree.entrypoint();
+                let schema = schemas.get(&mut client, address).await?;
+                let ty = schema.and_then(|(name, schema)| {
+                    schema
+                        .get_receive_return_value_schema(
+                            name.as_contract_name().contract_name(),
+                            entrypoint.into(),
+                        )
+                        .ok()
+                });
+                let mut decoded = DecodedValue::new(value);
+                decoded.decode_with(ty);
+                Some(decoded)
+            }
+            None => None,
+        };
+
+        Ok(DecodedContractUpdateInfo {
+            info,
+            events,
+            return_value,
+        })
+    }
+}
+
+/// A cache of the names and schemas of instances in a given block.
+pub(crate) struct SchemaCache {
+    block:     BlockHash,
+    instances: BTreeMap<ContractAddress, Option<(OwnedContractName, ModuleReference)>>,
+    modules:   BTreeMap<ModuleReference, Option<VersionedModuleSchema>>,
+}
+
+impl SchemaCache {
+    pub(crate) fn new(block: BlockHash) -> Self {
+        Self {
+            block,
+            instances: BTreeMap::new(),
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Get the contract name and schema of the instance, querying the node if
+    /// they are not cached. Returns [`None`] if the instance does not exist,
+    /// or its module has no embedded schema.
+    pub(crate) async fn get(
+        &mut self,
+        client: &mut v2::Client,
+        address: ContractAddress,
+    ) -> QueryResult<Option<(&OwnedContractName, &VersionedModuleSchema)>> {
+        if !self.instances.contains_key(&address) {
+            let instance = match client.get_instance_info(address, self.block).await {
+                Ok(info) => Some((info.response.name().clone(), info.response.source_module())),
+                Err(e) if e.is_not_found() => None,
+                Err(e) => return Err(e),
+            };
+            self.instances.insert(address, instance);
+        }
+        let Some((name, module_ref)) = &self.instances[&address] else {
+            return Ok(None);
+        };
+        if !self.modules.contains_key(module_ref) {
+            let module = client
+                .get_module_source(module_ref, self.block)
+                .await?
+                .response;
+            self.modules
+                .insert(*module_ref, get_embedded_schema(&module));
+        }
+        Ok(self.modules[module_ref]
+            .as_ref()
+            .map(|schema| (name, schema)))
+    }
+}
+
+/// An event logged by an instance, as produced by
+/// [`ContractClient::subscribe_events`].
+#[derive(Debug, Clone)]
+pub struct ContractEventRecord {
+    /// The height of the block containing the transaction.
+    pub block_height:     AbsoluteBlockHeight,
+    /// The hash of the block containing the transaction.
+    pub block_hash:       BlockHash,
+    /// The hash of the transaction that caused the event.
+    pub transaction_hash: TransactionHash,
+    /// The entrypoint the instance was executing when it logged the event, or
+    /// [`None`] if the event was logged when the instance was initialized.
+    pub entrypoint:       Option<OwnedEntrypointName>,
+    /// The event, decoded using the event type in the schema of the client if
+    /// available.
+    pub event:            DecodedValue,
+}
+
+impl ContractEventRecord {
+    /// Deserialize the event as an event of the given type.
+    pub fn event_as<A: contracts_common::Deserial>(
+        &self,
+    ) -> Result<A, contracts_common::ParseError> {
+        contracts_common::from_bytes(&self.event.raw)
+    }
+
+    /// Deserialize the event as a CIS-2 event.
+    pub fn cis2_event(&self) -> Result<cis2::Event, contracts_common::ParseError> {
+        self.event_as()
+    }
+
+    /// Deserialize the event as a CIS-4 event.
+    pub fn cis4_event(&self) -> Result<cis4::Event, contracts_common::ParseError> {
+        self.event_as()
+    }
+}
+
+impl<Type> ContractClient<Type> {
+    /// Get a stream of the events logged by the instance in contract updates,
+    /// starting from the block at the given height, in the order they were
+    /// logged. The stream follows the chain as new blocks are finalized, and
+    /// runs until it is dropped or querying the node fails, in which case the
+    /// error is the last item.
+    ///
+    /// Events are decoded using the event type in the schema of the client.
+    /// To resume a subscription after an interruption, start it from the
+    /// height of the last record, and skip the records of that block that were
+    /// already processed.
+    pub async fn subscribe_events(
+        &self,
+        from_height: AbsoluteBlockHeight,
+    ) -> QueryResult<impl Stream<Item = QueryResult<ContractEventRecord>> + Send + 'static> {
+        let mut client = self.client.clone();
+        let blocks = client.get_finalized_blocks_from(from_height).await?;
+        let event_type = (*self.schema).as_ref().and_then(|schema| {
+            schema
+                .get_event_schema(self.contract_name.as_contract_name().contract_name())
+                .ok()
+        });
+        let subscription = Subscription {
+            client,
+            blocks,
+            address: self.address,
+            event_type,
+            pending: VecDeque::new(),
+        };
+        Ok(futures::stream::try_unfold(
+            subscription,
+            |mut subscription| async move {
+                loop {
+                    if let Some(record) = subscription.pending.pop_front() {
+                        return Ok(Some((record, subscription)));
+                    }
+                    let Some(block) = subscription.blocks.next().await else {
+                        return Ok(None);
+                    };
+                    subscription.process_block(block).await?;
+                }
+            },
+        ))
+    }
+}
+
+/// The state of a stream returned by [`ContractClient::subscribe_events`].
+struct Subscription {
+    client:     v2::Client,
+    blocks:     FinalizedBlocksStream,
+    address:    ContractAddress,
+    event_type: Option<schema::Type>,
+    /// Records of the last processed block that have not been returned yet.
+    pending:    VecDeque<ContractEventRecord>,
+}
+
+impl Subscription {
+    /// Add records for the events of the instance in the block.
+    async fn process_block(&mut self, block: FinalizedBlockInfo) -> QueryResult<()> {
+        let summaries = self
+            .client
+            .get_block_transaction_events(block.block_hash)
+            .await?
+            .response;
+        let mut summaries = std::pin::pin!(summaries);
+        while let Some(summary) = summaries.try_next().await? {
+            // Skip transactions in which the instance did not log any events.
+            let initialized = summary
+                .contract_init()
+                .is_some_and(|data| data.address == self.address && !data.events.is_empty());
+            let logs_events = summary.contract_update_logs().is_some_and(|mut logs| {
+                logs.any(|(address, events)| address == self.address && !events.is_empty())
+            });
+            if !initialized && !logs_events {
+                continue;
+            }
+            let BlockItemSummaryDetails::AccountTransaction(at) = summary.details else {
+                continue;
+            };
+            match at.effects {
+                AccountTransactionEffects::ContractInitialized { data } => {
+                    for event in data.events {
+                        self.push_event(&block, summary.hash, None, event);
+                    }
+                }
+                AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                    let Some(tree) = execution_tree(effects) else {
+                        continue;
+                    };
+                    for (address, entrypoint, events) in tree.events() {
+                        if address != self.address {
+                            continue;
+                        }
+                        for event in events {
+                            self.push_event(
+                                &block,
+                                summary.hash,
+                                Some(entrypoint.to_owned()),
+                                event,
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Decode an event of the instance and add a record for it.
+    fn push_event(
+        &mut self,
+        block: &FinalizedBlockInfo,
+        transaction_hash: TransactionHash,
+        entrypoint: Option<OwnedEntrypointName>,
+        event: impl AsRef<[u8]>,
+    ) {
+        let mut decoded = DecodedValue::new(event);
+        decoded.decode_with(self.event_type.clone());
+        self.pending.push_back(ContractEventRecord {
+            block_height: block.height,
+            block_hash: block.block_hash,
+            transaction_hash,
+            entrypoint,
+            event: decoded,
+        });
+    }
+}
@@ -0,0 +1,374 @@
+//! Querying view functions of an instance over ranges of blocks.
+//!
+//! [`ContractClient::view_history`] invokes a view function at regularly
+//! spaced heights, e.g., to chart the total supply of a token, and
+//! [`ContractClient::view_changes`] uses binary search to find the exact
+//! blocks in which the value returned by a view function changed.
+use super::ContractClient;
+use crate::{
+    types::{
+        smart_contracts::{ContractContext, InvokeContractResult},
+        RejectReason,
+    },
+    v2::{self, QueryResult},
+};
+use concordium_base::{
+    base::AbsoluteBlockHeight,
+    contracts_common::{self, Amount, NewReceiveNameError},
+    hashes::BlockHash,
+    smart_contracts::{ExceedsParameterSize, OwnedParameter, OwnedReceiveName},
+};
+use futures::{Future, StreamExt, TryStreamExt};
+use std::ops::{Bound, RangeBounds, RangeInclusive};
+
+/// The maximum number of concurrent invocations made by
+/// [`ContractClient::view_history`].
+const MAX_CONCURRENT_VIEWS: usize = 16;
+
+/// The value returned by a view function in a given block.
+#[derive(Debug, Clone)]
+pub struct ViewSample<A> {
+    /// The height of the block.
+    pub height:     AbsoluteBlockHeight,
+    /// The hash of the block.
+    pub block_hash: BlockHash,
+    /// The value returned by the view function.
+    pub value:      A,
+}
+
+/// The outcome of invoking a view function in a given block, before the
+/// return value is deserialized.
+#[derive(Clone)]
+struct ViewOutcome {
+    height:     AbsoluteBlockHeight,
+    block_hash: BlockHash,
+    /// The return value, or the reason the invocation was rejected.
+    result:     Result<Vec<u8>, RejectReason>,
+}
+
+impl ViewOutcome {
+    /// Whether the invocations had the same result.
+    fn same_result(&self, other: &Self) -> bool {
+        match (&self.result, &other.result) {
+            (Ok(a), Ok(b)) => a == b,
+            // Reject reasons are compared by their JSON representation since
+            // they do not implement `PartialEq`.
+            (Err(a), Err(b)) => serde_json::to_value(a).ok() == serde_json::to_value(b).ok(),
+            _ => false,
+        }
+    }
+
+    fn decode<A: contracts_common::Deserial, E>(self) -> Result<ViewSample<A>, E>
+    where
+        E: From<RejectReason> + From<contracts_common::ParseError>, {
+        let value = contracts_common::from_bytes(&self.result?)?;
+        Ok(ViewSample {
+            height: self.height,
+            block_hash: self.block_hash,
+            value,
+        })
+    }
+}
+
+impl<Type> ContractClient<Type> {
+    /// Invoke the view function with the given parameter at the heights in
+    /// the range, starting from the lower bound and spaced by `step` blocks.
+    /// If the upper bound is not provided, or is after the last finalized
+    /// block, it defaults to the last finalized block at the time of the
+    /// call. The invocations are made concurrently, and the values are
+    /// returned in order of increasing height.
+    ///
+    /// This fails if any of the invocations fails, e.g., because the instance
+    /// does not exist in one of the blocks.
+    ///
+    /// For a general contract [`ViewError`](super::ViewError) can be used as a
+    /// concrete error type `E`.
+    pub async fn view_history<P: contracts_common::Serial, A: contracts_common::Deserial, E>(
+        &self,
+        entrypoint: &str,
+        parameter: &P,
+        range: impl RangeBounds<AbsoluteBlockHeight>,
+        step: u64,
+    ) -> Result<Vec<ViewSample<A>>, E>
+    where
+        E: From<NewReceiveNameError>
+            + From<RejectReason>
+            + From<contracts_common::ParseError>
+            + From<v2::QueryError>
+            + From<ExceedsParameterSize>, {
+        let context = self.view_context::<_, E>(entrypoint, parameter)?;
+        let Some((start, end)) = height_range(&mut self.client.clone(), range).await? else {
+            return Ok(Vec::new());
+        };
+        let step = usize::try_from(step.max(1)).unwrap_or(usize::MAX);
+        futures::stream::iter((start..=end).step_by(step))
+            .map(|height| {
+                let mut client = self.client.clone();
+                let context = context.clone();
+                async move { invoke_view(&mut client, &context, height.into()).await }
+            })
+            .buffered(MAX_CONCURRENT_VIEWS)
+            .map(|outcome| -> Result<ViewSample<A>, E> { outcome?.decode() })
+            .try_collect()
+            .await
+    }
+
+    /// Find the blocks in the range in which the value returned by the view
+    /// function with the given parameter changed. The first returned value is
+    /// the value at the lower bound of the range, followed by the value in
+    /// each block where it changed. If the upper bound is not provided, or is
+    /// after the last finalized block, it defaults to the last finalized block
+    /// at the time of the call.
+    ///
+    /// The blocks are found using binary search with
+    /// [`find_at_lowest_height`](v2::Client::find_at_lowest_height), which
+    /// makes a number of invocations logarithmic in the length of the range
+    /// for each change. This assumes that the view function does not return
+    /// a value it returned before a change, e.g., the value increases
+    /// monotonically. Changes that are reverted within the range may
+    /// otherwise be missed.
+    ///
+    /// For a general contract [`ViewError`](super::ViewError) can be used as a
+    /// concrete error type `E`.
+    pub async fn view_changes<P: contracts_common::Serial, A: contracts_common::Deserial, E>(
+        &self,
+        entrypoint: &str,
+        parameter: &P,
+        range: impl RangeBounds<AbsoluteBlockHeight>,
+    ) -> Result<Vec<ViewSample<A>>, E>
+    where
+        E: From<NewReceiveNameError>
+            + From<RejectReason>
+            + From<contracts_common::ParseError>
+            + From<v2::QueryError>
+            + From<ExceedsParameterSize>, {
+        let context = self.view_context::<_, E>(entrypoint, parameter)?;
+        let mut client = self.client.clone();
+        let Some((start, end)) = height_range(&mut client, range).await? else {
+            return Ok(Vec::new());
+        };
+        let first = invoke_view(&mut client, &context, start.into()).await?;
+        let last = invoke_view(&mut client, &context, end.into()).await?;
+        let context = &context;
+        let changes = find_changes(first, last, |range, current| {
+            let mut client = client.clone();
+            async move {
+                client
+                    .find_at_lowest_height(range, |mut client, height| {
+                        let current = current.clone();
+                        async move {
+                            let outcome = invoke_view(&mut client, context, height).await?;
+                            Ok((!outcome.same_result(&current)).then_some(outcome))
+                        }
+                    })
+                    .await
+            }
+        })
+        .await?;
+        changes.into_iter().map(ViewOutcome::decode).collect()
+    }
+
+    /// The context for invoking the view function with the given parameter.
+    fn view_context<P: contracts_common::Serial, E>(
+        &self,
+        entrypoint: &str,
+        parameter: &P,
+    ) -> Result<ContractContext, E>
+    where
+        E: From<NewReceiveNameError> + From<ExceedsParameterSize>, {
+        let contract_name = self.contract_name.as_contract_name().contract_name();
+        let method = OwnedReceiveName::try_from(format!("{contract_name}.{entrypoint}"))?;
+        Ok(ContractContext {
+            invoker: None,
+            contract: self.address,
+            amount: Amount::zero(),
+            method,
+            parameter: OwnedParameter::from_serial(parameter)?,
+            energy: None,
+        })
+    }
+}
+
+/// Invoke the view function at the given height.
+async fn invoke_view(
+    client: &mut v2::Client,
+    context: &ContractContext,
+    height: AbsoluteBlockHeight,
+) -> QueryResult<ViewOutcome> {
+    let response = client.invoke_instance(&height, context).await?;
+    let result = match response.response {
+        InvokeContractResult::Success { return_value, .. } => {
+            Ok(return_value.map(|rv| rv.value).unwrap_or_default())
+        }
+        InvokeContractResult::Failure { reason, .. } => Err(reason),
+    };
+    Ok(ViewOutcome {
+        height,
+        block_hash: response.block_hash,
+        result,
+    })
+}
+
+/// Find the outcomes at the start of the search and in each block where the
+/// result changed, given the outcomes at the first and last height. The `find`
+/// function returns the outcome at the lowest height in the given range whose
+/// result differs from the given outcome. Like
+/// [`find_at_lowest_height`](v2::Client::find_at_lowest_height) it fails with a
+/// not found error if there is no such height below the upper bound.
+async fn find_changes<F, Fut>(
+    first: ViewOutcome,
+    last: ViewOutcome,
+    mut find: F,
+) -> QueryResult<Vec<ViewOutcome>>
+where
+    F: FnMut(RangeInclusive<AbsoluteBlockHeight>, ViewOutcome) -> Fut,
+    Fut: Future<Output = QueryResult<ViewOutcome>>, {
+    let mut current = first;
+    let mut changes = Vec::new();
+    while current.height < last.height && !current.same_result(&last) {
+        let from = AbsoluteBlockHeight::from(u64::from(current.height) + 1);
+        let next = match find(from..=last.height, current.clone()).await {
+            Ok(outcome) => outcome,
+            // The search does not test the upper bound of the range, which is
+            // where the value changed if no earlier block was found.
+            Err(e) if e.is_not_found() => last.clone(),
+            Err(e) => return Err(e),
+        };
+        changes.push(std::mem::replace(&mut current, next));
+    }
+    changes.push(current);
+    Ok(changes)
+}
+
+/// The first and last height of the range, bounded by the last finalized
+/// block. Returns [`None`] if the range is empty.
+async fn height_range(
+    client: &mut v2::Client,
+    range: impl RangeBounds<AbsoluteBlockHeight>,
+) -> QueryResult<Option<(u64, u64)>> {
+    let last_final = client
+        .get_consensus_info()
+        .await?
+        .last_finalized_block_height;
+    Ok(bounded_range(range, last_final.into()))
+}
+
+/// The first and last height of the range, bounded by the given height.
+/// Returns [`None`] if the range is empty.
+fn bounded_range(range: impl RangeBounds<AbsoluteBlockHeight>, max: u64) -> Option<(u64, u64)> {
+    let start = match range.start_bound() {
+        Bound::Included(s) => u64::from(*s),
+        Bound::Excluded(s) => u64::from(*s).saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(e) => u64::from(*e).min(max),
+        Bound::Excluded(e) => u64::from(*e).checked_sub(1)?.min(max),
+        Bound::Unbounded => max,
+    };
+    (start <= end).then_some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::ready;
+
+    fn height(height: u64) -> AbsoluteBlockHeight { AbsoluteBlockHeight { height } }
+
+    fn outcome(height: u64, result: Result<u8, RejectReason>) -> ViewOutcome {
+        ViewOutcome {
+            height:     self::height(height),
+            block_hash: BlockHash::new([height as u8; 32]),
+            result:     result.map(|value| vec![value]),
+        }
+    }
+
+    /// Find the changes of the values returned in each block, starting at
+    /// height 0, searching linearly like the binary search would. Rejected
+    /// invocations are reported as [`None`].
+    async fn changes(values: &[Result<u8, RejectReason>]) -> Vec<(u64, Option<u8>)> {
+        let at = |h: u64| outcome(h, values[h as usize].clone());
+        let last = values.len() as u64 - 1;
+        let found = find_changes(at(0), at(last), |range, current| {
+            let found = (u64::from(*range.start())..u64::from(*range.end()))
+                .map(at)
+                .find(|outcome| !outcome.same_result(&current));
+            ready(found.ok_or(v2::QueryError::NotFound))
+        })
+        .await
+        .expect("Search does not fail.");
+        found
+            .into_iter()
+            .map(|outcome| {
+                (
+                    outcome.height.height,
+                    outcome.result.ok().map(|value| value[0]),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    /// Test bounding ranges of heights by the last finalized height.
+    fn bound_height_ranges() {
+        assert_eq!(bounded_range(.., 10), Some((0, 10)));
+        assert_eq!(bounded_range(height(3)..=height(20), 10), Some((3, 10)));
+        assert_eq!(bounded_range(height(3)..height(5), 10), Some((3, 4)));
+        assert_eq!(bounded_range(height(10).., 10), Some((10, 10)));
+        assert_eq!(
+            bounded_range((Bound::Excluded(height(2)), Bound::Unbounded), 10),
+            Some((3, 10))
+        );
+        assert_eq!(bounded_range(..height(0), 10), None);
+        assert_eq!(bounded_range(height(5)..height(5), 10), None);
+        assert_eq!(bounded_range(height(11).., 10), None);
+    }
+
+    #[test]
+    /// Test that reject reasons are compared by value.
+    fn compare_results() {
+        let reject = || Err(RejectReason::OutOfEnergy);
+        assert!(outcome(0, reject()).same_result(&outcome(1, reject())));
+        assert!(outcome(0, Ok(1)).same_result(&outcome(1, Ok(1))));
+        assert!(!outcome(0, Ok(1)).same_result(&outcome(1, Ok(2))));
+        assert!(!outcome(0, reject()).same_result(&outcome(1, Ok(1))));
+    }
+
+    #[tokio::test]
+    /// Test finding the blocks in which the value changed.
+    async fn find_value_changes() {
+        assert_eq!(
+            changes(&[Ok(1), Ok(1), Ok(2), Ok(2), Ok(2), Ok(3), Ok(3)]).await,
+            vec![(0, Some(1)), (2, Some(2)), (5, Some(3))]
+        );
+        // The value changes in the last block, which the search does not test.
+        assert_eq!(changes(&[Ok(1), Ok(1), Ok(1), Ok(2)]).await, vec![
+            (0, Some(1)),
+            (3, Some(2))
+        ]);
+        assert_eq!(changes(&[Ok(1), Ok(2)]).await, vec![
+            (0, Some(1)),
+            (1, Some(2))
+        ]);
+        assert_eq!(changes(&[Ok(5), Ok(5), Ok(5)]).await, vec![(0, Some(5))]);
+        assert_eq!(changes(&[Ok(5)]).await, vec![(0, Some(5))]);
+        let reject = || Err(RejectReason::OutOfEnergy);
+        assert_eq!(changes(&[reject(), reject(), Ok(1), Ok(1)]).await, vec![
+            (0, None),
+            (2, Some(1))
+        ]);
+    }
+
+    #[tokio::test]
+    /// Test that the search fails if finding a change fails.
+    async fn find_changes_error() {
+        let result = find_changes(outcome(0, Ok(1)), outcome(5, Ok(2)), |_, _| {
+            ready(Err(v2::QueryError::RPCError(v2::RPCError::CallError(
+                tonic::Status::unavailable("node is down"),
+            ))))
+        })
+        .await;
+        assert!(matches!(result, Err(v2::QueryError::RPCError(_))));
+    }
+}
@@ -0,0 +1,630 @@
+//! Energy profiling of contract entrypoints.
+//!
+//! A [`Profiler`] invokes entrypoints of a contract instance with a set of
+//! [`ProfileCase`]s, and reports the energy used, the cost of a transaction
+//! performing the same update, and the calls to other contracts made during
+//! the execution. Cases can be constructed from user-supplied parameters, or
+//! generated from the schema of the contract using
+//! [`ProfileCase::from_schema`].
+//!
+//! [`Profiler::compare`] profiles the same cases against two instances, e.g.,
+//! the current instance and an instance running a new version of the module,
+//! to compare their energy usage before upgrading.
+use super::ContractClient;
+use crate::{
+    types::{
+        smart_contracts::{ContractContext, InvokeContractResult},
+        transactions, ExecutionTree, RejectReason, TraceV0, TraceV1,
+    },
+    v2::{self, BlockIdentifier},
+};
+use concordium_base::{
+    base::Energy,
+    contracts_common::{
+        schema::{Fields, SizeLength, Type, VersionedModuleSchema},
+        AccountAddress, Address, Amount, ContractAddress, NewReceiveNameError,
+    },
+    smart_contracts::{ExceedsParameterSize, OwnedParameter, OwnedReceiveName},
+    transactions::{construct::TRANSACTION_HEADER_SIZE, PayloadLike, UpdateContractPayload},
+};
+use serde_json::{json, Value};
+use std::fmt;
+
+/// An error that occurred while profiling.
+#[derive(thiserror::Error, Debug)]
+pub enum ProfileError {
+    #[error("Query failed: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Invalid receive name: {0}")]
+    ReceiveName(#[from] NewReceiveNameError),
+    #[error("Parameter is too large: {0}")]
+    ParameterSize(#[from] ExceedsParameterSize),
+    #[error("The contract has no schema for the parameter of {entrypoint}.")]
+    MissingSchema { entrypoint: String },
+    #[error("Could not serialize a generated parameter: {0}")]
+    Serialization(String),
+}
+
+/// An invocation of an entrypoint to profile.
+#[derive(Debug, Clone)]
+pub struct ProfileCase {
+    /// A label identifying the case in the report.
+    pub label:      String,
+    /// The name of the entrypoint, without the contract name.
+    pub entrypoint: String,
+    /// The parameter to invoke the entrypoint with.
+    pub parameter:  OwnedParameter,
+    /// The amount to invoke the entrypoint with.
+    pub amount:     Amount,
+}
+
+impl ProfileCase {
+    /// Construct a case invoking the entrypoint with the given parameter and
+    /// no CCD.
+    pub fn new(
+        label: impl Into<String>,
+        entrypoint: impl Into<String>,
+        parameter: OwnedParameter,
+    ) -> Self {
+        Self {
+            label: label.into(),
+            entrypoint: entrypoint.into(),
+            parameter,
+            amount: Amount::zero(),
+        }
+    }
+
+    /// Set the amount to invoke the entrypoint with.
+    pub fn set_amount(self, amount: Amount) -> Self { Self { amount, ..self } }
+
+    /// Generate a case for each of the given sizes, with a parameter generated
+    /// from the parameter schema of the entrypoint by [`schema_sample`]. Note
+    /// that the generated parameters are not necessarily meaningful to the
+    /// contract, which might reject them.
+    pub fn from_schema(
+        schema: &VersionedModuleSchema,
+        contract_name: &str,
+        entrypoint: &str,
+        sizes: &[usize],
+    ) -> Result<Vec<Self>, ProfileError> {
+        let ty = schema
+            .get_receive_param_schema(contract_name, entrypoint)
+            .map_err(|_| ProfileError::MissingSchema {
+                entrypoint: entrypoint.into(),
+            })?;
+        sizes
+            .iter()
+            .map(|&size| {
+                let bytes = ty
+                    .serial_value(&schema_sample(&ty, size))
+                    .map_err(|e| ProfileError::Serialization(e.to_string()))?;
+                Ok(Self::new(
+                    format!("{entrypoint} (size {size})"),
+                    entrypoint,
+                    OwnedParameter::try_from(bytes)?,
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Generate a JSON value of the given schema type, suitable for serializing
+/// with [`Type::serial_value`]. The size determines the number of elements of
+/// lists, sets, maps, strings and byte lists, as well as the numeric values,
+/// so that larger sizes generate larger parameters.
+pub fn schema_sample(ty: &Type, size: usize) -> Value {
+    let n = size as u64;
+    match ty {
+        Type::Unit => json!([]),
+        Type::Bool => json!(size % 2 == 1),
+        Type::U8 => json!(n.min(u8::MAX.into())),
+        Type::U16 => json!(n.min(u16::MAX.into())),
+        Type::U32 => json!(n.min(u32::MAX.into())),
+        Type::U64 => json!(n),
+        Type::I8 => json!(n.min(i8::MAX as u64)),
+        Type::I16 => json!(n.min(i16::MAX as u64)),
+        Type::I32 => json!(n.min(i32::MAX as u64)),
+        Type::I64 => json!(n.min(i64::MAX as u64)),
+        Type::U128 | Type::I128 | Type::ULeb128(_) | Type::ILeb128(_) => json!(n.to_string()),
+        Type::Amount => json!(n.to_string()),
+        Type::AccountAddress => json!(AccountAddress([size as u8; 32]).to_string()),
+        Type::ContractAddress => json!({ "index": n, "subindex": 0 }),
+        Type::Timestamp => json!(
+            (chrono::DateTime::<chrono::Utc>::from(std::time::UNIX_EPOCH)
+                + chrono::Duration::seconds(n as i64))
+            .to_rfc3339()
+        ),
+        Type::Duration => json!(format!("{n}ms")),
+        Type::Pair(a, b) => json!([schema_sample(a, size), schema_sample(b, size)]),
+        Type::List(len, ty) | Type::Set(len, ty) => Value::Array(
+            (0..clamp_length(*len, size))
+                .map(|i| schema_sample(ty, i))
+                .collect(),
+        ),
+        Type::Map(len, key, value) => Value::Array(
+            (0..clamp_length(*len, size))
+                .map(|i| json!([schema_sample(key, i), schema_sample(value, i)]))
+                .collect(),
+        ),
+        Type::Array(len, ty) => Value::Array((0..*len).map(|_| schema_sample(ty, size)).collect()),
+        Type::Struct(fields) => fields_sample(fields, size),
+        Type::Enum(variants) => match variants.get(size % variants.len().max(1)) {
+            Some((name, fields)) => json!({ name: fields_sample(fields, size) }),
+            None => json!({}),
+        },
+        Type::TaggedEnum(variants) => match variants.values().nth(size % variants.len().max(1)) {
+            Some((name, fields)) => json!({ name: fields_sample(fields, size) }),
+            None => json!({}),
+        },
+        Type::String(len) => json!("a".repeat(clamp_length(*len, size))),
+        Type::ContractName(_) => json!({ "contract": "contract" }),
+        Type::ReceiveName(_) => json!({ "contract": "contract", "func": "func" }),
+        Type::ByteList(len) => json!(hex::encode(vec![0u8; clamp_length(*len, size)])),
+        Type::ByteArray(len) => json!(hex::encode(vec![0u8; *len as usize])),
+    }
+}
+
+fn fields_sample(fields: &Fields, size: usize) -> Value {
+    match fields {
+        Fields::Named(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, ty)| (name.clone(), schema_sample(ty, size)))
+                .collect(),
+        ),
+        Fields::Unnamed(fields) => {
+            Value::Array(fields.iter().map(|ty| schema_sample(ty, size)).collect())
+        }
+        Fields::None => json!([]),
+    }
+}
+
+/// Limit the length of a collection to what can be represented by its length
+/// prefix.
+fn clamp_length(len: SizeLength, size: usize) -> usize {
+    let max = match len {
+        SizeLength::U8 => u8::MAX.into(),
+        SizeLength::U16 => u16::MAX.into(),
+        SizeLength::U32 | SizeLength::U64 => usize::MAX,
+    };
+    size.min(max)
+}
+
+/// The outcome of a profiled invocation.
+#[derive(Debug, Clone)]
+pub enum ProfileOutcome {
+    Success,
+    Rejected { reason: RejectReason },
+}
+
+/// A call of a contract entrypoint made during a profiled invocation.
+#[derive(Debug, Clone)]
+pub struct ProfiledCall {
+    /// The nesting depth of the call. The top-level call has depth 0.
+    pub depth:           usize,
+    /// The address of the called instance.
+    pub address:         ContractAddress,
+    /// The entrypoint that was called.
+    pub receive_name:    OwnedReceiveName,
+    /// The caller.
+    pub instigator:      Address,
+    /// The amount sent with the call.
+    pub amount:          Amount,
+    /// The parameter of the call.
+    pub parameter:       OwnedParameter,
+    /// The energy used by invoking the same entrypoint directly, with the
+    /// same caller, parameter and amount. This includes the energy of calls
+    /// nested within it. Since it is measured in the state before the
+    /// top-level invocation, it is an approximation. This is only measured
+    /// for nested calls if [`Profiler::set_measure_nested`] is enabled.
+    pub isolated_energy: Option<Energy>,
+}
+
+/// The profile of a single [`ProfileCase`].
+#[derive(Debug, Clone)]
+pub struct ProfileEntry {
+    /// The label of the case.
+    pub label:              String,
+    /// The entrypoint that was invoked.
+    pub entrypoint:         String,
+    /// Whether the invocation succeeded.
+    pub outcome:            ProfileOutcome,
+    /// The energy used by executing the entrypoint.
+    pub used_energy:        Energy,
+    /// The energy of a transaction performing the update, i.e., the energy
+    /// used by execution plus the base cost of the transaction with a single
+    /// signature.
+    pub transaction_energy: Energy,
+    /// The CCD cost of the transaction energy.
+    pub ccd_cost:           Amount,
+    /// The calls made during the invocation, in the order they were made,
+    /// starting with the top-level call. This is empty if the invocation was
+    /// rejected.
+    pub calls:              Vec<ProfiledCall>,
+}
+
+/// The result of [`Profiler::profile`].
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    pub entries: Vec<ProfileEntry>,
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<40} {:>12} {:>12} {:>16} {:>6}",
+            "case", "energy", "tx energy", "CCD cost", "calls"
+        )?;
+        for entry in &self.entries {
+            let label = match entry.outcome {
+                ProfileOutcome::Success => entry.label.clone(),
+                ProfileOutcome::Rejected { .. } => format!("{} (rejected)", entry.label),
+            };
+            writeln!(
+                f,
+                "{:<40} {:>12} {:>12} {:>16} {:>6}",
+                label,
+                entry.used_energy.energy,
+                entry.transaction_energy.energy,
+                entry.ccd_cost.to_string(),
+                entry.calls.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The profiles of the same case against two instances.
+#[derive(Debug, Clone)]
+pub struct ComparedEntry {
+    pub current:   ProfileEntry,
+    pub candidate: ProfileEntry,
+}
+
+impl ComparedEntry {
+    /// The difference in energy used by the candidate compared to the current
+    /// instance.
+    pub fn energy_difference(&self) -> i128 {
+        i128::from(self.candidate.used_energy.energy) - i128::from(self.current.used_energy.energy)
+    }
+}
+
+/// The result of [`Profiler::compare`].
+#[derive(Debug, Clone)]
+pub struct ProfileComparison {
+    pub entries: Vec<ComparedEntry>,
+}
+
+impl fmt::Display for ProfileComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<40} {:>12} {:>12} {:>12}",
+            "case", "current", "candidate", "difference"
+        )?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:<40} {:>12} {:>12} {:>+12}",
+                entry.current.label,
+                entry.current.used_energy.energy,
+                entry.candidate.used_energy.energy,
+                entry.energy_difference()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Profiles the energy usage of contract entrypoints. See the
+/// [module documentation](self) for an overview.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    sender:         AccountAddress,
+    block:          BlockIdentifier,
+    measure_nested: bool,
+}
+
+impl Profiler {
+    /// Construct a profiler that invokes entrypoints as the given sender in
+    /// the last finalized block.
+    pub fn new(sender: AccountAddress) -> Self {
+        Self {
+            sender,
+            block: BlockIdentifier::LastFinal,
+            measure_nested: false,
+        }
+    }
+
+    /// Set the block to profile in.
+    pub fn set_block(self, block: impl v2::IntoBlockIdentifier) -> Self {
+        Self {
+            block: block.into_block_identifier(),
+            ..self
+        }
+    }
+
+    /// Set whether to measure the energy of nested calls by invoking them
+    /// directly. See [`ProfiledCall::isolated_energy`].
+    pub fn set_measure_nested(self, measure_nested: bool) -> Self {
+        Self {
+            measure_nested,
+            ..self
+        }
+    }
+
+    /// Profile the cases against the instance of the client.
+    pub async fn profile<Type>(
+        &self,
+        client: &mut ContractClient<Type>,
+        cases: &[ProfileCase],
+    ) -> Result<ProfileReport, ProfileError> {
+        let chain_parameters = client.client.get_block_chain_parameters(self.block).await?;
+        // Use the same block for all invocations.
+        let block = chain_parameters.block_hash;
+        let chain_parameters = chain_parameters.response;
+        let mut entries = Vec::with_capacity(cases.len());
+        for case in cases {
+            let contract_name = client.contract_name.as_contract_name().contract_name();
+            let method =
+                OwnedReceiveName::try_from(format!("{contract_name}.{}", case.entrypoint))?;
+            let context = ContractContext {
+                invoker:   Some(Address::Account(self.sender)),
+                contract:  client.address,
+                amount:    case.amount,
+                method:    method.clone(),
+                parameter: case.parameter.clone(),
+                energy:    None,
+            };
+            let result = client
+                .client
+                .invoke_instance(block, &context)
+                .await?
+                .response;
+            let used_energy = result.used_energy();
+            let payload = transactions::Payload::Update {
+                payload: UpdateContractPayload {
+                    amount:       case.amount,
+                    address:      client.address,
+                    receive_name: method,
+                    message:      case.parameter.clone(),
+                },
+            };
+            let base_cost = transactions::cost::base_cost(
+                TRANSACTION_HEADER_SIZE + u64::from(u32::from(payload.encode().size())),
+                1,
+            );
+            let transaction_energy = base_cost + used_energy;
+            let (outcome, calls) = match result {
+                InvokeContractResult::Success { events, .. } => {
+                    let mut calls = Vec::new();
+                    if let Some(tree) = crate::types::execution_tree(events) {
+                        collect_calls(&tree, 0, &mut calls);
+                    }
+                    if self.measure_nested {
+                        for call in calls.iter_mut().filter(|call| call.depth > 0) {
+                            call.isolated_energy =
+                                Some(self.isolated_energy(client, block, call).await?);
+                        }
+                    }
+                    (ProfileOutcome::Success, calls)
+                }
+                InvokeContractResult::Failure { reason, .. } => {
+                    (ProfileOutcome::Rejected { reason }, Vec::new())
+                }
+            };
+            entries.push(ProfileEntry {
+                label: case.label.clone(),
+                entrypoint: case.entrypoint.clone(),
+                outcome,
+                used_energy,
+                transaction_energy,
+                ccd_cost: chain_parameters.ccd_cost(transaction_energy),
+                calls,
+            });
+        }
+        Ok(ProfileReport { entries })
+    }
+
+    /// Profile the cases against two instances, typically the current
+    /// instance and an instance running a candidate version of its module,
+    /// e.g., on a test network. Both instances must accept the same
+    /// parameters.
+    pub async fn compare<A, B>(
+        &self,
+        current: &mut ContractClient<A>,
+        candidate: &mut ContractClient<B>,
+        cases: &[ProfileCase],
+    ) -> Result<ProfileComparison, ProfileError> {
+        let current = self.profile(current, cases).await?;
+        let candidate = self.profile(candidate, cases).await?;
+        let entries = current
+            .entries
+            .into_iter()
+            .zip(candidate.entries)
+            .map(|(current, candidate)| ComparedEntry { current, candidate })
+            .collect();
+        Ok(ProfileComparison { entries })
+    }
+
+    /// Measure the energy of invoking a nested call directly.
+    async fn isolated_energy<Type>(
+        &self,
+        client: &mut ContractClient<Type>,
+        block: v2::BlockHash,
+        call: &ProfiledCall,
+    ) -> Result<Energy, ProfileError> {
+        let context = ContractContext {
+            invoker:   Some(call.instigator),
+            contract:  call.address,
+            amount:    call.amount,
+            method:    call.receive_name.clone(),
+            parameter: call.parameter.clone(),
+            energy:    None,
+        };
+        Ok(client
+            .client
+            .invoke_instance(block, &context)
+            .await?
+            .response
+            .used_energy())
+    }
+}
+
+/// Collect the calls in the execution tree in the order they were made.
+fn collect_calls(tree: &ExecutionTree, depth: usize, calls: &mut Vec<ProfiledCall>) {
+    match tree {
+        ExecutionTree::V0(v0) => {
+            calls.push(ProfiledCall {
+                depth,
+                address: v0.top_level.address,
+                receive_name: v0.top_level.receive_name.clone(),
+                instigator: v0.top_level.instigator,
+                amount: v0.top_level.amount,
+                parameter: v0.top_level.message.clone(),
+                isolated_energy: None,
+            });
+            for rest in &v0.rest {
+                if let TraceV0::Call(call) = rest {
+                    collect_calls(call, depth + 1, calls);
+                }
+            }
+        }
+        ExecutionTree::V1(v1) => {
+            calls.push(ProfiledCall {
+                depth,
+                address: v1.address,
+                receive_name: v1.receive_name.clone(),
+                instigator: v1.instigator,
+                amount: v1.amount,
+                parameter: v1.message.clone(),
+                isolated_energy: None,
+            });
+            for event in &v1.events {
+                if let TraceV1::Call { call } = event {
+                    collect_calls(call, depth + 1, calls);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExecutionTreeV1;
+
+    #[test]
+    /// Test that lengths are limited by their length prefix.
+    fn clamp_lengths() {
+        assert_eq!(clamp_length(SizeLength::U8, 10), 10);
+        assert_eq!(clamp_length(SizeLength::U8, 300), 255);
+        assert_eq!(clamp_length(SizeLength::U16, 300), 300);
+        assert_eq!(clamp_length(SizeLength::U16, 70_000), 65_535);
+        assert_eq!(clamp_length(SizeLength::U32, 70_000), 70_000);
+        assert_eq!(clamp_length(SizeLength::U64, usize::MAX), usize::MAX);
+    }
+
+    #[test]
+    /// Test the values generated for schema types of different sizes.
+    fn schema_samples() {
+        assert_eq!(schema_sample(&Type::U8, 300), json!(255));
+        assert_eq!(schema_sample(&Type::I8, 300), json!(127));
+        assert_eq!(schema_sample(&Type::U64, 300), json!(300));
+        assert_eq!(schema_sample(&Type::Amount, 7), json!("7"));
+        assert_eq!(
+            schema_sample(&Type::List(SizeLength::U8, Box::new(Type::U8)), 3),
+            json!([0, 1, 2])
+        );
+        assert_eq!(
+            schema_sample(&Type::String(SizeLength::U8), 300),
+            json!("a".repeat(255))
+        );
+        let ty = Type::Struct(Fields::Named(vec![
+            ("flag".into(), Type::Bool),
+            (
+                "pair".into(),
+                Type::Pair(Box::new(Type::U16), Box::new(Type::Unit)),
+            ),
+        ]));
+        assert_eq!(
+            schema_sample(&ty, 5),
+            json!({ "flag": true, "pair": [5, []] })
+        );
+        let ty = Type::Enum(vec![
+            ("A".into(), Fields::None),
+            ("B".into(), Fields::Unnamed(vec![Type::U32])),
+        ]);
+        assert_eq!(schema_sample(&ty, 4), json!({ "A": [] }));
+        assert_eq!(schema_sample(&ty, 5), json!({ "B": [5] }));
+    }
+
+    #[test]
+    /// Test that generated values can be serialized with their schema type,
+    /// and that larger sizes generate larger parameters.
+    fn schema_samples_serialize() {
+        let ty = Type::Struct(Fields::Named(vec![
+            ("owner".into(), Type::AccountAddress),
+            ("at".into(), Type::Timestamp),
+            (
+                "balances".into(),
+                Type::Map(
+                    SizeLength::U16,
+                    Box::new(Type::ContractAddress),
+                    Box::new(Type::U128),
+                ),
+            ),
+            ("data".into(), Type::ByteList(SizeLength::U32)),
+        ]));
+        let small = ty.serial_value(&schema_sample(&ty, 1)).unwrap();
+        let large = ty.serial_value(&schema_sample(&ty, 10)).unwrap();
+        assert!(small.len() < large.len());
+    }
+
+    fn call(index: u64, events: Vec<TraceV1>) -> ExecutionTree {
+        ExecutionTree::V1(ExecutionTreeV1 {
+            address: ContractAddress::new(index, 0),
+            instigator: Address::Account(AccountAddress([0u8; 32])),
+            amount: Amount::from_micro_ccd(index),
+            message: OwnedParameter::empty(),
+            receive_name: OwnedReceiveName::new_unchecked(format!("contract{index}.receive")),
+            events,
+        })
+    }
+
+    #[test]
+    /// Test that calls are collected in the order they were made, with their
+    /// nesting depth.
+    fn collected_calls() {
+        let tree = call(0, vec![
+            TraceV1::Events { events: Vec::new() },
+            TraceV1::Call {
+                call: call(1, vec![TraceV1::Call {
+                    call: call(2, Vec::new()),
+                }]),
+            },
+            TraceV1::Transfer {
+                from:   ContractAddress::new(0, 0),
+                amount: Amount::from_micro_ccd(1),
+                to:     AccountAddress([1u8; 32]),
+            },
+            TraceV1::Call {
+                call: call(3, Vec::new()),
+            },
+        ]);
+        let mut calls = Vec::new();
+        collect_calls(&tree, 0, &mut calls);
+        let collected: Vec<_> = calls
+            .iter()
+            .map(|call| (call.address.index, call.depth))
+            .collect();
+        assert_eq!(collected, [(0, 0), (1, 1), (2, 2), (3, 1)]);
+        assert_eq!(
+            calls[2].receive_name.as_receive_name().get_chain_name(),
+            "contract2.receive"
+        );
+        assert_eq!(calls[3].amount, Amount::from_micro_ccd(3));
+        assert!(calls.iter().all(|call| call.isolated_energy.is_none()));
+    }
+}
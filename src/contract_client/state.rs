@@ -0,0 +1,610 @@
+//! Inspection of the state of V1 contract instances.
+//!
+//! The state of a V1 instance is a tree of key-value pairs. Contracts written
+//! using `concordium-std` lay out the state as follows:
+//! - the root of the state, i.e., the value of the state type of the contract,
+//!   is stored under the empty key,
+//! - the next free prefix for allocating state items is stored under the key
+//!   [`NEXT_ITEM_PREFIX_KEY`],
+//! - every `StateMap`, `StateSet` and `StateBox` is allocated a unique 8 byte
+//!   [`StateItemPrefix`]. In the root, and in other items, the item is
+//!   serialized as its prefix. The entries of a `StateMap` are stored under the
+//!   prefix followed by the serialized key, the values of a `StateSet` under
+//!   the prefix followed by the serialized value, with an empty value, and the
+//!   content of a `StateBox` under the prefix itself.
+//!
+//! A [`StateInspector`] queries the state of an instance in a given block,
+//! either looking up single keys, or fetching all entries with a given prefix.
+//! [`StateInspector::snapshot`] fetches the whole state as a [`StateSnapshot`],
+//! which can be dumped, decoded and compared with a snapshot from another
+//! block using [`StateSnapshot::diff`].
+use super::ContractClient;
+use crate::v2::{self, BlockIdentifier, IntoBlockIdentifier, QueryResult};
+use concordium_base::{
+    contracts_common::{self, ContractAddress, Deserial, ParseError, Serial},
+    hashes::BlockHash,
+};
+use futures::TryStreamExt;
+use std::{collections::BTreeMap, fmt};
+
+/// The key under which `concordium-std` stores the next free
+/// [`StateItemPrefix`].
+pub const NEXT_ITEM_PREFIX_KEY: [u8; 8] = 0u64.to_le_bytes();
+
+/// An error that occurred while inspecting the state of an instance.
+#[derive(thiserror::Error, Debug)]
+pub enum StateError {
+    #[error("Query failed: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Could not deserialize a state entry: {0}")]
+    Parse(#[from] ParseError),
+    #[error("The key {} is not a key of the state item.", hex::encode(.0))]
+    UnexpectedKey(Vec<u8>),
+}
+
+/// The prefix of a `StateMap`, `StateSet` or `StateBox` allocated by
+/// `concordium-std`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StateItemPrefix(pub u64);
+
+impl StateItemPrefix {
+    /// The key bytes of the prefix.
+    pub fn to_key(self) -> [u8; 8] { self.0.to_le_bytes() }
+
+    /// The key of the entry of a `StateMap` or `StateSet` with this prefix.
+    pub fn entry_key(self, key: &impl Serial) -> Vec<u8> {
+        let mut out = self.to_key().to_vec();
+        key.serial(&mut out)
+            .expect("Serializing into a vector does not fail.");
+        out
+    }
+
+    /// The prefix of the given key, if it is long enough to have one.
+    pub fn of_key(key: &[u8]) -> Option<Self> {
+        let bytes = key.get(..8)?;
+        Some(Self(u64::from_le_bytes(
+            bytes.try_into().expect("Slice has length 8."),
+        )))
+    }
+}
+
+impl Serial for StateItemPrefix {
+    fn serial<W: contracts_common::Write>(&self, out: &mut W) -> Result<(), W::Err> {
+        self.0.serial(out)
+    }
+}
+
+impl Deserial for StateItemPrefix {
+    fn deserial<R: contracts_common::Read>(source: &mut R) -> contracts_common::ParseResult<Self> {
+        Ok(Self(u64::deserial(source)?))
+    }
+}
+
+impl fmt::Display for StateItemPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "#{}", self.0) }
+}
+
+/// Inspects the state of a V1 contract instance.
+#[derive(Debug, Clone)]
+pub struct StateInspector {
+    client:  v2::Client,
+    address: ContractAddress,
+}
+
+impl<Type> ContractClient<Type> {
+    /// Construct a [`StateInspector`] for the instance.
+    pub fn state_inspector(&self) -> StateInspector {
+        StateInspector::new(self.client.clone(), self.address)
+    }
+}
+
+impl StateInspector {
+    /// Construct a new inspector for the instance at the given address.
+    pub fn new(client: v2::Client, address: ContractAddress) -> Self { Self { client, address } }
+
+    /// Look up the value at the given key. Returns [`None`] if there is no
+    /// entry with the key. If the block or the instance does not exist, the
+    /// query fails with a not found error.
+    pub async fn lookup(
+        &mut self,
+        key: impl Into<Vec<u8>>,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<Option<Vec<u8>>> {
+        let bi = bi.into_block_identifier();
+        match self
+            .client
+            .instance_state_lookup(self.address, key, bi)
+            .await
+        {
+            Ok(value) => Ok(Some(value.response)),
+            Err(e) if e.is_not_found() => {
+                // The node does not distinguish a missing key from a missing
+                // block or instance, so check that the latter exist.
+                self.client.get_instance_info(self.address, bi).await?;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the root of the state, deserialized as the state type of the
+    /// contract. State items in the root are deserialized as their
+    /// [`StateItemPrefix`].
+    pub async fn root<A: Deserial>(
+        &mut self,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<A, StateError> {
+        let value = self.lookup(Vec::new(), bi).await?.unwrap_or_default();
+        Ok(contracts_common::from_bytes(&value)?)
+    }
+
+    /// Get the content of a `StateBox`. Returns [`None`] if the box does not
+    /// exist.
+    pub async fn state_box<A: Deserial>(
+        &mut self,
+        prefix: StateItemPrefix,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Option<A>, StateError> {
+        match self.lookup(prefix.to_key(), bi).await? {
+            Some(value) => Ok(Some(contracts_common::from_bytes(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the value of the entry with the given key in a `StateMap`.
+    pub async fn map_get<K: Serial, V: Deserial>(
+        &mut self,
+        prefix: StateItemPrefix,
+        key: &K,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Option<V>, StateError> {
+        match self.lookup(prefix.entry_key(key), bi).await? {
+            Some(value) => Ok(Some(contracts_common::from_bytes(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Check whether a `StateSet` contains the given value.
+    pub async fn set_contains<K: Serial>(
+        &mut self,
+        prefix: StateItemPrefix,
+        value: &K,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<bool> {
+        Ok(self.lookup(prefix.entry_key(value), bi).await?.is_some())
+    }
+
+    /// Get all entries whose keys start with the given prefix, in
+    /// lexicographic order of keys.
+    ///
+    /// Note that this streams the state of the instance up to the last key
+    /// with the prefix, since the node does not support querying a range of
+    /// keys.
+    pub async fn prefix_entries(
+        &mut self,
+        prefix: &[u8],
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let stream = self
+            .client
+            .get_instance_state(self.address, bi)
+            .await?
+            .response;
+        let mut stream = std::pin::pin!(stream);
+        let mut out = Vec::new();
+        while let Some((key, value)) = stream.try_next().await? {
+            if key.starts_with(prefix) {
+                out.push((key, value));
+            } else if key.as_slice() > prefix {
+                // Keys are streamed in lexicographic order, so there are no
+                // more keys with the prefix.
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Get the entries of a `StateMap`, deserialized using the given types.
+    pub async fn map_entries<K: Deserial, V: Deserial>(
+        &mut self,
+        prefix: StateItemPrefix,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Vec<(K, V)>, StateError> {
+        let entries = self.prefix_entries(&prefix.to_key(), bi).await?;
+        decode_map_entries(prefix, entries.iter().map(|(k, v)| (&k[..], &v[..])))
+    }
+
+    /// Get the values of a `StateSet`, deserialized using the given type.
+    pub async fn set_values<K: Deserial>(
+        &mut self,
+        prefix: StateItemPrefix,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Vec<K>, StateError> {
+        let entries = self.prefix_entries(&prefix.to_key(), bi).await?;
+        decode_set_values(prefix, entries.iter().map(|(k, _)| &k[..]))
+    }
+
+    /// Fetch the whole state of the instance in the given block.
+    pub async fn snapshot(&mut self, bi: impl IntoBlockIdentifier) -> QueryResult<StateSnapshot> {
+        let response = self.client.get_instance_state(self.address, bi).await?;
+        let entries = response.response.try_collect().await?;
+        Ok(StateSnapshot {
+            block_hash: response.block_hash,
+            entries,
+        })
+    }
+
+    /// Compare the state of the instance in two blocks.
+    pub async fn diff(
+        &mut self,
+        from: BlockIdentifier,
+        to: BlockIdentifier,
+    ) -> QueryResult<StateDiff> {
+        let from = self.snapshot(from).await?;
+        let to = self.snapshot(to).await?;
+        Ok(from.diff(&to))
+    }
+}
+
+/// The state of an instance in a given block.
+#[derive(Debug, Clone)]
+pub struct StateSnapshot {
+    /// The block the state was queried in.
+    pub block_hash: BlockHash,
+    /// The entries of the state.
+    pub entries:    BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl StateSnapshot {
+    /// Get the value at the given key.
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> { self.entries.get(key).map(Vec::as_slice) }
+
+    /// Iterate over the entries whose keys start with the given prefix, in
+    /// lexicographic order of keys.
+    pub fn prefix_iter<'a>(
+        &'a self,
+        prefix: &'a [u8],
+    ) -> impl Iterator<Item = (&'a [u8], &'a [u8])> + 'a {
+        self.entries
+            .range(prefix.to_vec()..)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+            .map(|(key, value)| (key.as_slice(), value.as_slice()))
+    }
+
+    /// Deserialize the root of the state.
+    pub fn root<A: Deserial>(&self) -> Result<A, ParseError> {
+        contracts_common::from_bytes(self.get(&[]).unwrap_or_default())
+    }
+
+    /// The next free [`StateItemPrefix`], if it is stored in the state.
+    pub fn next_item_prefix(&self) -> Option<StateItemPrefix> {
+        let value = self.get(&NEXT_ITEM_PREFIX_KEY)?;
+        contracts_common::from_bytes(value).ok()
+    }
+
+    /// Deserialize the content of a `StateBox`.
+    pub fn state_box<A: Deserial>(&self, prefix: StateItemPrefix) -> Option<Result<A, ParseError>> {
+        self.get(&prefix.to_key()).map(contracts_common::from_bytes)
+    }
+
+    /// Deserialize the entries of a `StateMap`.
+    pub fn map_entries<K: Deserial, V: Deserial>(
+        &self,
+        prefix: StateItemPrefix,
+    ) -> Result<Vec<(K, V)>, StateError> {
+        decode_map_entries(prefix, self.prefix_iter(&prefix.to_key()))
+    }
+
+    /// Deserialize the values of a `StateSet`.
+    pub fn set_values<K: Deserial>(&self, prefix: StateItemPrefix) -> Result<Vec<K>, StateError> {
+        decode_set_values(prefix, self.prefix_iter(&prefix.to_key()).map(|(k, _)| k))
+    }
+
+    /// Group the entries of the state by the [`StateItemPrefix`] of their
+    /// keys. Entries with keys shorter than a prefix, such as the root, are
+    /// not included.
+    pub fn items(&self) -> BTreeMap<StateItemPrefix, Vec<(&[u8], &[u8])>> {
+        let mut items = BTreeMap::<_, Vec<_>>::new();
+        for (key, value) in &self.entries {
+            if let Some(prefix) = StateItemPrefix::of_key(key) {
+                items
+                    .entry(prefix)
+                    .or_default()
+                    .push((key.as_slice(), value.as_slice()));
+            }
+        }
+        items
+    }
+
+    /// Compare this state with a later state of the same instance.
+    pub fn diff(&self, other: &Self) -> StateDiff {
+        let mut changes = Vec::new();
+        for (key, value) in &self.entries {
+            match other.entries.get(key) {
+                None => changes.push(StateChange::Removed {
+                    key:   key.clone(),
+                    value: value.clone(),
+                }),
+                Some(new) if new != value => changes.push(StateChange::Changed {
+                    key: key.clone(),
+                    old: value.clone(),
+                    new: new.clone(),
+                }),
+                Some(_) => (),
+            }
+        }
+        for (key, value) in &other.entries {
+            if !self.entries.contains_key(key) {
+                changes.push(StateChange::Added {
+                    key:   key.clone(),
+                    value: value.clone(),
+                });
+            }
+        }
+        changes.sort_by(|a, b| a.key().cmp(b.key()));
+        StateDiff {
+            from: self.block_hash,
+            to: other.block_hash,
+            changes,
+        }
+    }
+}
+
+/// Dumps the state with one line per entry, grouped by state item, with keys
+/// and values in hex. The key of an entry in a state item is shown without the
+/// prefix of the item.
+impl fmt::Display for StateSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "State in block {}:", self.block_hash)?;
+        let mut current = None;
+        for (key, value) in &self.entries {
+            match StateItemPrefix::of_key(key) {
+                Some(prefix) if key.as_slice() != NEXT_ITEM_PREFIX_KEY => {
+                    if current != Some(prefix) {
+                        writeln!(f, "{prefix}:")?;
+                        current = Some(prefix);
+                    }
+                    writeln!(f, "  {} = {}", hex::encode(&key[8..]), hex::encode(value))?;
+                }
+                _ => writeln!(f, "{} = {}", hex::encode(key), hex::encode(value))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A change to an entry of the state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateChange {
+    Added {
+        key:   Vec<u8>,
+        value: Vec<u8>,
+    },
+    Removed {
+        key:   Vec<u8>,
+        value: Vec<u8>,
+    },
+    Changed {
+        key: Vec<u8>,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+}
+
+impl StateChange {
+    /// The key of the changed entry.
+    pub fn key(&self) -> &[u8] {
+        match self {
+            StateChange::Added { key, .. } => key,
+            StateChange::Removed { key, .. } => key,
+            StateChange::Changed { key, .. } => key,
+        }
+    }
+
+    /// The [`StateItemPrefix`] of the changed entry, if any.
+    pub fn item(&self) -> Option<StateItemPrefix> { StateItemPrefix::of_key(self.key()) }
+}
+
+/// The changes to the state of an instance between two blocks, as computed by
+/// [`StateSnapshot::diff`].
+#[derive(Debug, Clone)]
+pub struct StateDiff {
+    /// The block of the old state.
+    pub from:    BlockHash,
+    /// The block of the new state.
+    pub to:      BlockHash,
+    /// The changed entries, in lexicographic order of keys.
+    pub changes: Vec<StateChange>,
+}
+
+impl StateDiff {
+    /// Whether the state is unchanged.
+    pub fn is_empty(&self) -> bool { self.changes.is_empty() }
+
+    /// The changes to entries with keys starting with the prefix of the given
+    /// state item.
+    pub fn item_changes(&self, prefix: StateItemPrefix) -> impl Iterator<Item = &StateChange> {
+        self.changes
+            .iter()
+            .filter(move |change| change.item() == Some(prefix))
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "State changes from block {} to block {}:",
+            self.from, self.to
+        )?;
+        for change in &self.changes {
+            match change {
+                StateChange::Added { key, value } => {
+                    writeln!(f, "+ {} = {}", hex::encode(key), hex::encode(value))?
+                }
+                StateChange::Removed { key, value } => {
+                    writeln!(f, "- {} = {}", hex::encode(key), hex::encode(value))?
+                }
+                StateChange::Changed { key, old, new } => writeln!(
+                    f,
+                    "~ {} = {} -> {}",
+                    hex::encode(key),
+                    hex::encode(old),
+                    hex::encode(new)
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode_map_entries<'a, K: Deserial, V: Deserial>(
+    prefix: StateItemPrefix,
+    entries: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+) -> Result<Vec<(K, V)>, StateError> {
+    let prefix = prefix.to_key();
+    let mut out = Vec::new();
+    for (key, value) in entries {
+        let Some(suffix) = key.strip_prefix(&prefix[..]) else {
+            return Err(StateError::UnexpectedKey(key.to_vec()));
+        };
+        out.push((
+            contracts_common::from_bytes(suffix)?,
+            contracts_common::from_bytes(value)?,
+        ));
+    }
+    Ok(out)
+}
+
+fn decode_set_values<'a, K: Deserial>(
+    prefix: StateItemPrefix,
+    keys: impl Iterator<Item = &'a [u8]>,
+) -> Result<Vec<K>, StateError> {
+    let prefix = prefix.to_key();
+    let mut out = Vec::new();
+    for key in keys {
+        let Some(suffix) = key.strip_prefix(&prefix[..]) else {
+            return Err(StateError::UnexpectedKey(key.to_vec()));
+        };
+        out.push(contracts_common::from_bytes(suffix)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> StateSnapshot {
+        StateSnapshot {
+            block_hash: BlockHash::new([0u8; 32]),
+            entries:    entries.into_iter().collect(),
+        }
+    }
+
+    /// A state with the root `(1u64, 2u64)` referring to a map from `u32` to
+    /// `u64` with prefix 1 and a set of `u8` with prefix 2.
+    fn state() -> StateSnapshot {
+        let map = StateItemPrefix(1);
+        let set = StateItemPrefix(2);
+        snapshot([
+            (Vec::new(), contracts_common::to_bytes(&(map, set))),
+            (
+                NEXT_ITEM_PREFIX_KEY.to_vec(),
+                contracts_common::to_bytes(&3u64),
+            ),
+            (map.entry_key(&7u32), contracts_common::to_bytes(&70u64)),
+            (map.entry_key(&8u32), contracts_common::to_bytes(&80u64)),
+            (set.entry_key(&1u8), Vec::new()),
+            (set.entry_key(&2u8), Vec::new()),
+        ])
+    }
+
+    #[test]
+    /// Test the layout of the keys of state items.
+    fn state_item_keys() {
+        let prefix = StateItemPrefix(0x0102);
+        assert_eq!(prefix.to_key(), [2, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(contracts_common::to_bytes(&prefix), prefix.to_key());
+        assert_eq!(prefix.entry_key(&7u32), vec![
+            2, 1, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0
+        ]);
+        assert_eq!(
+            StateItemPrefix::of_key(&prefix.entry_key(&7u32)),
+            Some(prefix)
+        );
+        assert_eq!(StateItemPrefix::of_key(&prefix.to_key()), Some(prefix));
+        assert_eq!(StateItemPrefix::of_key(&[2, 1, 0]), None);
+        assert_eq!(
+            contracts_common::from_bytes::<StateItemPrefix>(&prefix.to_key()),
+            Ok(prefix)
+        );
+        assert_eq!(prefix.to_string(), "#258");
+    }
+
+    #[test]
+    /// Test decoding the root and state items of a snapshot.
+    fn decode_snapshot() -> Result<(), StateError> {
+        let state = state();
+        let (map, set): (StateItemPrefix, StateItemPrefix) = state.root()?;
+        assert_eq!(state.next_item_prefix(), Some(StateItemPrefix(3)));
+        assert_eq!(state.map_entries::<u32, u64>(map)?, vec![(7, 70), (8, 80)]);
+        assert_eq!(state.set_values::<u8>(set)?, vec![1, 2]);
+        assert_eq!(state.map_entries::<u32, u64>(StateItemPrefix(4))?, vec![]);
+        assert!(state.state_box::<u64>(StateItemPrefix(4)).is_none());
+
+        let items = state.items();
+        let prefixes: Vec<_> = items.keys().copied().collect();
+        assert_eq!(prefixes, vec![
+            StateItemPrefix(0),
+            StateItemPrefix(1),
+            StateItemPrefix(2)
+        ]);
+        assert_eq!(items[&map].len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    /// Test that keys are checked to belong to the state item.
+    fn decode_unexpected_key() {
+        let result = decode_map_entries::<u32, u64>(
+            StateItemPrefix(1),
+            [(&[2u8, 0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0][..], &[0u8; 8][..])].into_iter(),
+        );
+        assert!(matches!(result, Err(StateError::UnexpectedKey(_))));
+        let result = decode_set_values::<u8>(StateItemPrefix(1), [&[1u8, 0][..]].into_iter());
+        assert!(matches!(result, Err(StateError::UnexpectedKey(_))));
+    }
+
+    #[test]
+    /// Test comparing two snapshots.
+    fn diff_snapshots() {
+        let old = state();
+        let mut entries = old.entries.clone();
+        let map = StateItemPrefix(1);
+        entries.insert(map.entry_key(&7u32), contracts_common::to_bytes(&71u64));
+        entries.remove(&map.entry_key(&8u32));
+        entries.insert(map.entry_key(&9u32), contracts_common::to_bytes(&90u64));
+        let new = snapshot(entries);
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.changes, vec![
+            StateChange::Changed {
+                key: map.entry_key(&7u32),
+                old: contracts_common::to_bytes(&70u64),
+                new: contracts_common::to_bytes(&71u64),
+            },
+            StateChange::Removed {
+                key:   map.entry_key(&8u32),
+                value: contracts_common::to_bytes(&80u64),
+            },
+            StateChange::Added {
+                key:   map.entry_key(&9u32),
+                value: contracts_common::to_bytes(&90u64),
+            },
+        ]);
+        assert_eq!(diff.item_changes(map).count(), 3);
+        assert_eq!(diff.item_changes(StateItemPrefix(2)).count(), 0);
+        assert!(old.diff(&old).is_empty());
+    }
+}
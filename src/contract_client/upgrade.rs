@@ -0,0 +1,497 @@
+//! Upgrading contract instances to new modules.
+//!
+//! A [`ContractUpgradeBuilder`] is constructed using
+//! [`ContractClient::upgrade`]. It describes the upgrade of an instance to a
+//! new module: the module is deployed unless it already exists on chain, and
+//! the `upgrade` entrypoint of the instance is called with the reference of
+//! the new module and an optional migration call, following the convention of
+//! `concordium-std`. The entrypoint is expected to upgrade the instance and
+//! then invoke the migration entrypoint of the new module.
+//!
+//! [`ContractUpgradeBuilder::dry_run`] dry-runs the whole sequence and checks
+//! that the instance was upgraded to the new module. It produces a
+//! [`SimulatedUpgrade`], which reports the entrypoints and schema types that
+//! differ between the old and new modules, and which can be used to send the
+//! transactions.
+use super::{
+    codegen::entrypoint_schemas, get_embedded_schema, ContractClient, ContractUpdateError,
+    ContractUpdateHandle, ContractUpdateInner, ModuleDeployData, ModuleDeployError,
+    TransactionBuilder,
+};
+use crate::{
+    indexer::ContractUpdateInfo,
+    types::{
+        smart_contracts::{InstanceInfo, ReturnValue},
+        transactions, AccountTransactionEffects, ExecutionTree, RejectReason, TraceV1,
+    },
+    v2::{
+        self,
+        dry_run::{self, DryRunTransaction, TransactionExecuted},
+        BlockIdentifier, QueryError,
+    },
+};
+use concordium_base::{
+    base::Energy,
+    contracts_common::{
+        schema::VersionedModuleSchema, AccountAddress, Amount, ContractAddress, NewReceiveNameError,
+    },
+    smart_contracts::{
+        ContractTraceElement, ExceedsParameterSize, ModuleReference, OwnedEntrypointName,
+        OwnedParameter, OwnedReceiveName, WasmModule,
+    },
+    transactions::{construct::TRANSACTION_HEADER_SIZE, PayloadLike, UpdateContractPayload},
+};
+use std::{collections::BTreeSet, sync::Arc};
+
+#[derive(thiserror::Error, Debug)]
+/// An error that may occur when dry-running an upgrade.
+pub enum DryRunUpgradeError {
+    #[error("Dry run failed: {0}")]
+    DryRun(#[from] dry_run::DryRunError),
+    #[error("Node query error: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Invalid receive name: {0}")]
+    InvalidName(#[from] NewReceiveNameError),
+    #[error("Parameter too large: {0}")]
+    ExceedsParameterSize(#[from] ExceedsParameterSize),
+    #[error("Deploying the module failed due to {0:#?}.")]
+    DeployFailed(RejectReason),
+    #[error("Calling the upgrade entrypoint failed due to {0:#?}.")]
+    UpgradeFailed(RejectReason),
+    #[error("The upgrade entrypoint succeeded, but the instance was not upgraded to {0}.")]
+    NotUpgraded(ModuleReference),
+    #[error("The reported energy consumed for the dry run is less than expected ({min}).")]
+    InvalidEnergy {
+        /// Minimum amount of energy expected
+        min: Energy,
+    },
+}
+
+#[derive(thiserror::Error, Debug)]
+/// An error that may occur when sending an upgrade.
+pub enum ContractUpgradeError {
+    #[error("The status of a transaction could not be ascertained: {0}")]
+    Query(#[from] QueryError),
+    #[error("Module deployment failed: {0}")]
+    Deploy(#[from] ModuleDeployError),
+    #[error("Calling the upgrade entrypoint failed: {0}")]
+    Update(#[from] ContractUpdateError),
+    #[error("The upgrade entrypoint succeeded, but the instance was not upgraded to {0}.")]
+    NotUpgraded(ModuleReference),
+}
+
+/// A change to the schema of an entrypoint that exists in both the old and
+/// the new module.
+#[derive(Debug, Clone)]
+pub struct EntrypointChange {
+    /// The name of the entrypoint.
+    pub entrypoint:   String,
+    /// Whether the parameter type changed.
+    pub parameter:    bool,
+    /// Whether the return value type changed.
+    pub return_value: bool,
+    /// Whether the error type changed.
+    pub error:        bool,
+}
+
+/// The differences between the old and the new module of an upgrade.
+#[derive(Debug, Clone, Default)]
+pub struct SchemaDiff {
+    /// Entrypoints of the new module that the old module does not have.
+    pub added:            Vec<String>,
+    /// Entrypoints of the old module that the new module does not have.
+    pub removed:          Vec<String>,
+    /// Entrypoints whose types changed. This is only computed if both modules
+    /// have embedded schemas.
+    pub changed:          Vec<EntrypointChange>,
+    /// Whether the event type changed.
+    pub event_changed:    bool,
+    /// Whether both modules have embedded schemas, and hence the types were
+    /// compared.
+    pub schemas_compared: bool,
+}
+
+impl SchemaDiff {
+    /// Whether the new module is compatible with the old, i.e., no entrypoint
+    /// was removed and no types were changed. Note that if the types could
+    /// not be compared because a schema is missing, only the entrypoints are
+    /// taken into account.
+    pub fn is_compatible(&self) -> bool {
+        self.removed.is_empty() && self.changed.is_empty() && !self.event_changed
+    }
+
+    fn new(
+        contract_name: &str,
+        old_entrypoints: &BTreeSet<OwnedReceiveName>,
+        new_entrypoints: &BTreeSet<OwnedReceiveName>,
+        old_schema: Option<&VersionedModuleSchema>,
+        new_schema: Option<&VersionedModuleSchema>,
+    ) -> Self {
+        let names = |entrypoints: &BTreeSet<OwnedReceiveName>| -> BTreeSet<String> {
+            entrypoints
+                .iter()
+                .map(|rn| rn.as_receive_name().entrypoint_name().to_string())
+                .collect()
+        };
+        let old_names = names(old_entrypoints);
+        let new_names = names(new_entrypoints);
+        let mut diff = Self {
+            added: new_names.difference(&old_names).cloned().collect(),
+            removed: old_names.difference(&new_names).cloned().collect(),
+            ..Self::default()
+        };
+        let (Some(old_schema), Some(new_schema)) = (old_schema, new_schema) else {
+            return diff;
+        };
+        let (Some(old_types), Some(new_types)) = (
+            entrypoint_schemas(old_schema, contract_name),
+            entrypoint_schemas(new_schema, contract_name),
+        ) else {
+            return diff;
+        };
+        diff.schemas_compared = true;
+        for old in &old_types {
+            let Some(new) = new_types.iter().find(|new| new.name == old.name) else {
+                continue;
+            };
+            let change = EntrypointChange {
+                entrypoint:   old.name.clone(),
+                parameter:    old.parameter != new.parameter,
+                return_value: old.return_value != new.return_value,
+                error:        old.error != new.error,
+            };
+            if change.parameter || change.return_value || change.error {
+                diff.changed.push(change);
+            }
+        }
+        diff.event_changed = old_schema.get_event_schema(contract_name).ok()
+            != new_schema.get_event_schema(contract_name).ok();
+        diff
+    }
+}
+
+/// A description of an upgrade of a contract instance, constructed using
+/// [`ContractClient::upgrade`]. See the [module documentation](self) for an
+/// overview.
+pub struct ContractUpgradeBuilder<Type> {
+    contract:   ContractClient<Type>,
+    sender:     AccountAddress,
+    module:     WasmModule,
+    entrypoint: String,
+    migration:  Option<(OwnedEntrypointName, OwnedParameter)>,
+    parameter:  Option<OwnedParameter>,
+}
+
+impl<Type> ContractClient<Type> {
+    /// Start describing an upgrade of the instance to the module, sent by the
+    /// given account. See [`ContractUpgradeBuilder`].
+    pub fn upgrade(
+        &self,
+        sender: AccountAddress,
+        module: WasmModule,
+    ) -> ContractUpgradeBuilder<Type> {
+        ContractUpgradeBuilder {
+            contract: self.clone(),
+            sender,
+            module,
+            entrypoint: "upgrade".into(),
+            migration: None,
+            parameter: None,
+        }
+    }
+}
+
+impl<Type> ContractUpgradeBuilder<Type> {
+    /// Set the entrypoint of the instance that performs the upgrade. Defaults
+    /// to `upgrade`.
+    pub fn set_upgrade_entrypoint(self, entrypoint: impl Into<String>) -> Self {
+        Self {
+            entrypoint: entrypoint.into(),
+            ..self
+        }
+    }
+
+    /// Set the entrypoint of the new module to invoke after upgrading, and its
+    /// parameter.
+    pub fn set_migration(self, entrypoint: OwnedEntrypointName, parameter: OwnedParameter) -> Self {
+        Self {
+            migration: Some((entrypoint, parameter)),
+            ..self
+        }
+    }
+
+    /// Set the parameter of the upgrade entrypoint. By default the parameter
+    /// is the module reference followed by the optional migration call, as
+    /// expected by the upgrade entrypoints of `concordium-std` examples. This
+    /// overrides any migration set with [`set_migration`](Self::set_migration).
+    pub fn set_upgrade_parameter(self, parameter: OwnedParameter) -> Self {
+        Self {
+            parameter: Some(parameter),
+            ..self
+        }
+    }
+
+    /// Dry-run the deployment of the module and the upgrade in the state of
+    /// the last finalized block, and check that the instance is upgraded to
+    /// the new module.
+    pub async fn dry_run(self) -> Result<SimulatedUpgrade<Type>, DryRunUpgradeError> {
+        let mut client = self.contract.client.clone();
+        let address = self.contract.address;
+        let contract_name = self
+            .contract
+            .contract_name
+            .as_contract_name()
+            .contract_name();
+        let module_ref = self.module.get_module_ref();
+        let new_schema = get_embedded_schema(&self.module);
+
+        let old_info = client
+            .get_instance_info(address, BlockIdentifier::LastFinal)
+            .await?;
+        let block = old_info.block_hash;
+        let old_info = old_info.response;
+        let old_schema = match &*self.contract.schema {
+            Some(schema) => Some(schema.clone()),
+            None => {
+                let source = client
+                    .get_module_source(&old_info.source_module(), block)
+                    .await?
+                    .response;
+                get_embedded_schema(&source)
+            }
+        };
+
+        let mut dry_run = client.dry_run(block).await?.inner.0;
+        let quota = dry_run.energy_quota();
+
+        // Deploy the module, unless it already exists.
+        let deploy_payload = transactions::Payload::DeployModule {
+            module: self.module,
+        };
+        let deploy = dry_run
+            .run_transaction(DryRunTransaction::new(self.sender, quota, &deploy_payload))
+            .await?
+            .inner;
+        let deploy_energy = match &deploy.details.effects {
+            AccountTransactionEffects::ModuleDeployed { .. } => {
+                Some(execution_energy(&deploy, &deploy_payload)?)
+            }
+            AccountTransactionEffects::None {
+                reject_reason: RejectReason::ModuleHashAlreadyExists { .. },
+                ..
+            } => None,
+            AccountTransactionEffects::None { reject_reason, .. } => {
+                return Err(DryRunUpgradeError::DeployFailed(reject_reason.clone()))
+            }
+            _ => {
+                return Err(
+                    dry_run::DryRunError::CallError(tonic::Status::invalid_argument(
+                        "Unexpected response from dry-running a module deployment.",
+                    ))
+                    .into(),
+                )
+            }
+        };
+
+        let parameter = match self.parameter {
+            Some(parameter) => parameter,
+            None => OwnedParameter::from_serial(&(module_ref, self.migration))?,
+        };
+        let receive_name =
+            OwnedReceiveName::try_from(format!("{contract_name}.{}", self.entrypoint))?;
+        let update_payload = transactions::Payload::Update {
+            payload: UpdateContractPayload {
+                amount: Amount::zero(),
+                address,
+                receive_name,
+                message: parameter,
+            },
+        };
+        let update = dry_run
+            .run_transaction(DryRunTransaction::new(self.sender, quota, &update_payload))
+            .await?
+            .inner;
+        let (trace, return_value) = match &update.details.effects {
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                (effects.clone(), update.return_value.clone())
+            }
+            AccountTransactionEffects::None { reject_reason, .. } => {
+                return Err(DryRunUpgradeError::UpgradeFailed(reject_reason.clone()))
+            }
+            _ => {
+                return Err(
+                    dry_run::DryRunError::CallError(tonic::Status::invalid_argument(
+                        "Unexpected response from dry-running a contract update.",
+                    ))
+                    .into(),
+                )
+            }
+        };
+        let upgraded = trace.iter().any(|element| match element {
+            ContractTraceElement::Upgraded { address: a, to, .. } => {
+                *a == address && *to == module_ref
+            }
+            _ => false,
+        });
+        if !upgraded {
+            return Err(DryRunUpgradeError::NotUpgraded(module_ref));
+        }
+        let update_energy = execution_energy(&update, &update_payload)?;
+
+        let new_info = dry_run.get_instance_info(&address).await?.inner;
+        dry_run.close();
+        let diff = SchemaDiff::new(
+            contract_name,
+            old_info.entrypoints(),
+            new_info.entrypoints(),
+            old_schema.as_ref(),
+            new_schema.as_ref(),
+        );
+
+        Ok(SimulatedUpgrade {
+            contract: self.contract,
+            sender: self.sender,
+            module_ref,
+            deploy: deploy_energy.map(|energy| (deploy_payload, energy)),
+            update: (update_payload, update_energy),
+            new_schema,
+            trace,
+            return_value: return_value.map(|rv| ReturnValue { value: rv }),
+            new_info,
+            diff,
+        })
+    }
+}
+
+/// The energy used by executing the dry-run transaction, excluding the base
+/// cost of the transaction.
+fn execution_energy(
+    executed: &TransactionExecuted,
+    payload: &transactions::Payload,
+) -> Result<Energy, DryRunUpgradeError> {
+    let base_cost = transactions::cost::base_cost(
+        TRANSACTION_HEADER_SIZE + u64::from(u32::from(payload.encode().size())),
+        1,
+    );
+    executed
+        .energy_cost
+        .checked_sub(base_cost)
+        .ok_or(DryRunUpgradeError::InvalidEnergy { min: base_cost })
+}
+
+/// The result of dry-running an upgrade with
+/// [`ContractUpgradeBuilder::dry_run`].
+pub struct SimulatedUpgrade<Type> {
+    contract:     ContractClient<Type>,
+    sender:       AccountAddress,
+    module_ref:   ModuleReference,
+    deploy:       Option<(transactions::Payload, Energy)>,
+    update:       (transactions::Payload, Energy),
+    new_schema:   Option<VersionedModuleSchema>,
+    trace:        Vec<ContractTraceElement>,
+    return_value: Option<ReturnValue>,
+    new_info:     InstanceInfo,
+    diff:         SchemaDiff,
+}
+
+/// The result of sending an upgrade with [`SimulatedUpgrade::send`].
+pub struct ContractUpgradeData<Type> {
+    /// The result of deploying the module, if it was deployed.
+    pub deploy:   Option<ModuleDeployData>,
+    /// The result of calling the upgrade entrypoint.
+    pub update:   ContractUpdateInfo,
+    /// A client for the upgraded instance, using the schema of the new
+    /// module.
+    pub contract: ContractClient<Type>,
+}
+
+impl<Type> SimulatedUpgrade<Type> {
+    /// The reference of the new module.
+    pub fn module_ref(&self) -> ModuleReference { self.module_ref }
+
+    /// Whether the module has to be deployed, i.e., it does not exist on chain
+    /// yet.
+    pub fn deploys_module(&self) -> bool { self.deploy.is_some() }
+
+    /// The differences between the old and the new module.
+    pub fn diff(&self) -> &SchemaDiff { &self.diff }
+
+    /// The trace of calling the upgrade entrypoint, including the migration.
+    pub fn trace(&self) -> &[ContractTraceElement] { &self.trace }
+
+    /// The return value of the upgrade entrypoint.
+    pub fn return_value(&self) -> Option<&ReturnValue> { self.return_value.as_ref() }
+
+    /// The instance as it is after the upgrade.
+    pub fn instance_info(&self) -> &InstanceInfo { &self.new_info }
+
+    /// The energy used by deploying the module and by calling the upgrade
+    /// entrypoint, excluding the base cost of the transactions.
+    pub fn energy(&self) -> (Option<Energy>, Energy) {
+        (
+            self.deploy.as_ref().map(|(_, energy)| *energy),
+            self.update.1,
+        )
+    }
+
+    /// Send the transactions. The module is deployed first if needed, and the
+    /// upgrade entrypoint is called once the deployment is finalized. This
+    /// waits until both transactions are finalized, which can potentially
+    /// take indefinitely, and checks that the instance was upgraded.
+    pub async fn send(
+        self,
+        signer: &impl transactions::ExactSizeTransactionSigner,
+    ) -> Result<ContractUpgradeData<Type>, ContractUpgradeError> {
+        let client = self.contract.client.clone();
+        let deploy = match self.deploy {
+            Some((payload, energy)) => {
+                let builder: super::ModuleDeployBuilder = TransactionBuilder::new(
+                    client.clone(),
+                    self.sender,
+                    energy,
+                    payload,
+                    self.module_ref,
+                );
+                Some(builder.send(signer).await?.wait_for_finalization().await?)
+            }
+            None => None,
+        };
+        let (payload, energy) = self.update;
+        let builder: super::ContractUpdateBuilder =
+            TransactionBuilder::new(client, self.sender, energy, payload, ContractUpdateInner {
+                return_value:      self.return_value,
+                return_value_json: None,
+                events:            self.trace,
+            });
+        let handle: ContractUpdateHandle = builder.send(signer).await?;
+        let update = handle.wait_for_finalization().await?;
+        if !upgraded_to(
+            &update.execution_tree,
+            self.contract.address,
+            self.module_ref,
+        ) {
+            return Err(ContractUpgradeError::NotUpgraded(self.module_ref));
+        }
+        let contract = ContractClient {
+            schema: Arc::new(self.new_schema),
+            ..self.contract
+        };
+        Ok(ContractUpgradeData {
+            deploy,
+            update,
+            contract,
+        })
+    }
+}
+
+/// Check whether the execution tree upgrades the instance to the module.
+fn upgraded_to(tree: &ExecutionTree, address: ContractAddress, module: ModuleReference) -> bool {
+    let ExecutionTree::V1(tree) = tree else {
+        return false;
+    };
+    tree.events.iter().any(|event| match event {
+        TraceV1::Upgrade { to, .. } => tree.address == address && *to == module,
+        TraceV1::Call { call } => upgraded_to(call, address, module),
+        _ => false,
+    })
+}
@@ -4,21 +4,28 @@
 //! which will start chain traversal, calling methods of the [`Indexer`] trait
 //! for each finalized block it discovers.
 use crate::{
+    contract_client::get_embedded_schema,
     types::{
-        execution_tree, queries::BlockInfo, AccountTransactionEffects, BlockItemSummary,
-        BlockItemSummaryDetails, ExecutionTree, SpecialTransactionOutcome,
+        execution_tree, queries::BlockInfo, AccountInfo, AccountTransactionEffects,
+        BlockItemSummary, BlockItemSummaryDetails, Cooldown, CooldownStatus, ExecutionTree, Memo,
+        ProtocolVersion, Release, SpecialTransactionOutcome, TraceV0, TraceV1, TransactionType,
     },
-    v2::{self, FinalizedBlockInfo, QueryError, QueryResult},
+    v2::{self, AccountIdentifier, BlockIdentifier, FinalizedBlockInfo, QueryError, QueryResult},
 };
 use concordium_base::{
     base::{AbsoluteBlockHeight, Energy},
-    contracts_common::{AccountAddress, Amount, ContractAddress, OwnedEntrypointName},
-    hashes::TransactionHash,
-    smart_contracts::OwnedReceiveName,
+    contracts_common::{
+        schema::{self, VersionedModuleSchema},
+        AccountAddress, Address, Amount, ContractAddress, Cursor, OwnedEntrypointName,
+    },
+    hashes::{BlockHash, TransactionHash},
+    smart_contracts::{ContractTraceElement, ModuleReference, OwnedContractName, OwnedReceiveName},
+    transactions::{BlockItem, Payload},
 };
 use futures::{stream::FuturesOrdered, StreamExt, TryStreamExt as _};
 use std::{
     collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Mutex},
     time::Duration,
 };
 use tokio::time::error::Elapsed;
@@ -43,6 +50,44 @@ pub enum TraverseError {
     Query(#[from] QueryError),
     #[error("Timed out waiting for finalized blocks.")]
     Elapsed(#[from] Elapsed),
+    #[error("Inconsistent chain: {0}")]
+    Inconsistent(#[from] ChainInconsistency),
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+/// An inconsistency in the finalized chain detected during chain traversal
+/// by [`TraverseConfig::traverse_verified`].
+pub enum ChainInconsistency {
+    #[error(
+        "Block {block} at height {height} has parent {actual_parent}, but the previously \
+         delivered block is {expected_parent}."
+    )]
+    ParentMismatch {
+        height:          AbsoluteBlockHeight,
+        block:           BlockHash,
+        expected_parent: BlockHash,
+        actual_parent:   BlockHash,
+    },
+    #[error("Expected the next finalized block at height {expected}, but got {actual}.")]
+    UnexpectedHeight {
+        expected: AbsoluteBlockHeight,
+        actual:   AbsoluteBlockHeight,
+    },
+    #[error("Block {block} at height {height} is not finalized.")]
+    NotFinalized {
+        height: AbsoluteBlockHeight,
+        block:  BlockHash,
+    },
+    #[error(
+        "Endpoint {endpoint} reports block {actual} as finalized at height {height}, but block \
+         {expected} was previously delivered at that height."
+    )]
+    EndpointDisagreement {
+        endpoint: String,
+        height:   AbsoluteBlockHeight,
+        expected: BlockHash,
+        actual:   BlockHash,
+    },
 }
 
 #[async_trait]
@@ -219,9 +264,47 @@ impl TraverseConfig {
     /// [`tokio::spawn`].
     pub async fn traverse<I: Indexer>(
         self,
-        mut indexer: I,
+        indexer: I,
         sender: tokio::sync::mpsc::Sender<I::Data>,
     ) -> QueryResult<()> {
+        match self.traverse_with(indexer, sender, false).await {
+            Ok(()) => Ok(()),
+            Err(err) => unreachable!("Inconsistency {err} detected without verification."),
+        }
+    }
+
+    /// Like [`traverse`](Self::traverse), but verify the consistency of the
+    /// finalized chain during traversal.
+    ///
+    /// The block information of each finalized block is queried and it is
+    /// checked that the block is finalized at the expected height, and that
+    /// its parent is the previously delivered block. Parents of genesis
+    /// blocks, i.e., blocks at the start of a new era after a protocol update,
+    /// are not checked. When connecting to a new endpoint it is checked that
+    /// the endpoint agrees on the previously delivered block.
+    ///
+    /// If any inconsistency is detected, it is passed to
+    /// [`on_failure`](Indexer::on_failure) as a
+    /// [`TraverseError::Inconsistent`] error and logged on `error` level, after
+    /// which the traversal stops and the inconsistency is returned, regardless
+    /// of the return value of [`on_failure`](Indexer::on_failure).
+    pub async fn traverse_verified<I: Indexer>(
+        self,
+        indexer: I,
+        sender: tokio::sync::mpsc::Sender<I::Data>,
+    ) -> Result<(), ChainInconsistency> {
+        self.traverse_with(indexer, sender, true).await
+    }
+
+    /// The implementation of [`traverse`](Self::traverse) and
+    /// [`traverse_verified`](Self::traverse_verified). An inconsistency can
+    /// only be returned if `verify_chain` is set.
+    async fn traverse_with<I: Indexer>(
+        self,
+        mut indexer: I,
+        sender: tokio::sync::mpsc::Sender<I::Data>,
+        verify_chain: bool,
+    ) -> Result<(), ChainInconsistency> {
         let TraverseConfig {
             endpoints,
             max_parallel,
@@ -230,6 +313,8 @@ impl TraverseConfig {
             start_height: mut height,
         } = self;
         let mut successive_failures: u64 = 0;
+        // The last block that was delivered, used for verifying consistency.
+        let mut last_block: Option<(AbsoluteBlockHeight, BlockHash)> = None;
         for node_ep in endpoints.into_iter().cycle() {
             if sender.is_closed() {
                 return Ok(());
@@ -266,6 +351,27 @@ impl TraverseConfig {
                     }
                 }
             };
+            if let (true, Some((last_height, last_hash))) = (verify_chain, last_block) {
+                let block_hash = match node.get_block_info(last_height).await {
+                    Ok(v) => v.response.block_hash,
+                    Err(e) => {
+                        successive_failures += 1;
+                        let should_stop = indexer
+                            .on_failure(node_ep, successive_failures, e.into())
+                            .await;
+                        if should_stop {
+                            return Ok(());
+                        } else {
+                            continue;
+                        }
+                    }
+                };
+                if let Err(err) = verify_endpoint(&node_ep, last_height, last_hash, block_hash) {
+                    return fail_inconsistent(&mut indexer, node_ep, successive_failures, err)
+                        .await;
+                }
+            }
+
             let mut finalized_blocks = match node.get_finalized_blocks_from(height).await {
                 Ok(v) => v,
                 Err(e) => {
@@ -303,10 +409,22 @@ impl TraverseConfig {
 
                 let mut futs = FuturesOrdered::new();
                 for fb in chunks {
-                    futs.push_back(indexer.on_finalized(node.clone(), &context, fb));
+                    let mut client = node.clone();
+                    let data = indexer.on_finalized(node.clone(), &context, fb);
+                    futs.push_back(async move {
+                        let info = async move {
+                            if !verify_chain {
+                                return Ok(None);
+                            }
+                            let info = client.get_block_info(fb.block_hash).await?.response;
+                            QueryResult::Ok(Some(info))
+                        };
+                        let (info, data) = futures::try_join!(info, data)?;
+                        Ok::<_, QueryError>((fb, info, data))
+                    });
                 }
                 while let Some(data) = futs.next().await {
-                    let data = match data {
+                    let (fb, info, data) = match data {
                         Ok(v) => v,
                         Err(e) => {
                             drop(futs);
@@ -321,11 +439,24 @@ impl TraverseConfig {
                             }
                         }
                     };
+                    if let Some(info) = info {
+                        if let Err(err) = verify_block(height, last_block, &fb, &info) {
+                            drop(futs);
+                            return fail_inconsistent(
+                                &mut indexer,
+                                node_ep,
+                                successive_failures,
+                                err,
+                            )
+                            .await;
+                        }
+                    }
                     if sender.send(data).await.is_err() {
                         return Ok(()); // the listener ended the stream, meaning
                                        // we
                                        // should stop.
                     }
+                    last_block = Some((fb.height, fb.block_hash));
                     height = height.next();
                 }
 
@@ -342,6 +473,251 @@ impl TraverseConfig {
         }
         Ok(()) // unreachable
     }
+
+    /// Traverse the chain according to the supplied configuration, and return
+    /// the data produced by [`on_finalized`](Indexer::on_finalized) for each
+    /// finalized block as a [`Stream`](futures::Stream).
+    ///
+    /// The traversal is performed by a background task spawned via
+    /// [`tokio::spawn`], in the same way as by [`traverse`](Self::traverse).
+    /// The task only runs ahead of the consumer of the stream by a bounded
+    /// number of blocks, equal to the maximum number of blocks queried in
+    /// parallel. Dropping the stream stops the traversal.
+    ///
+    /// Note that in contrast to [`traverse`](Self::traverse) the
+    /// [`on_failure`](Indexer::on_failure) method of the indexer is not called.
+    /// Instead failures are returned as [`Err`] items of the stream, after
+    /// which the traversal continues with the next endpoint. The consumer of
+    /// the stream may decide to stop by dropping the stream.
+    ///
+    /// ```no_run
+    /// # use concordium_rust_sdk::{indexer::*, v2};
+    /// # use futures::TryStreamExt;
+    /// # async fn example(config: TraverseConfig) -> anyhow::Result<()> {
+    /// let mut blocks = config.into_stream(FnIndexer::new(
+    ///     |mut client: v2::Client, fbi: v2::FinalizedBlockInfo| async move {
+    ///         let block_info = client.get_block_info(fbi.height).await?.response;
+    ///         v2::QueryResult::Ok(block_info)
+    ///     },
+    /// ));
+    /// while let Some(block_info) = blocks.try_next().await? {
+    ///     println!(
+    ///         "{} at height {}",
+    ///         block_info.block_hash, block_info.block_height
+    ///     );
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_stream<I>(self, indexer: I) -> TraverseStream<I::Data>
+    where
+        I: Indexer + Send + 'static, {
+        let (sender, receiver) = tokio::sync::mpsc::channel(self.max_parallel.max(1));
+        let indexer = StreamIndexer {
+            inner:  indexer,
+            sender: sender.clone(),
+        };
+        let handle = tokio::spawn(self.traverse(indexer, sender));
+        TraverseStream { handle, receiver }
+    }
+}
+
+/// Check that the endpoint reports the same block at the height of the last
+/// delivered block.
+fn verify_endpoint(
+    endpoint: &v2::Endpoint,
+    height: AbsoluteBlockHeight,
+    expected: BlockHash,
+    actual: BlockHash,
+) -> Result<(), ChainInconsistency> {
+    if actual != expected {
+        return Err(ChainInconsistency::EndpointDisagreement {
+            endpoint: endpoint.uri().to_string(),
+            height,
+            expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Check that the block is the expected next block in the finalized chain.
+fn verify_block(
+    height: AbsoluteBlockHeight,
+    last_block: Option<(AbsoluteBlockHeight, BlockHash)>,
+    fb: &FinalizedBlockInfo,
+    info: &BlockInfo,
+) -> Result<(), ChainInconsistency> {
+    if fb.height != height || info.block_height != height {
+        return Err(ChainInconsistency::UnexpectedHeight {
+            expected: height,
+            actual:   if fb.height != height {
+                fb.height
+            } else {
+                info.block_height
+            },
+        });
+    }
+    if !info.finalized || info.block_hash != fb.block_hash {
+        return Err(ChainInconsistency::NotFinalized {
+            height,
+            block: fb.block_hash,
+        });
+    }
+    if let Some((_, expected_parent)) = last_block {
+        if info.era_block_height.height != 0 && info.block_parent != expected_parent {
+            return Err(ChainInconsistency::ParentMismatch {
+                height,
+                block: fb.block_hash,
+                expected_parent,
+                actual_parent: info.block_parent,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Report the inconsistency to the indexer and construct the error that is
+/// returned from [`TraverseConfig::traverse_verified`].
+async fn fail_inconsistent<I: Indexer>(
+    indexer: &mut I,
+    endpoint: v2::Endpoint,
+    successive_failures: u64,
+    err: ChainInconsistency,
+) -> Result<(), ChainInconsistency> {
+    tracing::error!(
+        target: "ccd_indexer",
+        "Chain inconsistency detected when querying endpoint {}: {err}",
+        endpoint.uri()
+    );
+    indexer
+        .on_failure(endpoint, successive_failures + 1, err.clone().into())
+        .await;
+    Err(err)
+}
+
+/// A stream of data produced by an [`Indexer`] for each finalized block, in
+/// increasing order of block height. This is constructed by
+/// [`TraverseConfig::into_stream`]. It contains a background task that
+/// traverses the chain. The task is stopped when the stream is dropped.
+pub struct TraverseStream<Data> {
+    handle:   tokio::task::JoinHandle<QueryResult<()>>,
+    receiver: tokio::sync::mpsc::Receiver<Result<Data, TraverseError>>,
+}
+
+// Make sure to abort the background task so that those resources are cleaned up
+// before we drop the handle.
+impl<Data> Drop for TraverseStream<Data> {
+    fn drop(&mut self) { self.handle.abort(); }
+}
+
+impl<Data> futures::Stream for TraverseStream<Data> {
+    type Item = Result<Data, TraverseError>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.get_mut().receiver.poll_recv(cx)
+    }
+}
+
+/// An indexer that wraps another indexer, and reports failures as items of a
+/// [`TraverseStream`], instead of passing them to the inner indexer.
+struct StreamIndexer<I: Indexer> {
+    inner:  I,
+    sender: tokio::sync::mpsc::Sender<Result<I::Data, TraverseError>>,
+}
+
+#[async_trait]
+impl<I: Indexer + Send> Indexer for StreamIndexer<I> {
+    type Context = I::Context;
+    type Data = Result<I::Data, TraverseError>;
+
+    async fn on_connect<'a>(
+        &mut self,
+        endpoint: v2::Endpoint,
+        client: &'a mut v2::Client,
+    ) -> QueryResult<Self::Context> {
+        self.inner.on_connect(endpoint, client).await
+    }
+
+    async fn on_finalized<'a>(
+        &self,
+        client: v2::Client,
+        ctx: &'a Self::Context,
+        fbi: FinalizedBlockInfo,
+    ) -> QueryResult<Self::Data> {
+        self.inner.on_finalized(client, ctx, fbi).await.map(Ok)
+    }
+
+    async fn on_failure(
+        &mut self,
+        _endpoint: v2::Endpoint,
+        _successive_failures: u64,
+        err: TraverseError,
+    ) -> bool {
+        // Stop if the stream has been dropped.
+        self.sender.send(Err(err)).await.is_err()
+    }
+}
+
+/// An indexer that calls the given function for each finalized block. This is
+/// useful for simple indexers that do not need any context, in particular
+/// together with [`TraverseConfig::into_stream`].
+///
+/// The [`on_connect`](Indexer::on_connect) and
+/// [`on_failure`](Indexer::on_failure) methods of the [`Indexer`] trait only
+/// log the events on `info` and `warn` levels, respectively, using the
+/// [`tracing`](https://docs.rs/tracing/latest/tracing/) crate. The [target](https://docs.rs/tracing/latest/tracing/struct.Metadata.html#method.target)
+/// of the log is `ccd_indexer` which may be used to filter the logs.
+pub struct FnIndexer<F> {
+    on_finalized: F,
+}
+
+impl<F> FnIndexer<F> {
+    /// Construct an indexer that calls `on_finalized` for each finalized
+    /// block.
+    pub fn new(on_finalized: F) -> Self { Self { on_finalized } }
+}
+
+#[async_trait]
+impl<F, Fut, A> Indexer for FnIndexer<F>
+where
+    F: Fn(v2::Client, FinalizedBlockInfo) -> Fut + Send + Sync,
+    Fut: std::future::Future<Output = QueryResult<A>> + Send + 'static,
+    A: Send + Sync,
+{
+    type Context = ();
+    type Data = A;
+
+    async fn on_connect<'a>(
+        &mut self,
+        endpoint: v2::Endpoint,
+        client: &'a mut v2::Client,
+    ) -> QueryResult<()> {
+        TransactionIndexer.on_connect(endpoint, client).await
+    }
+
+    async fn on_finalized<'a>(
+        &self,
+        client: v2::Client,
+        _ctx: &'a (),
+        fbi: FinalizedBlockInfo,
+    ) -> QueryResult<Self::Data> {
+        (self.on_finalized)(client, fbi).await
+    }
+
+    async fn on_failure(
+        &mut self,
+        endpoint: v2::Endpoint,
+        successive_failures: u64,
+        err: TraverseError,
+    ) -> bool {
+        TransactionIndexer
+            .on_failure(endpoint, successive_failures, err)
+            .await
+    }
 }
 
 /// An indexer that retrieves all transaction outcomes.
@@ -672,121 +1048,1233 @@ impl Indexer for BlockEventsIndexer {
     }
 }
 
-#[async_trait]
-/// Handle an individual event. This trait is designed to be used together with
-/// the [`ProcessorConfig`]. These two together are designed to ease the work of
-/// writing the part of indexers where data is to be stored in a database.
-pub trait ProcessEvent {
-    /// The type of events that are to be processed. Typically this will be all
-    /// of the transactions of interest for a single block.
-    type Data;
-    /// An error that can be signalled.
-    type Error: std::fmt::Display + std::fmt::Debug;
-    /// A description returned by the [`process`](ProcessEvent::process) method.
-    /// This message is logged by the [`ProcessorConfig`] and is intended to
-    /// describe the data that was just processed.
-    type Description: std::fmt::Display;
+/// A value logged or passed to a contract, together with its JSON
+/// representation obtained using the schema embedded in the contract module.
+#[derive(Debug, Clone)]
+pub struct DecodedValue {
+    /// The raw bytes of the value, as they appear on the chain.
+    pub raw:     Vec<u8>,
+    /// The value decoded using the schema. This is [`None`] if the module
+    /// does not have an embedded schema, if the schema does not contain a type
+    /// for the value, or if the value could not be decoded using the type.
+    pub decoded: Option<serde_json::Value>,
+}
 
-    /// Process a single item. This should work atomically in the sense that
-    /// either the entire `data` is processed or none of it is in case of an
-    /// error. This property is relied upon by the [`ProcessorConfig`] to retry
-    /// failed attempts.
-    async fn process(&mut self, data: &Self::Data) -> Result<Self::Description, Self::Error>;
+impl DecodedValue {
+    pub(crate) fn new(raw: impl AsRef<[u8]>) -> Self {
+        Self {
+            raw:     raw.as_ref().to_vec(),
+            decoded: None,
+        }
+    }
 
-    /// The `on_failure` method is invoked by the [`ProcessorConfig`] when it
-    /// fails to process an event. It is meant to retry to recreate the
-    /// resources, such as a database connection, that might have been
-    /// dropped. The return value should signal if the handler process
-    /// should continue (`true`) or not.
-    ///
-    /// The function takes the `error` that occurred at the latest
-    /// [`process`](Self::process) call that just failed, and the number of
-    /// attempts of calling `process` that failed.
-    async fn on_failure(
-        &mut self,
-        error: Self::Error,
-        failed_attempts: u32,
-    ) -> Result<bool, Self::Error>;
+    pub(crate) fn decode_with(&mut self, ty: Option<schema::Type>) {
+        self.decoded = ty.and_then(|ty| ty.to_json(&mut Cursor::new(&self.raw)).ok());
+    }
 }
 
-pub struct ProcessorConfig {
-    /// The amount of time to wait after a failure to process an event.
-    wait_after_fail: std::time::Duration,
-    /// A future to be signalled to stop processing.
-    stop:            std::pin::Pin<Box<dyn std::future::Future<Output = ()>>>,
+/// An interaction with a contract instance as reported by the
+/// [`SchemaEventIndexer`].
+#[derive(Debug, Clone)]
+pub struct SchemaEventRecord {
+    /// The hash of the transaction from which this record stems.
+    pub transaction_hash: TransactionHash,
+    /// The sender of the transaction.
+    pub sender:           AccountAddress,
+    /// The module the instance was running at the time of the interaction.
+    /// This is the module whose schema was used for decoding.
+    pub module_reference: ModuleReference,
+    /// The details of the interaction.
+    pub details:          SchemaEventDetails,
 }
 
-/// The default implementation behaves the same as
-/// [`ProcessorConfig::new`](ProcessorConfig::new).
-impl Default for ProcessorConfig {
-    fn default() -> Self { Self::new() }
+/// The details of a [`SchemaEventRecord`].
+#[derive(Debug, Clone)]
+pub enum SchemaEventDetails {
+    /// The instance was initialized.
+    Initialized {
+        /// The amount the instance was initialized with.
+        amount:    Amount,
+        /// The parameter to the `init` function.
+        parameter: DecodedValue,
+        /// The events logged by the `init` function.
+        events:    Vec<DecodedValue>,
+    },
+    /// An entrypoint of the instance was invoked, either directly by the
+    /// transaction, or by another contract.
+    Updated {
+        /// The account or contract that invoked the entrypoint.
+        instigator: Address,
+        /// The amount the entrypoint was invoked with.
+        amount:     Amount,
+        /// The name of the entrypoint that was invoked.
+        entrypoint: OwnedEntrypointName,
+        /// The parameter the entrypoint was invoked with.
+        parameter:  DecodedValue,
+        /// The events logged by the entrypoint. These are only the events
+        /// logged by this invocation, and not by any nested invocations of the
+        /// same instance. Those are reported as separate records.
+        events:     Vec<DecodedValue>,
+    },
 }
 
-impl ProcessorConfig {
-    /// After each failure the [`ProcessorConfig`] will pause a bit before
-    /// trying again. Defaults to 5 seconds.
-    pub fn set_wait_after_failure(self, wait_after_fail: Duration) -> Self {
+/// An indexer that retrieves all interactions with a specific contract
+/// instance, and decodes parameters and logged events using the schema
+/// embedded in the instance's module, in the same way that
+/// [`ContractClient::create`](crate::contract_client::ContractClient::create)
+/// does.
+///
+/// Each initialization of the instance, and each invocation of any of its
+/// entrypoints, including nested invocations by other contracts, result in one
+/// [`SchemaEventRecord`]. Instance upgrades are taken into account, so that
+/// each record is decoded using the schema of the module the instance was
+/// running at the time. Schemas are cached by module reference.
+///
+/// The [`on_connect`](Indexer::on_connect) and
+/// [`on_failure`](Indexer::on_failure) methods of the [`Indexer`] trait only
+/// log the events on `info` and `warn` levels, respectively, using the
+/// [`tracing`](https://docs.rs/tracing/latest/tracing/) crate. The [target](https://docs.rs/tracing/latest/tracing/struct.Metadata.html#method.target)
+/// of the log is `ccd_indexer` which may be used to filter the logs.
+pub struct SchemaEventIndexer {
+    pub target_address: ContractAddress,
+    schemas:            Mutex<BTreeMap<ModuleReference, Arc<Option<VersionedModuleSchema>>>>,
+}
+
+impl SchemaEventIndexer {
+    /// Construct an indexer for the given contract instance.
+    pub fn new(target_address: ContractAddress) -> Self {
         Self {
-            wait_after_fail,
-            ..self
+            target_address,
+            schemas: Mutex::new(BTreeMap::new()),
         }
     }
 
-    /// Set the stop signal for the processor. This accepts a future which will
-    /// be polled and if the future yields ready the `process_events` method
-    /// will terminate.
-    ///
-    /// An example of such a future would be the `Receiver` end of a oneshot
-    /// channel.
-    pub fn set_stop_signal(self, stop: impl std::future::Future<Output = ()> + 'static) -> Self {
-        Self {
-            stop: Box::pin(stop),
-            ..self
+    /// Get the schema embedded in the given module, either from the cache, or
+    /// by querying the node.
+    async fn get_schema(
+        &self,
+        client: &mut v2::Client,
+        module_reference: ModuleReference,
+        height: AbsoluteBlockHeight,
+    ) -> QueryResult<Arc<Option<VersionedModuleSchema>>> {
+        let cached = self
+            .schemas
+            .lock()
+            .expect("Schema cache is not poisoned.")
+            .get(&module_reference)
+            .cloned();
+        if let Some(schema) = cached {
+            return Ok(schema);
         }
+        let module = client
+            .get_module_source(&module_reference, height)
+            .await?
+            .response;
+        let schema = Arc::new(get_embedded_schema(&module));
+        self.schemas
+            .lock()
+            .expect("Schema cache is not poisoned.")
+            .insert(module_reference, schema.clone());
+        Ok(schema)
     }
+}
 
-    /// Construct a new [`ProcessorConfig`] that will retry the given number of
-    /// times. The default wait after a failure is 5 seconds.
-    pub fn new() -> Self {
-        Self {
-            wait_after_fail: std::time::Duration::from_secs(5),
-            stop:            Box::pin(std::future::pending()),
+/// Add a record for each invocation of the `target` instance in the execution
+/// tree, in the order the invocations occurred. The `module_reference` is the
+/// module of the `target` instance at the start of the execution, and it is
+/// updated if the instance is upgraded.
+fn schema_update_records(
+    tree: &ExecutionTree,
+    target: ContractAddress,
+    module_reference: &mut ModuleReference,
+    transaction_hash: TransactionHash,
+    sender: AccountAddress,
+    records: &mut Vec<SchemaEventRecord>,
+) {
+    let mut record = |instigator, amount, receive_name: &OwnedReceiveName, message| {
+        records.push(SchemaEventRecord {
+            transaction_hash,
+            sender,
+            module_reference: *module_reference,
+            details: SchemaEventDetails::Updated {
+                instigator,
+                amount,
+                entrypoint: receive_name.as_receive_name().entrypoint_name().to_owned(),
+                parameter: DecodedValue::new(message),
+                events: Vec::new(),
+            },
+        });
+        records.len() - 1
+    };
+    match tree {
+        ExecutionTree::V0(v0) => {
+            let top_level = &v0.top_level;
+            if top_level.address == target {
+                let idx = record(
+                    top_level.instigator,
+                    top_level.amount,
+                    &top_level.receive_name,
+                    &top_level.message,
+                );
+                if let SchemaEventDetails::Updated { events, .. } = &mut records[idx].details {
+                    events.extend(top_level.events.iter().map(DecodedValue::new));
+                }
+            }
+            for rest in &v0.rest {
+                if let TraceV0::Call(call) = rest {
+                    schema_update_records(
+                        call,
+                        target,
+                        module_reference,
+                        transaction_hash,
+                        sender,
+                        records,
+                    );
+                }
+            }
+        }
+        ExecutionTree::V1(v1) => {
+            let idx = (v1.address == target)
+                .then(|| record(v1.instigator, v1.amount, &v1.receive_name, &v1.message));
+            for event in &v1.events {
+                match event {
+                    TraceV1::Events { events: logged } => {
+                        if let Some(idx) = idx {
+                            if let SchemaEventDetails::Updated { events, .. } =
+                                &mut records[idx].details
+                            {
+                                events.extend(logged.iter().map(DecodedValue::new));
+                            }
+                        }
+                    }
+                    TraceV1::Call { call } => schema_update_records(
+                        call,
+                        target,
+                        module_reference,
+                        transaction_hash,
+                        sender,
+                        records,
+                    ),
+                    TraceV1::Transfer { .. } => (),
+                    // The upgrade takes effect when the current invocation
+                    // returns, so only subsequent invocations are affected.
+                    TraceV1::Upgrade { to, .. } => {
+                        if v1.address == target {
+                            *module_reference = *to;
+                        }
+                    }
+                }
+            }
         }
     }
+}
 
-    /// Process events that are coming in on the provided channel.
-    ///
-    /// This handler will only terminate in the case of
-    ///
-    /// - the [`on_failure`](ProcessEvent::on_failure) method indicates so.
-    /// - the sender part of the `events` channel has been dropped
-    /// - the [`ProcessorConfig`] was configured with a termination signal that
-    ///   was triggered.
-    ///
-    /// The function will log progress using the `tracing` library with the
-    /// target set to `ccd_event_processor`.
-    pub async fn process_events<P: ProcessEvent>(
-        mut self,
-        mut process: P,
-        mut events: tokio::sync::mpsc::Receiver<P::Data>,
-    ) {
-        while let Some(event) = tokio::select! {
-            biased;
-            _ = &mut self.stop => None,
-            r = events.recv() => r,
-        } {
-            let mut try_number: u32 = 0;
-            'outer: loop {
-                let start = tokio::time::Instant::now();
-                let response = process.process(&event).await;
-                let end = tokio::time::Instant::now();
-                let duration = end.duration_since(start).as_millis();
-                match response {
-                    Ok(descr) => {
-                        tracing::info!(
-                            target: "ccd_event_processor",
-                            "{descr} in {duration}ms."
-                        );
+#[async_trait]
+impl Indexer for SchemaEventIndexer {
+    /// The name of the contract. This does not change during the lifetime of
+    /// an instance, even if it is upgraded.
+    type Context = OwnedContractName;
+    type Data = (BlockInfo, Vec<SchemaEventRecord>);
+
+    async fn on_connect<'a>(
+        &mut self,
+        endpoint: v2::Endpoint,
+        client: &'a mut v2::Client,
+    ) -> QueryResult<OwnedContractName> {
+        TransactionIndexer.on_connect(endpoint, client).await?;
+        let info = client
+            .get_instance_info(self.target_address, BlockIdentifier::LastFinal)
+            .await?
+            .response;
+        Ok(info.name().clone())
+    }
+
+    async fn on_finalized<'a>(
+        &self,
+        mut client: v2::Client,
+        contract_name: &'a OwnedContractName,
+        fbi: FinalizedBlockInfo,
+    ) -> QueryResult<Self::Data> {
+        let bi = client.get_block_info(fbi.height).await?.response;
+        if bi.transaction_count == 0 {
+            return Ok((bi, Vec::new()));
+        }
+        let summaries = client
+            .get_block_transaction_events(fbi.height)
+            .await?
+            .response
+            .try_filter_map(|summary| async move {
+                if summary.affected_contracts().contains(&self.target_address) {
+                    Ok(Some(summary))
+                } else {
+                    Ok(None)
+                }
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+        if summaries.is_empty() {
+            return Ok((bi, Vec::new()));
+        }
+
+        // The module of the instance at the start of the block. This is the
+        // module at the end of the block, unless the instance was upgraded
+        // during the block.
+        let first_upgrade = summaries.iter().find_map(|summary| {
+            let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+                return None;
+            };
+            let AccountTransactionEffects::ContractUpdateIssued { effects } = &at.effects else {
+                return None;
+            };
+            effects.iter().find_map(|effect| match effect {
+                ContractTraceElement::Upgraded { address, from, .. }
+                    if *address == self.target_address =>
+                {
+                    Some(*from)
+                }
+                _ => None,
+            })
+        });
+        let mut module_reference = match first_upgrade {
+            Some(from) => from,
+            None => client
+                .get_instance_info(self.target_address, fbi.height)
+                .await?
+                .response
+                .source_module(),
+        };
+
+        // Initialization parameters are not part of the transaction outcome, so
+        // they have to be looked up in the block items.
+        let init_hashes = summaries
+            .iter()
+            .filter(|summary| summary.contract_init().is_some())
+            .map(|summary| summary.hash)
+            .collect::<BTreeSet<_>>();
+        let mut init_parameters = BTreeMap::new();
+        if !init_hashes.is_empty() {
+            let mut items = client.get_block_items(fbi.height).await?.response;
+            while let Some(item) = items.try_next().await? {
+                let hash = item.hash();
+                if !init_hashes.contains(&hash) {
+                    continue;
+                }
+                if let BlockItem::AccountTransaction(at) = item {
+                    if let Ok(Payload::InitContract { payload }) = at.payload.decode() {
+                        init_parameters.insert(hash, payload.param);
+                    }
+                }
+            }
+        }
+
+        let mut records = Vec::new();
+        for summary in summaries {
+            let transaction_hash = summary.hash;
+            let BlockItemSummaryDetails::AccountTransaction(at) = summary.details else {
+                continue;
+            };
+            match at.effects {
+                AccountTransactionEffects::ContractInitialized { data } => {
+                    module_reference = data.origin_ref;
+                    let parameter = init_parameters
+                        .get(&transaction_hash)
+                        .map_or_else(|| DecodedValue::new(Vec::<u8>::new()), DecodedValue::new);
+                    records.push(SchemaEventRecord {
+                        transaction_hash,
+                        sender: at.sender,
+                        module_reference,
+                        details: SchemaEventDetails::Initialized {
+                            amount: data.amount,
+                            parameter,
+                            events: data.events.iter().map(DecodedValue::new).collect(),
+                        },
+                    });
+                }
+                AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                    let Some(tree) = execution_tree(effects) else {
+                        continue;
+                    };
+                    schema_update_records(
+                        &tree,
+                        self.target_address,
+                        &mut module_reference,
+                        transaction_hash,
+                        at.sender,
+                        &mut records,
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        let contract_name = contract_name.as_contract_name().contract_name();
+        for record in records.iter_mut() {
+            let schema = self
+                .get_schema(&mut client, record.module_reference, fbi.height)
+                .await?;
+            let Some(schema) = schema.as_ref() else {
+                continue;
+            };
+            match &mut record.details {
+                SchemaEventDetails::Initialized {
+                    parameter, events, ..
+                } => {
+                    parameter.decode_with(schema.get_init_param_schema(contract_name).ok());
+                    for event in events {
+                        event.decode_with(schema.get_event_schema(contract_name).ok());
+                    }
+                }
+                SchemaEventDetails::Updated {
+                    entrypoint,
+                    parameter,
+                    events,
+                    ..
+                } => {
+                    parameter.decode_with(
+                        schema
+                            .get_receive_param_schema(
+                                contract_name,
+                                entrypoint.as_entrypoint_name().into(),
+                            )
+                            .ok(),
+                    );
+                    for event in events {
+                        event.decode_with(schema.get_event_schema(contract_name).ok());
+                    }
+                }
+            }
+        }
+        Ok((bi, records))
+    }
+
+    async fn on_failure(
+        &mut self,
+        endpoint: v2::Endpoint,
+        successive_failures: u64,
+        err: TraverseError,
+    ) -> bool {
+        TransactionIndexer
+            .on_failure(endpoint, successive_failures, err)
+            .await
+    }
+}
+
+/// The source of an [`AccountLedgerEntry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerEntryKind {
+    /// An account transaction sent by, or affecting, the account. The type is
+    /// [`None`] only for transactions that failed to deserialize.
+    Transaction {
+        transaction_type: Option<TransactionType>,
+    },
+    /// Baking rewards distributed in protocol versions 1-3.
+    BakingReward,
+    /// Finalization rewards distributed in protocol versions 1-3.
+    FinalizationReward,
+    /// The baker reward and foundation charge of a block, in protocol
+    /// versions 1-3.
+    BlockReward,
+    /// Newly minted CCD credited to the foundation account in protocol
+    /// versions 1-3, and the foundation's share of minting and transaction
+    /// fees paid out at a payday in protocol versions 4 and up.
+    FoundationReward,
+    /// Rewards paid out to a baker or a delegator at a payday, in protocol
+    /// versions 4 and up.
+    PaydayReward,
+    /// Release of the given amount of scheduled transfers to the account. The
+    /// amount is part of the balance from the time of the transfer, so the
+    /// entry does not change the balance.
+    ScheduledRelease { amount: Amount },
+    /// Release of the given amount of stake from cooldown. The amount is part
+    /// of the balance while it is staked, so the entry does not change the
+    /// balance.
+    CooldownRelease { amount: Amount },
+}
+
+/// A single credit and/or debit of an account as reported by the
+/// [`AccountLedgerIndexer`]. There is one entry for each pair of a
+/// transaction (or special transaction outcome) and tracked account affected by
+/// it.
+#[derive(Debug, Clone)]
+pub struct AccountLedgerEntry {
+    /// The account that was affected.
+    pub account:          AccountAddress,
+    /// The hash of the transaction. This is [`None`] for entries stemming from
+    /// special transaction outcomes, i.e., rewards.
+    pub transaction_hash: Option<TransactionHash>,
+    /// What caused the entry.
+    pub kind:             LedgerEntryKind,
+    /// The other party of the transfer, if any. If the account interacted
+    /// with multiple parties in a transaction this is the first one.
+    pub counterparty:     Option<Address>,
+    /// The amount credited to the public balance of the account.
+    pub credit:           Amount,
+    /// The amount debited from the public balance of the account, excluding
+    /// the transaction fee.
+    pub debit:            Amount,
+    /// The transaction fee paid by the account. This is only non-zero if the
+    /// account is the sender of the transaction.
+    pub fee:              Amount,
+    /// The memo of the transfer, if any.
+    pub memo:             Option<Memo>,
+    /// The public balance of the account after the entry.
+    pub balance:          Amount,
+}
+
+impl AccountLedgerEntry {
+    fn new(
+        account: AccountAddress,
+        transaction_hash: Option<TransactionHash>,
+        kind: LedgerEntryKind,
+    ) -> Self {
+        Self {
+            account,
+            transaction_hash,
+            kind,
+            counterparty: None,
+            credit: Amount::zero(),
+            debit: Amount::zero(),
+            fee: Amount::zero(),
+            memo: None,
+            balance: Amount::zero(),
+        }
+    }
+
+    /// The net change of the balance of the account in microCCD.
+    fn net(&self) -> i128 {
+        i128::from(self.credit.micro_ccd())
+            - i128::from(self.debit.micro_ccd())
+            - i128::from(self.fee.micro_ccd())
+    }
+}
+
+/// A mismatch between the balance reported by the node and the balance
+/// derived by the [`AccountLedgerIndexer`].
+#[derive(Debug, Clone)]
+pub struct BalanceDiscrepancy {
+    /// The affected account.
+    pub account:  AccountAddress,
+    /// The balance at the end of the block, computed by adding the ledger
+    /// entries of the block to the balance at the end of the previous block.
+    pub expected: Amount,
+    /// The balance at the end of the block as reported by the node.
+    pub actual:   Amount,
+}
+
+/// An indexer that produces a ledger of all the changes to the public balance
+/// of a set of accounts.
+///
+/// Ledger entries are derived from transaction outcomes and special transaction
+/// outcomes. Releases of scheduled transfers, which are credited in full at the
+/// time of the transfer, and releases of stake from cooldown, which is part of
+/// the balance while it is staked, do not change the balance of the account.
+/// They are reported as entries with neither credit nor debit, of kind
+/// [`ScheduledRelease`](LedgerEntryKind::ScheduledRelease) and
+/// [`CooldownRelease`](LedgerEntryKind::CooldownRelease), respectively. These
+/// are detected by comparing the state of each tracked account at the start and
+/// the end of each block. Transfers of encrypted amounts are not included, only
+/// conversions between public and encrypted balance.
+///
+/// The resulting balances of entries are computed backwards from the balance at
+/// the end of the block. Entries stemming from transactions are listed before
+/// entries stemming from special transaction outcomes, which are listed before
+/// releases.
+///
+/// If `check_interval` is non-zero, then every `check_interval` blocks the
+/// indexer checks for each tracked account that the balance at the end of the
+/// previous block together with the entries of the current block adds up to the
+/// balance at the end of the current block. Any mismatches are returned
+/// together with the entries and logged on `warn` level.
+///
+/// The [`on_connect`](Indexer::on_connect) and
+/// [`on_failure`](Indexer::on_failure) methods of the [`Indexer`] trait only
+/// log the events on `info` and `warn` levels, respectively, using the
+/// [`tracing`](https://docs.rs/tracing/latest/tracing/) crate. The [target](https://docs.rs/tracing/latest/tracing/struct.Metadata.html#method.target)
+/// of the log is `ccd_indexer` which may be used to filter the logs.
+pub struct AccountLedgerIndexer {
+    /// The accounts to track. Aliases of these accounts are tracked as well,
+    /// and are reported as the account in this set.
+    pub accounts:       BTreeSet<AccountAddress>,
+    /// How often to check the derived balances against the balances reported
+    /// by the node, in number of blocks. A value of `0` disables the checks.
+    pub check_interval: u64,
+}
+
+/// Accumulates the ledger entries of a single transaction or special
+/// transaction outcome, one for each tracked account that is affected.
+struct LedgerEntries<'a> {
+    accounts:         &'a BTreeSet<AccountAddress>,
+    transaction_hash: Option<TransactionHash>,
+    kind:             LedgerEntryKind,
+    entries:          Vec<AccountLedgerEntry>,
+}
+
+impl<'a> LedgerEntries<'a> {
+    fn new(
+        accounts: &'a BTreeSet<AccountAddress>,
+        transaction_hash: Option<TransactionHash>,
+        kind: LedgerEntryKind,
+    ) -> Self {
+        Self {
+            accounts,
+            transaction_hash,
+            kind,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Get the entry for the given account, if the account is tracked.
+    fn entry(&mut self, account: &AccountAddress) -> Option<&mut AccountLedgerEntry> {
+        let account = *self.accounts.iter().find(|a| a.is_alias(account))?;
+        let idx = match self.entries.iter().position(|e| e.account == account) {
+            Some(idx) => idx,
+            None => {
+                self.entries.push(AccountLedgerEntry::new(
+                    account,
+                    self.transaction_hash,
+                    self.kind,
+                ));
+                self.entries.len() - 1
+            }
+        };
+        Some(&mut self.entries[idx])
+    }
+
+    fn credit(&mut self, account: &AccountAddress, amount: Amount, from: Option<Address>) {
+        if let Some(entry) = self.entry(account) {
+            entry.credit += amount;
+            entry.counterparty = entry.counterparty.or(from);
+        }
+    }
+
+    fn debit(&mut self, account: &AccountAddress, amount: Amount, to: Option<Address>) {
+        if let Some(entry) = self.entry(account) {
+            entry.debit += amount;
+            entry.counterparty = entry.counterparty.or(to);
+        }
+    }
+
+    fn transfer(
+        &mut self,
+        from: &AccountAddress,
+        to: &AccountAddress,
+        amount: Amount,
+        memo: Option<&Memo>,
+    ) {
+        self.debit(from, amount, Some(Address::Account(*to)));
+        self.credit(to, amount, Some(Address::Account(*from)));
+        if let Some(memo) = memo {
+            for account in [from, to] {
+                if let Some(entry) = self.entry(account) {
+                    entry.memo = Some(memo.clone());
+                }
+            }
+        }
+    }
+
+    fn summary(mut self, summary: &BlockItemSummary) -> Vec<AccountLedgerEntry> {
+        let BlockItemSummaryDetails::AccountTransaction(at) = &summary.details else {
+            return Vec::new();
+        };
+        if let Some(entry) = self.entry(&at.sender) {
+            entry.fee = at.cost;
+        }
+        match &at.effects {
+            AccountTransactionEffects::AccountTransfer { amount, to } => {
+                self.transfer(&at.sender, to, *amount, None)
+            }
+            AccountTransactionEffects::AccountTransferWithMemo { amount, to, memo } => {
+                self.transfer(&at.sender, to, *amount, Some(memo))
+            }
+            AccountTransactionEffects::TransferredWithSchedule { to, amount } => {
+                let amount = amount
+                    .iter()
+                    .fold(Amount::zero(), |acc, (_, amount)| acc + *amount);
+                self.transfer(&at.sender, to, amount, None)
+            }
+            AccountTransactionEffects::TransferredWithScheduleAndMemo { to, amount, memo } => {
+                let amount = amount
+                    .iter()
+                    .fold(Amount::zero(), |acc, (_, amount)| acc + *amount);
+                self.transfer(&at.sender, to, amount, Some(memo))
+            }
+            AccountTransactionEffects::TransferredToEncrypted { data } => {
+                self.debit(&at.sender, data.amount, None)
+            }
+            AccountTransactionEffects::TransferredToPublic { amount, .. } => {
+                self.credit(&at.sender, *amount, None)
+            }
+            AccountTransactionEffects::ContractInitialized { data } => self.debit(
+                &at.sender,
+                data.amount,
+                Some(Address::Contract(data.address)),
+            ),
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                for effect in effects {
+                    match effect {
+                        ContractTraceElement::Updated { data } => {
+                            if let Address::Account(instigator) = &data.instigator {
+                                self.debit(
+                                    instigator,
+                                    data.amount,
+                                    Some(Address::Contract(data.address)),
+                                )
+                            }
+                        }
+                        ContractTraceElement::Transferred { from, amount, to } => {
+                            self.credit(to, *amount, Some(Address::Contract(*from)))
+                        }
+                        ContractTraceElement::Interrupted { .. } => (),
+                        ContractTraceElement::Resumed { .. } => (),
+                        ContractTraceElement::Upgraded { .. } => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        self.entries
+    }
+
+    fn special(mut self, outcome: &SpecialTransactionOutcome) -> Vec<AccountLedgerEntry> {
+        match outcome {
+            SpecialTransactionOutcome::BakingRewards { baker_rewards, .. } => {
+                for (account, amount) in baker_rewards {
+                    self.credit(account, *amount, None);
+                }
+            }
+            SpecialTransactionOutcome::Mint {
+                mint_platform_development_charge,
+                foundation_account,
+                ..
+            } => self.credit(foundation_account, *mint_platform_development_charge, None),
+            SpecialTransactionOutcome::FinalizationRewards {
+                finalization_rewards,
+                ..
+            } => {
+                for (account, amount) in finalization_rewards {
+                    self.credit(account, *amount, None);
+                }
+            }
+            SpecialTransactionOutcome::BlockReward {
+                baker_reward,
+                foundation_charge,
+                baker,
+                foundation_account,
+                ..
+            } => {
+                self.credit(baker, *baker_reward, None);
+                self.credit(foundation_account, *foundation_charge, None);
+            }
+            SpecialTransactionOutcome::PaydayFoundationReward {
+                foundation_account,
+                development_charge,
+            } => self.credit(foundation_account, *development_charge, None),
+            SpecialTransactionOutcome::PaydayAccountReward {
+                account,
+                transaction_fees,
+                baker_reward,
+                finalization_reward,
+            } => self.credit(
+                account,
+                *transaction_fees + *baker_reward + *finalization_reward,
+                None,
+            ),
+            SpecialTransactionOutcome::BlockAccrueReward { .. } => (),
+            SpecialTransactionOutcome::PaydayPoolReward { .. } => (),
+        }
+        self.entries
+    }
+}
+
+/// Get the state of the account at the end of the given block, or [`None`] if
+/// the account does not exist in the block.
+async fn account_info(
+    client: &mut v2::Client,
+    account: AccountAddress,
+    height: AbsoluteBlockHeight,
+) -> QueryResult<Option<AccountInfo>> {
+    match client
+        .get_account_info(&AccountIdentifier::Address(account), height)
+        .await
+    {
+        Ok(info) => Ok(Some(info.response)),
+        Err(e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The amounts of scheduled transfers and of stake in cooldown that were
+/// released in a block with the given slot time, given the release schedule of
+/// the account at the start of the block, and its cooldowns at the start and at
+/// the end of the block.
+fn released_amounts(
+    schedule: &[Release],
+    start_cooldowns: &[Cooldown],
+    end_cooldowns: &[Cooldown],
+    slot_time: chrono::DateTime<chrono::Utc>,
+) -> (Amount, Amount) {
+    let scheduled = schedule
+        .iter()
+        .filter(|release| release.timestamp <= slot_time)
+        .fold(Amount::zero(), |acc, release| acc + release.amount);
+    // Cooldowns that have ended are released at the next payday, so they
+    // remain until then.
+    let slot_millis = u64::try_from(slot_time.timestamp_millis()).unwrap_or(0);
+    let ended = |cooldowns: &[Cooldown]| {
+        cooldowns
+            .iter()
+            .filter(|c| c.status == CooldownStatus::Cooldown && c.end_time.millis <= slot_millis)
+            .fold(Amount::zero(), |acc, c| acc + c.amount)
+    };
+    let cooldown = ended(start_cooldowns)
+        .checked_sub(ended(end_cooldowns))
+        .unwrap_or_else(Amount::zero);
+    (scheduled, cooldown)
+}
+
+/// The kind of the ledger entries stemming from a special transaction outcome
+/// in a block of the given protocol version, or [`None`] if the outcome does
+/// not lead to ledger entries.
+fn special_entry_kind(
+    outcome: &SpecialTransactionOutcome,
+    protocol_version: ProtocolVersion,
+) -> Option<LedgerEntryKind> {
+    let kind = match outcome {
+        SpecialTransactionOutcome::BakingRewards { .. } => LedgerEntryKind::BakingReward,
+        // From protocol version 4 the development charge of minting is
+        // paid out to the foundation account as part of the
+        // `PaydayFoundationReward`, so it must not be counted twice.
+        SpecialTransactionOutcome::Mint { .. } if protocol_version >= ProtocolVersion::P4 => {
+            return None
+        }
+        SpecialTransactionOutcome::Mint { .. } => LedgerEntryKind::FoundationReward,
+        SpecialTransactionOutcome::FinalizationRewards { .. } => {
+            LedgerEntryKind::FinalizationReward
+        }
+        SpecialTransactionOutcome::BlockReward { .. } => LedgerEntryKind::BlockReward,
+        SpecialTransactionOutcome::PaydayFoundationReward { .. } => {
+            LedgerEntryKind::FoundationReward
+        }
+        SpecialTransactionOutcome::PaydayAccountReward { .. } => LedgerEntryKind::PaydayReward,
+        SpecialTransactionOutcome::BlockAccrueReward { .. } => return None,
+        SpecialTransactionOutcome::PaydayPoolReward { .. } => return None,
+    };
+    Some(kind)
+}
+
+/// Set the resulting balances of the entries of the account, computing them
+/// backwards from the balance at the end of the block.
+fn set_balances(entries: &mut [AccountLedgerEntry], account: AccountAddress, end_balance: Amount) {
+    let mut balance = i128::from(end_balance.micro_ccd());
+    for entry in entries.iter_mut().rev().filter(|e| e.account == account) {
+        entry.balance = Amount::from_micro_ccd(u64::try_from(balance).unwrap_or(0));
+        balance -= entry.net();
+    }
+}
+
+#[async_trait]
+impl Indexer for AccountLedgerIndexer {
+    type Context = ();
+    type Data = (BlockInfo, Vec<AccountLedgerEntry>, Vec<BalanceDiscrepancy>);
+
+    async fn on_connect<'a>(
+        &mut self,
+        endpoint: v2::Endpoint,
+        client: &'a mut v2::Client,
+    ) -> QueryResult<()> {
+        TransactionIndexer.on_connect(endpoint, client).await
+    }
+
+    async fn on_finalized<'a>(
+        &self,
+        mut client: v2::Client,
+        ctx: &'a (),
+        fbi: FinalizedBlockInfo,
+    ) -> QueryResult<Self::Data> {
+        let (bi, summaries, special) = BlockEventsIndexer
+            .on_finalized(client.clone(), ctx, fbi)
+            .await?;
+
+        let mut entries = Vec::new();
+        for summary in &summaries {
+            let kind = LedgerEntryKind::Transaction {
+                transaction_type: match &summary.details {
+                    BlockItemSummaryDetails::AccountTransaction(at) => at.transaction_type(),
+                    _ => None,
+                },
+            };
+            entries.extend(
+                LedgerEntries::new(&self.accounts, Some(summary.hash), kind).summary(summary),
+            );
+        }
+        for outcome in &special {
+            if let Some(kind) = special_entry_kind(outcome, bi.protocol_version) {
+                entries.extend(LedgerEntries::new(&self.accounts, None, kind).special(outcome));
+            }
+        }
+
+        let check = self.check_interval != 0 && fbi.height.height % self.check_interval == 0;
+        let mut discrepancies = Vec::new();
+        for account in &self.accounts {
+            let start = match fbi.height.height.checked_sub(1) {
+                Some(height) => {
+                    account_info(&mut client, *account, AbsoluteBlockHeight { height }).await?
+                }
+                None => None,
+            };
+            let end = account_info(&mut client, *account, fbi.height).await?;
+            if let (Some(start), Some(end)) = (&start, &end) {
+                let (scheduled, cooldown) = released_amounts(
+                    &start.account_release_schedule.schedule,
+                    &start.cooldowns,
+                    &end.cooldowns,
+                    bi.block_slot_time,
+                );
+                if scheduled != Amount::zero() {
+                    entries.push(AccountLedgerEntry::new(
+                        *account,
+                        None,
+                        LedgerEntryKind::ScheduledRelease { amount: scheduled },
+                    ));
+                }
+                if cooldown != Amount::zero() {
+                    entries.push(AccountLedgerEntry::new(
+                        *account,
+                        None,
+                        LedgerEntryKind::CooldownRelease { amount: cooldown },
+                    ));
+                }
+            }
+            let net = entries
+                .iter()
+                .filter(|e| e.account == *account)
+                .map(AccountLedgerEntry::net)
+                .sum::<i128>();
+            if !check && !entries.iter().any(|e| e.account == *account) {
+                continue;
+            }
+            let end_balance = end.map_or(Amount::zero(), |info| info.account_amount);
+            set_balances(&mut entries, *account, end_balance);
+            if check {
+                let start_balance = start.map_or(Amount::zero(), |info| info.account_amount);
+                let expected = i128::from(start_balance.micro_ccd()) + net;
+                if expected != i128::from(end_balance.micro_ccd()) {
+                    let expected = Amount::from_micro_ccd(u64::try_from(expected).unwrap_or(0));
+                    tracing::warn!(
+                        target: "ccd_indexer",
+                        "Derived balance {expected} of account {account} does not match the balance {end_balance} in block {}.",
+                        bi.block_hash
+                    );
+                    discrepancies.push(BalanceDiscrepancy {
+                        account: *account,
+                        expected,
+                        actual: end_balance,
+                    });
+                }
+            }
+        }
+        Ok((bi, entries, discrepancies))
+    }
+
+    async fn on_failure(
+        &mut self,
+        endpoint: v2::Endpoint,
+        successive_failures: u64,
+        err: TraverseError,
+    ) -> bool {
+        TransactionIndexer
+            .on_failure(endpoint, successive_failures, err)
+            .await
+    }
+}
+
+#[async_trait]
+/// Handle an individual event. This trait is designed to be used together with
+/// the [`ProcessorConfig`]. These two together are designed to ease the work of
+/// writing the part of indexers where data is to be stored in a database.
+pub trait ProcessEvent {
+    /// The type of events that are to be processed. Typically this will be all
+    /// of the transactions of interest for a single block.
+    type Data;
+    /// An error that can be signalled.
+    type Error: std::fmt::Display + std::fmt::Debug;
+    /// A description returned by the [`process`](ProcessEvent::process) method.
+    /// This message is logged by the [`ProcessorConfig`] and is intended to
+    /// describe the data that was just processed.
+    type Description: std::fmt::Display;
+
+    /// Process a single item. This should work atomically in the sense that
+    /// either the entire `data` is processed or none of it is in case of an
+    /// error. This property is relied upon by the [`ProcessorConfig`] to retry
+    /// failed attempts.
+    async fn process(&mut self, data: &Self::Data) -> Result<Self::Description, Self::Error>;
+
+    /// The `on_failure` method is invoked by the [`ProcessorConfig`] when it
+    /// fails to process an event. It is meant to retry to recreate the
+    /// resources, such as a database connection, that might have been
+    /// dropped. The return value should signal if the handler process
+    /// should continue (`true`) or not.
+    ///
+    /// The function takes the `error` that occurred at the latest
+    /// [`process`](Self::process) call that just failed, and the number of
+    /// attempts of calling `process` that failed.
+    async fn on_failure(
+        &mut self,
+        error: Self::Error,
+        failed_attempts: u32,
+    ) -> Result<bool, Self::Error>;
+}
+
+/// A handle that can be used to gracefully shut down event processing started
+/// by [`traverse_and_process_with_shutdown`] or
+/// [`process_events`](ProcessorConfig::process_events). It is registered with
+/// the [`ProcessorConfig`] via
+/// [`set_shutdown_handle`](ProcessorConfig::set_shutdown_handle).
+///
+/// In contrast to the [stop signal](ProcessorConfig::set_stop_signal), which
+/// stops processing immediately, a shutdown stops traversal of the chain, but
+/// lets the processor finish processing events that are already queued.
+///
+/// Note that cloning is cheap and is, therefore, the intended way of sharing
+/// values of this type between multiple tasks.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<tokio::sync::watch::Sender<bool>>,
+}
+
+/// The default implementation behaves the same as
+/// [`ShutdownHandle::new`](ShutdownHandle::new).
+impl Default for ShutdownHandle {
+    fn default() -> Self { Self::new() }
+}
+
+impl ShutdownHandle {
+    /// Construct a new handle that has not been signalled.
+    pub fn new() -> Self {
+        let (sender, _) = tokio::sync::watch::channel(false);
+        Self {
+            sender: Arc::new(sender),
+        }
+    }
+
+    /// Signal a shutdown. This has an effect on all clones of the handle.
+    pub fn shutdown(&self) { self.sender.send_replace(true); }
+
+    /// Whether a shutdown has been signalled.
+    pub fn is_shutdown(&self) -> bool { *self.sender.borrow() }
+
+    /// Wait until a shutdown is signalled.
+    async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        while !*receiver.borrow_and_update() {
+            if receiver.changed().await.is_err() {
+                // The sender is owned by self, so it cannot be dropped.
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Wait until a shutdown is signalled on the handle, if there is one.
+async fn wait_for_shutdown(shutdown: Option<&ShutdownHandle>) {
+    match shutdown {
+        Some(handle) => handle.wait().await,
+        None => std::future::pending().await,
+    }
+}
+
+pub struct ProcessorConfig {
+    /// The amount of time to wait after a failure to process an event.
+    wait_after_fail:  std::time::Duration,
+    /// A future to be signalled to stop processing.
+    stop:             std::pin::Pin<Box<dyn std::future::Future<Output = ()>>>,
+    /// A handle to be signalled to shut down gracefully.
+    shutdown:         Option<ShutdownHandle>,
+    /// The capacity of the channel between traversal and processing.
+    channel_capacity: usize,
+    /// The maximum number of events processed together by
+    /// [`process_event_batches`](ProcessorConfig::process_event_batches).
+    max_batch_size:   usize,
+}
+
+/// The default implementation behaves the same as
+/// [`ProcessorConfig::new`](ProcessorConfig::new).
+impl Default for ProcessorConfig {
+    fn default() -> Self { Self::new() }
+}
+
+impl ProcessorConfig {
+    /// After each failure the [`ProcessorConfig`] will pause a bit before
+    /// trying again. Defaults to 5 seconds.
+    pub fn set_wait_after_failure(self, wait_after_fail: Duration) -> Self {
+        Self {
+            wait_after_fail,
+            ..self
+        }
+    }
+
+    /// Set the stop signal for the processor. This accepts a future which will
+    /// be polled and if the future yields ready the `process_events` method
+    /// will terminate.
+    ///
+    /// An example of such a future would be the `Receiver` end of a oneshot
+    /// channel.
+    pub fn set_stop_signal(self, stop: impl std::future::Future<Output = ()> + 'static) -> Self {
+        Self {
+            stop: Box::pin(stop),
+            ..self
+        }
+    }
+
+    /// Set the handle for graceful shutdown of the processor. When the handle
+    /// is signalled, the processor will process the events that are already
+    /// queued and then terminate. When used with [`traverse_and_process`] the
+    /// traversal of the chain is stopped as well, so that no new events are
+    /// queued.
+    pub fn set_shutdown_handle(self, shutdown: ShutdownHandle) -> Self {
+        Self {
+            shutdown: Some(shutdown),
+            ..self
+        }
+    }
+
+    /// Set the capacity of the channel used by [`traverse_and_process`] to
+    /// pass events from the traversal to the processor. This bounds the number
+    /// of events that are queued for processing. Defaults to 10, and is at
+    /// least 1.
+    pub fn set_channel_capacity(self, channel_capacity: usize) -> Self {
+        Self {
+            channel_capacity: channel_capacity.max(1),
+            ..self
+        }
+    }
+
+    /// Set the maximum number of events that are processed together by
+    /// [`process_event_batches`](Self::process_event_batches). Batches are
+    /// formed from the events that are queued at the time, so batches may be
+    /// smaller. Defaults to 10, and is at least 1.
+    pub fn set_max_batch_size(self, max_batch_size: usize) -> Self {
+        Self {
+            max_batch_size: max_batch_size.max(1),
+            ..self
+        }
+    }
+
+    /// Construct a new [`ProcessorConfig`] that will retry the given number of
+    /// times. The default wait after a failure is 5 seconds.
+    pub fn new() -> Self {
+        Self {
+            wait_after_fail:  std::time::Duration::from_secs(5),
+            stop:             Box::pin(std::future::pending()),
+            shutdown:         None,
+            channel_capacity: 10,
+            max_batch_size:   10,
+        }
+    }
+
+    /// Process events that are coming in on the provided channel.
+    ///
+    /// This handler will only terminate in the case of
+    ///
+    /// - the [`on_failure`](ProcessEvent::on_failure) method indicates so.
+    /// - the sender part of the `events` channel has been dropped
+    /// - the [`ProcessorConfig`] was configured with a termination signal that
+    ///   was triggered.
+    /// - the [`ProcessorConfig`] was configured with a [`ShutdownHandle`] that
+    ///   was signalled. In this case the events already queued in the channel
+    ///   are processed before terminating.
+    ///
+    /// The function will log progress using the `tracing` library with the
+    /// target set to `ccd_event_processor`.
+    pub async fn process_events<P: ProcessEvent>(
+        self,
+        process: P,
+        events: tokio::sync::mpsc::Receiver<P::Data>,
+    ) {
+        self.process_loop(process, events, 1, |mut events| {
+            events.pop().expect("Batches are never empty.")
+        })
+        .await;
+    }
+
+    /// Like [`process_events`](Self::process_events), but process multiple
+    /// queued events together, up to the
+    /// [maximum batch size](Self::set_max_batch_size). This allows, for
+    /// example, storing the data from multiple blocks in a single database
+    /// transaction.
+    ///
+    /// The batches are passed to [`process`](ProcessEvent::process) in order,
+    /// and if processing fails the same batch is retried.
+    pub async fn process_event_batches<D, P: ProcessEvent<Data = Vec<D>>>(
+        self,
+        process: P,
+        events: tokio::sync::mpsc::Receiver<D>,
+    ) {
+        let max_batch_size = self.max_batch_size;
+        self.process_loop(process, events, max_batch_size, |events| events)
+            .await;
+    }
+
+    /// The main loop of [`process_events`](Self::process_events) and
+    /// [`process_event_batches`](Self::process_event_batches). Returns the
+    /// number of events that were successfully processed.
+    async fn process_loop<D, P: ProcessEvent>(
+        mut self,
+        mut process: P,
+        mut events: tokio::sync::mpsc::Receiver<D>,
+        max_batch_size: usize,
+        make_batch: impl Fn(Vec<D>) -> P::Data,
+    ) -> u64 {
+        let mut processed: u64 = 0;
+        let mut draining = false;
+        loop {
+            let first = if draining {
+                match events.try_recv() {
+                    Ok(event) => event,
+                    Err(_) => {
+                        tracing::info!(
+                            target: "ccd_event_processor",
+                            "Terminating process_events due to shutdown."
+                        );
+                        return processed;
+                    }
+                }
+            } else {
+                let r = tokio::select! {
+                    biased;
+                    _ = &mut self.stop => None,
+                    _ = wait_for_shutdown(self.shutdown.as_ref()) => {
+                        draining = true;
+                        continue;
+                    }
+                    r = events.recv() => r,
+                };
+                let Some(event) = r else {
+                    break;
+                };
+                event
+            };
+            let mut batch = vec![first];
+            while batch.len() < max_batch_size {
+                match events.try_recv() {
+                    Ok(event) => batch.push(event),
+                    Err(_) => break,
+                }
+            }
+            let batch_len = batch.len() as u64;
+            let event = make_batch(batch);
+            let mut try_number: u32 = 0;
+            'outer: loop {
+                let start = tokio::time::Instant::now();
+                let response = process.process(&event).await;
+                let end = tokio::time::Instant::now();
+                let duration = end.duration_since(start).as_millis();
+                match response {
+                    Ok(descr) => {
+                        tracing::info!(
+                            target: "ccd_event_processor",
+                            "{descr} in {duration}ms."
+                        );
                         break 'outer;
                     }
                     Err(e) => {
@@ -807,7 +2295,7 @@ impl ProcessorConfig {
                             Ok(true) => {
                                 // do nothing, continue.
                             }
-                            Ok(false) => return,
+                            Ok(false) => return processed,
                             Err(e) => {
                                 tracing::warn!("Failed to restart: {e}.");
                             }
@@ -816,11 +2304,13 @@ impl ProcessorConfig {
                     }
                 }
             }
+            processed += batch_len;
         }
         tracing::info!(
             target: "ccd_event_processor",
             "Terminating process_events due to channel closing."
         );
+        processed
     }
 }
 
@@ -828,17 +2318,517 @@ impl ProcessorConfig {
 /// events start a process to traverse the chain and index events.
 ///
 /// This process will only stop when the `stop_signal` future completes, when
+/// the [`ShutdownHandle`] is signalled, when
 /// [`traverse`](TraverseConfig::traverse) completes, or when
 /// [`process_events`](ProcessorConfig::process_events) completes.
+///
+/// Use [`traverse_and_process_with_shutdown`] to learn where to resume
+/// traversal after a shutdown.
 pub async fn traverse_and_process<I: Indexer, P: ProcessEvent<Data = I::Data>>(
     config: TraverseConfig,
     i: I,
     processor: ProcessorConfig,
     p: P,
 ) -> Result<(), QueryError> {
-    let (sender, receiver) = tokio::sync::mpsc::channel(10);
-    let fut1 = config.traverse(i, sender);
-    let fut2 = processor.process_events(p, receiver);
-    let (r1, ()) = futures::join!(fut1, fut2);
-    r1
+    traverse_and_process_with_shutdown(config, i, processor, p)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.error)
+}
+
+/// Like [`traverse_and_process`], but return the height of the last block
+/// whose events were successfully processed.
+///
+/// When the [`ShutdownHandle`] is signalled the traversal is stopped, and the
+/// events that are already queued are processed before returning.
+///
+/// Returns the height of the last block whose events were successfully
+/// processed, or [`None`] if no events were processed. Traversal may be resumed
+/// from the block after this one. If traversal fails, the events that were
+/// already queued are processed, and the height of the last processed block is
+/// included in the error.
+pub async fn traverse_and_process_with_shutdown<I: Indexer, P: ProcessEvent<Data = I::Data>>(
+    config: TraverseConfig,
+    i: I,
+    processor: ProcessorConfig,
+    p: P,
+) -> Result<Option<AbsoluteBlockHeight>, TraverseProcessError> {
+    let start_height = config.start_height;
+    let (sender, receiver) = tokio::sync::mpsc::channel(processor.channel_capacity);
+    let fut1 = traverse_until_shutdown(config, i, sender, processor.shutdown.clone());
+    let fut2 = processor.process_loop(p, receiver, 1, |mut events| {
+        events.pop().expect("Batches are never empty.")
+    });
+    let (r1, processed) = futures::join!(fut1, fut2);
+    let last_processed = last_processed_height(start_height, processed);
+    match r1 {
+        Ok(()) => Ok(last_processed),
+        Err(error) => Err(TraverseProcessError {
+            last_processed,
+            error,
+        }),
+    }
+}
+
+/// Like [`traverse_and_process_with_shutdown`], but process events in batches
+/// using [`process_event_batches`](ProcessorConfig::process_event_batches).
+pub async fn traverse_and_process_batches<I: Indexer, P: ProcessEvent<Data = Vec<I::Data>>>(
+    config: TraverseConfig,
+    i: I,
+    processor: ProcessorConfig,
+    p: P,
+) -> Result<Option<AbsoluteBlockHeight>, TraverseProcessError> {
+    let start_height = config.start_height;
+    let (sender, receiver) = tokio::sync::mpsc::channel(processor.channel_capacity);
+    let fut1 = traverse_until_shutdown(config, i, sender, processor.shutdown.clone());
+    let max_batch_size = processor.max_batch_size;
+    let fut2 = processor.process_loop(p, receiver, max_batch_size, |events| events);
+    let (r1, processed) = futures::join!(fut1, fut2);
+    let last_processed = last_processed_height(start_height, processed);
+    match r1 {
+        Ok(()) => Ok(last_processed),
+        Err(error) => Err(TraverseProcessError {
+            last_processed,
+            error,
+        }),
+    }
+}
+
+/// An error returned by [`traverse_and_process_with_shutdown`] and
+/// [`traverse_and_process_batches`] when traversal of the chain fails.
+#[derive(Debug, thiserror::Error)]
+#[error("Traversal of the chain failed: {error}")]
+pub struct TraverseProcessError {
+    /// The height of the last block whose events were successfully
+    /// processed, or [`None`] if no events were processed. Traversal may be
+    /// resumed from the block after this one.
+    pub last_processed: Option<AbsoluteBlockHeight>,
+    /// The reason traversal failed.
+    #[source]
+    pub error:          QueryError,
+}
+
+/// Traverse the chain until traversal completes, or a shutdown is signalled.
+async fn traverse_until_shutdown<I: Indexer>(
+    config: TraverseConfig,
+    i: I,
+    sender: tokio::sync::mpsc::Sender<I::Data>,
+    shutdown: Option<ShutdownHandle>,
+) -> QueryResult<()> {
+    tokio::select! {
+        biased;
+        _ = wait_for_shutdown(shutdown.as_ref()) => Ok(()),
+        r = config.traverse(i, sender) => r,
+    }
+}
+
+/// The height of the last processed block, given that `processed` blocks were
+/// processed starting at `start_height`.
+fn last_processed_height(
+    start_height: AbsoluteBlockHeight,
+    processed: u64,
+) -> Option<AbsoluteBlockHeight> {
+    let processed = processed.checked_sub(1)?;
+    Some(AbsoluteBlockHeight {
+        height: start_height.height + processed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{
+        hashes::StateHash, AccountTransactionDetails, BlockHeight, TransactionIndex,
+    };
+    use chrono::TimeZone;
+    use concordium_base::common::types::Timestamp;
+
+    fn account(n: u8) -> AccountAddress { AccountAddress([n; 32]) }
+
+    fn height(height: u64) -> AbsoluteBlockHeight { AbsoluteBlockHeight { height } }
+
+    fn hash(n: u8) -> BlockHash { BlockHash::new([n; 32]) }
+
+    /// The information of the finalized block with hash `n` at height `n` and
+    /// the given parent.
+    fn block(n: u8, parent: BlockHash, era_height: u64) -> (FinalizedBlockInfo, BlockInfo) {
+        let fb = FinalizedBlockInfo {
+            block_hash: hash(n),
+            height:     height(n.into()),
+        };
+        let time = chrono::Utc.timestamp_millis_opt(0).unwrap();
+        let info = BlockInfo {
+            transactions_size:       0,
+            block_parent:            parent,
+            block_hash:              hash(n),
+            finalized:               true,
+            block_state_hash:        StateHash::new([0u8; 32]),
+            block_arrive_time:       time,
+            block_receive_time:      time,
+            transaction_count:       0,
+            transaction_energy_cost: Energy { energy: 0 },
+            block_slot:              None,
+            block_last_finalized:    hash(n),
+            block_slot_time:         time,
+            block_height:            height(n.into()),
+            era_block_height:        BlockHeight { height: era_height },
+            genesis_index:           0u32.into(),
+            block_baker:             None,
+            protocol_version:        ProtocolVersion::P6,
+            round:                   None,
+            epoch:                   None,
+        };
+        (fb, info)
+    }
+
+    fn ccd(micro_ccd: u64) -> Amount { Amount::from_micro_ccd(micro_ccd) }
+
+    /// The tracked accounts.
+    fn accounts() -> BTreeSet<AccountAddress> { [account(1), account(2)].into() }
+
+    fn transfer(
+        sender: AccountAddress,
+        to: AccountAddress,
+        amount: Amount,
+        memo: Option<Memo>,
+    ) -> BlockItemSummary {
+        let effects = match memo {
+            Some(memo) => AccountTransactionEffects::AccountTransferWithMemo { amount, to, memo },
+            None => AccountTransactionEffects::AccountTransfer { amount, to },
+        };
+        BlockItemSummary {
+            index:       TransactionIndex { index: 0 },
+            energy_cost: Energy { energy: 501 },
+            hash:        TransactionHash::new([0u8; 32]),
+            details:     BlockItemSummaryDetails::AccountTransaction(AccountTransactionDetails {
+                cost: ccd(10),
+                sender,
+                effects,
+            }),
+        }
+    }
+
+    fn cooldown(end_time: u64, amount: u64, status: CooldownStatus) -> Cooldown {
+        Cooldown {
+            end_time: Timestamp { millis: end_time },
+            amount: ccd(amount),
+            status,
+        }
+    }
+
+    #[test]
+    /// Test the entries of transfers between tracked and untracked accounts.
+    fn transfer_entries() {
+        let accounts = accounts();
+        let kind = LedgerEntryKind::Transaction {
+            transaction_type: Some(TransactionType::TransferWithMemo),
+        };
+        let memo = Memo::try_from(vec![1, 2, 3]).expect("The memo is small.");
+        let summary = transfer(account(1), account(2), ccd(100), Some(memo.clone()));
+        let entries = LedgerEntries::new(&accounts, Some(summary.hash), kind).summary(&summary);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].account, account(1));
+        assert_eq!(entries[0].debit, ccd(100));
+        assert_eq!(entries[0].fee, ccd(10));
+        assert_eq!(entries[0].counterparty, Some(Address::Account(account(2))));
+        assert_eq!(entries[0].memo, Some(memo.clone()));
+        assert_eq!(entries[0].net(), -110);
+        assert_eq!(entries[1].account, account(2));
+        assert_eq!(entries[1].credit, ccd(100));
+        assert_eq!(entries[1].fee, Amount::zero());
+        assert_eq!(entries[1].memo, Some(memo));
+        assert_eq!(entries[1].net(), 100);
+
+        // Aliases are reported as the tracked account, and untracked accounts
+        // get no entries.
+        let mut alias = account(1);
+        alias.0[31] = 7;
+        let summary = transfer(alias, account(3), ccd(100), None);
+        let entries = LedgerEntries::new(&accounts, Some(summary.hash), kind).summary(&summary);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].account, account(1));
+        assert_eq!(entries[0].counterparty, Some(Address::Account(account(3))));
+        assert_eq!(entries[0].net(), -110);
+    }
+
+    #[test]
+    /// Test that minting is only credited before protocol version 4, where
+    /// the foundation is paid at paydays instead.
+    fn special_entries() {
+        let accounts = accounts();
+        let mint = SpecialTransactionOutcome::Mint {
+            mint_baking_reward:               ccd(1),
+            mint_finalization_reward:         ccd(2),
+            mint_platform_development_charge: ccd(3),
+            foundation_account:               account(1),
+        };
+        assert_eq!(
+            special_entry_kind(&mint, ProtocolVersion::P3),
+            Some(LedgerEntryKind::FoundationReward)
+        );
+        assert_eq!(special_entry_kind(&mint, ProtocolVersion::P4), None);
+        let entries =
+            LedgerEntries::new(&accounts, None, LedgerEntryKind::FoundationReward).special(&mint);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].credit, ccd(3));
+
+        let payday = SpecialTransactionOutcome::PaydayFoundationReward {
+            foundation_account: account(1),
+            development_charge: ccd(4),
+        };
+        assert_eq!(
+            special_entry_kind(&payday, ProtocolVersion::P4),
+            Some(LedgerEntryKind::FoundationReward)
+        );
+        let reward = SpecialTransactionOutcome::PaydayAccountReward {
+            account:             account(2),
+            transaction_fees:    ccd(1),
+            baker_reward:        ccd(2),
+            finalization_reward: ccd(3),
+        };
+        assert_eq!(
+            special_entry_kind(&reward, ProtocolVersion::P4),
+            Some(LedgerEntryKind::PaydayReward)
+        );
+        let entries =
+            LedgerEntries::new(&accounts, None, LedgerEntryKind::PaydayReward).special(&reward);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].account, account(2));
+        assert_eq!(entries[0].credit, ccd(6));
+        assert_eq!(entries[0].transaction_hash, None);
+    }
+
+    #[test]
+    /// Test detecting releases of scheduled transfers and of stake in
+    /// cooldown.
+    fn released_amounts_in_block() {
+        let release = |millis: i64, amount: u64| Release {
+            timestamp:    chrono::Utc.timestamp_millis_opt(millis).unwrap(),
+            amount:       ccd(amount),
+            transactions: Vec::new(),
+        };
+        let slot_time = chrono::Utc.timestamp_millis_opt(2000).unwrap();
+        let schedule = [release(1000, 5), release(2000, 6), release(3000, 7)];
+        let start_cooldowns = [
+            cooldown(1500, 20, CooldownStatus::Cooldown),
+            cooldown(5000, 30, CooldownStatus::Cooldown),
+            cooldown(1000, 40, CooldownStatus::PreCooldown),
+        ];
+        // The cooldown that ended is released in the block.
+        let end_cooldowns = [
+            cooldown(5000, 30, CooldownStatus::Cooldown),
+            cooldown(1000, 40, CooldownStatus::PreCooldown),
+        ];
+        assert_eq!(
+            released_amounts(&schedule, &start_cooldowns, &end_cooldowns, slot_time),
+            (ccd(11), ccd(20))
+        );
+        // The cooldown that ended is not yet released.
+        assert_eq!(
+            released_amounts(&[], &start_cooldowns, &start_cooldowns, slot_time),
+            (Amount::zero(), Amount::zero())
+        );
+    }
+
+    #[test]
+    /// Test computing the resulting balances backwards from the balance at the
+    /// end of the block.
+    fn resulting_balances() {
+        let mut entries = vec![
+            AccountLedgerEntry {
+                credit: ccd(100),
+                ..AccountLedgerEntry::new(account(1), None, LedgerEntryKind::BakingReward)
+            },
+            AccountLedgerEntry {
+                credit: ccd(5),
+                ..AccountLedgerEntry::new(account(2), None, LedgerEntryKind::BakingReward)
+            },
+            AccountLedgerEntry {
+                debit: ccd(30),
+                fee: ccd(10),
+                ..AccountLedgerEntry::new(account(1), None, LedgerEntryKind::Transaction {
+                    transaction_type: Some(TransactionType::Transfer),
+                })
+            },
+            AccountLedgerEntry::new(account(1), None, LedgerEntryKind::ScheduledRelease {
+                amount: ccd(50),
+            }),
+        ];
+        set_balances(&mut entries, account(1), ccd(1000));
+        let balances: Vec<_> = entries.iter().map(|e| e.balance).collect();
+        assert_eq!(balances, vec![
+            ccd(1040),
+            Amount::zero(),
+            ccd(1000),
+            ccd(1000)
+        ]);
+    }
+
+    #[test]
+    /// Test that the next block must have the expected height and parent.
+    fn verify_next_block() {
+        let last = Some((height(4), hash(4)));
+        let (fb, info) = block(5, hash(4), 5);
+        assert!(verify_block(height(5), last, &fb, &info).is_ok());
+        // The parent of the first block is not known.
+        let (fb, info) = block(5, hash(3), 5);
+        assert!(verify_block(height(5), None, &fb, &info).is_ok());
+
+        assert!(matches!(
+            verify_block(height(5), last, &fb, &info),
+            Err(ChainInconsistency::ParentMismatch {
+                expected_parent,
+                actual_parent,
+                ..
+            }) if expected_parent == hash(4) && actual_parent == hash(3)
+        ));
+        // The parent of a genesis block is not checked.
+        let (fb, info) = block(5, hash(3), 0);
+        assert!(verify_block(height(5), last, &fb, &info).is_ok());
+
+        let mut info = block(5, hash(4), 5).1;
+        info.finalized = false;
+        assert!(matches!(
+            verify_block(height(5), last, &fb, &info),
+            Err(ChainInconsistency::NotFinalized { .. })
+        ));
+    }
+
+    #[test]
+    /// Test that gaps in the heights of delivered blocks are detected.
+    fn verify_height_gap() {
+        let last = Some((height(4), hash(4)));
+        let (fb, info) = block(6, hash(4), 6);
+        assert!(matches!(
+            verify_block(height(5), last, &fb, &info),
+            Err(ChainInconsistency::UnexpectedHeight { expected, actual })
+                if expected == height(5) && actual == height(6)
+        ));
+        // The node reports a different height than the finalized block stream.
+        let (fb, _) = block(5, hash(4), 5);
+        assert!(matches!(
+            verify_block(height(5), last, &fb, &info),
+            Err(ChainInconsistency::UnexpectedHeight { expected, actual })
+                if expected == height(5) && actual == height(6)
+        ));
+    }
+
+    #[test]
+    /// Test that an endpoint switched to must agree on the last delivered
+    /// block.
+    fn verify_endpoint_switch() {
+        let endpoint = v2::Endpoint::from_static("http://localhost:20000");
+        assert!(verify_endpoint(&endpoint, height(4), hash(4), hash(4)).is_ok());
+        assert!(matches!(
+            verify_endpoint(&endpoint, height(4), hash(4), hash(9)),
+            Err(ChainInconsistency::EndpointDisagreement {
+                height: h,
+                expected,
+                actual,
+                ..
+            }) if h == height(4) && expected == hash(4) && actual == hash(9)
+        ));
+    }
+
+    /// A processor that records the events it processes.
+    struct Recorder<D> {
+        processed: Arc<Mutex<Vec<D>>>,
+    }
+
+    #[async_trait]
+    impl<D: Clone + Send + Sync> ProcessEvent for Recorder<D> {
+        type Data = D;
+        type Description = String;
+        type Error = String;
+
+        async fn process(&mut self, data: &D) -> Result<String, String> {
+            self.processed.lock().unwrap().push(data.clone());
+            Ok("Processed".into())
+        }
+
+        async fn on_failure(&mut self, _error: String, _attempts: u32) -> Result<bool, String> {
+            Ok(false)
+        }
+    }
+
+    #[tokio::test]
+    /// Test that events already queued are processed after a shutdown, even
+    /// though the channel is still open.
+    async fn drain_after_shutdown() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(10);
+        for i in 1..=3u64 {
+            sender.send(i).await.unwrap();
+        }
+        let shutdown = ShutdownHandle::new();
+        shutdown.shutdown();
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            processed: processed.clone(),
+        };
+        let count = ProcessorConfig::new()
+            .set_shutdown_handle(shutdown)
+            .process_loop(recorder, receiver, 1, |mut events| {
+                events.pop().expect("Batches are never empty.")
+            })
+            .await;
+        assert_eq!(count, 3);
+        assert_eq!(*processed.lock().unwrap(), vec![1, 2, 3]);
+        drop(sender);
+    }
+
+    #[tokio::test]
+    /// Test that batches are formed from the queued events, up to the maximum
+    /// batch size.
+    async fn process_batches() {
+        let (sender, receiver) = tokio::sync::mpsc::channel(10);
+        for i in 1..=5u64 {
+            sender.send(i).await.unwrap();
+        }
+        drop(sender);
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            processed: processed.clone(),
+        };
+        ProcessorConfig::new()
+            .set_max_batch_size(2)
+            .process_event_batches(recorder, receiver)
+            .await;
+        assert_eq!(*processed.lock().unwrap(), vec![
+            vec![1, 2],
+            vec![3, 4],
+            vec![5]
+        ]);
+    }
+
+    #[tokio::test]
+    /// Test that a zero channel capacity and batch size are clamped to one, so
+    /// that events are passed and processed one at a time.
+    async fn clamped_batch_sizes() {
+        let config = ProcessorConfig::new()
+            .set_channel_capacity(0)
+            .set_max_batch_size(0);
+        assert_eq!(config.channel_capacity, 1);
+        assert_eq!(config.max_batch_size, 1);
+        let (sender, receiver) = tokio::sync::mpsc::channel(config.channel_capacity);
+        let producer = tokio::spawn(async move {
+            for i in 1..=3u64 {
+                sender.send(i).await.unwrap();
+            }
+        });
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = Recorder {
+            processed: processed.clone(),
+        };
+        config.process_event_batches(recorder, receiver).await;
+        producer.await.unwrap();
+        assert_eq!(*processed.lock().unwrap(), vec![vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    /// Test computing the height of the last processed block.
+    fn last_processed() {
+        assert_eq!(last_processed_height(height(10), 0), None);
+        assert_eq!(last_processed_height(height(10), 1), Some(height(10)));
+        assert_eq!(last_processed_height(height(10), 3), Some(height(12)));
+    }
 }
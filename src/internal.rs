@@ -126,3 +126,25 @@ pub(crate) mod duration_millis {
             .ok_or_else(|| serde::de::Error::custom("Duration out of bounds!"))
     }
 }
+
+pub(crate) mod block_identifier_string {
+    use crate::v2::BlockIdentifier;
+    use serde::Deserialize;
+    /// Serialize (via Serde) a [`BlockIdentifier`] as a string in the format
+    /// of its [`Display`](std::fmt::Display) implementation.
+    pub fn serialize<S: serde::Serializer>(
+        bi: &BlockIdentifier,
+        ser: S,
+    ) -> Result<S::Ok, S::Error> {
+        ser.collect_str(bi)
+    }
+
+    /// Deserialize (via Serde) a [`BlockIdentifier`] from a string in the
+    /// format of its [`FromStr`](std::str::FromStr) implementation.
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        des: D,
+    ) -> Result<BlockIdentifier, D::Error> {
+        let s = String::deserialize(des)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
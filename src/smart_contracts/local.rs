@@ -0,0 +1,963 @@
+//! Local, off-chain execution of smart contracts against the state of the
+//! chain.
+//!
+//! The [`LocalExecutor`] runs receive functions of V1 smart contracts using
+//! the [execution engine](concordium_smart_contract_engine) instead of the
+//! node. Modules, instance state and balances are fetched lazily from a given
+//! block, so nested calls to other instances work as on chain. In contrast to
+//! [`invoke_instance`](crate::v2::Client::invoke_instance) and dry-running,
+//! local execution
+//!
+//!  * produces the full trace of the execution, also for failed invocations;
+//!  * can use unlimited energy;
+//!  * allows the state and balances of instances and accounts to be edited
+//!    before execution.
+//!
+//! The executor does not support V0 instances, and the
+//! `query_exchange_rates`, `check_account_signature` and
+//! `get_account_public_keys` queries. Sender policies are not available to
+//! contracts.
+use crate::{
+    types::smart_contracts::{ContractContext, InstanceInfo, WasmVersion},
+    v2::{self, AccountIdentifier, IntoBlockIdentifier},
+};
+use concordium_base::{
+    base::Energy,
+    common::{to_bytes, types::Timestamp},
+    contracts_common::{
+        AccountAddress, Address, Amount, ChainMetadata, ContractAddress, EntrypointName,
+        OwnedContractName, OwnedEntrypointName, OwnedReceiveName,
+    },
+    hashes::BlockHash,
+    smart_contracts::{
+        ContractEvent, ContractTraceElement, InstanceUpdatedEvent, ModuleReference, OwnedParameter,
+    },
+};
+use concordium_smart_contract_engine::{
+    v0,
+    v1::{self, trie, InvokeFailure, InvokeResponse},
+    InterpreterEnergy,
+};
+use concordium_wasm::validate::ValidationConfig;
+use futures::{future::BoxFuture, FutureExt, TryStreamExt};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+/// Interpreter energy used for execution when no energy limit is set. This
+/// is not `u64::MAX` to avoid overflows in the engine's accounting.
+const UNLIMITED_ENERGY: u64 = u64::MAX / 2;
+
+/// The amount of interpreter energy per unit of [`Energy`].
+const INTERPRETER_ENERGY_PER_ENERGY: u64 = 1000;
+
+/// Serialization of an empty list of sender policies.
+const NO_POLICIES: [u8; 2] = [0, 0];
+
+/// An error that occurred while executing a contract locally. This is
+/// distinct from a contract rejecting or trapping, which are reported as a
+/// [`LocalOutcome`].
+#[derive(thiserror::Error, Debug)]
+pub enum LocalExecutionError {
+    #[error("Error querying the node: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Instance {0} does not exist.")]
+    InstanceNotFound(ContractAddress),
+    #[error("Instance {0} is not a V1 instance. Only V1 instances can be executed locally.")]
+    UnsupportedInstanceVersion(ContractAddress),
+    #[error("Module {0} is not a V1 module.")]
+    UnsupportedModuleVersion(ModuleReference),
+    #[error("The account {0} does not exist.")]
+    AccountNotFound(AccountAddress),
+    #[error("Insufficient balance to transfer {amount}, the balance is {balance}.")]
+    InsufficientBalance { amount: Amount, balance: Amount },
+    #[error("The {0} operation is not supported by the local executor.")]
+    UnsupportedOperation(&'static str),
+    #[error("Execution error: {0:#}")]
+    Execution(#[from] anyhow::Error),
+}
+
+/// The public balance of an account, as observed by smart contracts.
+#[derive(Debug, Clone, Copy)]
+pub struct LocalAccountBalance {
+    /// The total balance of the account.
+    pub total:  Amount,
+    /// The amount that is staked.
+    pub staked: Amount,
+    /// The amount that is locked in releases schedules.
+    pub locked: Amount,
+}
+
+impl LocalAccountBalance {
+    /// The part of the balance that is neither staked nor locked, and hence
+    /// can be transferred.
+    pub fn available(&self) -> Amount {
+        self.total
+            .checked_sub(self.staked)
+            .and_then(|amount| amount.checked_sub(self.locked))
+            .unwrap_or_else(Amount::zero)
+    }
+}
+
+/// A smart contract instance as maintained by the [`LocalExecutor`].
+#[derive(Clone)]
+pub struct LocalInstance {
+    /// The owner of the instance.
+    pub owner:       AccountAddress,
+    /// The name of the contract.
+    pub name:        OwnedContractName,
+    /// The module that the instance executes.
+    pub module:      ModuleReference,
+    /// The entrypoints of the instance.
+    pub entrypoints: BTreeSet<OwnedReceiveName>,
+    /// The CCD balance of the instance.
+    pub balance:     Amount,
+    /// The state of the instance.
+    state:           trie::PersistentState,
+}
+
+impl LocalInstance {
+    /// Get the state of the instance.
+    pub fn state(&self) -> &trie::PersistentState { &self.state }
+
+    /// Replace the state of the instance with the given key-value pairs. The
+    /// current key-value pairs of an instance on chain can be obtained using
+    /// [`get_instance_state`](v2::Client::get_instance_state).
+    pub fn set_state(&mut self, entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) {
+        self.state = trie::PersistentState::from_iter(entries.into_iter());
+    }
+}
+
+/// The outcome of a local invocation.
+#[derive(Debug, Clone)]
+pub enum LocalOutcome {
+    /// The invocation succeeded.
+    Success { return_value: Option<Vec<u8>> },
+    /// The contract rejected the invocation.
+    Reject {
+        reason:       i32,
+        return_value: Option<Vec<u8>>,
+    },
+    /// The execution trapped, i.e., it failed with a runtime error.
+    Trap { error: String },
+    /// The execution ran out of energy.
+    OutOfEnergy,
+}
+
+impl LocalOutcome {
+    /// Whether the invocation succeeded.
+    pub fn is_success(&self) -> bool { matches!(self, LocalOutcome::Success { .. }) }
+}
+
+/// The result of [`LocalExecutor::invoke`] and [`LocalExecutor::update`].
+#[derive(Debug, Clone)]
+pub struct LocalInvocation {
+    /// The outcome of the top-level invocation.
+    pub outcome:     LocalOutcome,
+    /// The trace of the execution, in the same format as produced by the node.
+    /// In contrast to the node, the trace is also produced when the invocation
+    /// fails, in which case it includes elements of nested calls whose effects
+    /// were rolled back.
+    pub trace:       Vec<ContractTraceElement>,
+    /// The energy used by the execution. This does not include the base cost
+    /// of a transaction.
+    pub used_energy: Energy,
+}
+
+/// A single receive invocation.
+struct Frame {
+    invoker:    AccountAddress,
+    sender:     Address,
+    address:    ContractAddress,
+    entrypoint: OwnedEntrypointName,
+    parameter:  OwnedParameter,
+    amount:     Amount,
+}
+
+/// The local copies of instances and accounts. They are cloned before an
+/// invocation and restored if it fails, which rolls back its effects.
+#[derive(Clone, Default)]
+struct LocalState {
+    instances: BTreeMap<ContractAddress, LocalInstance>,
+    accounts:  BTreeMap<AccountAddress, LocalAccountBalance>,
+    /// The number of times the state of each instance has been written, used
+    /// to tell an interrupted instance whether its state was modified.
+    writes:    BTreeMap<ContractAddress, u64>,
+}
+
+impl LocalState {
+    /// Deduct the amount from the balance of a loaded address. For accounts,
+    /// only the available balance, i.e., the part that is neither staked nor
+    /// locked, can be used.
+    fn debit(&mut self, address: Address, amount: Amount) -> Result<(), LocalExecutionError> {
+        match address {
+            Address::Account(account) => {
+                let balance = self
+                    .accounts
+                    .get_mut(&account)
+                    .ok_or(LocalExecutionError::AccountNotFound(account))?;
+                let available = balance.available();
+                if available < amount {
+                    return Err(LocalExecutionError::InsufficientBalance {
+                        amount,
+                        balance: available,
+                    });
+                }
+                balance.total -= amount;
+            }
+            Address::Contract(contract) => {
+                let balance = &mut self
+                    .instances
+                    .get_mut(&contract)
+                    .ok_or(LocalExecutionError::InstanceNotFound(contract))?
+                    .balance;
+                *balance = balance.checked_sub(amount).ok_or(
+                    LocalExecutionError::InsufficientBalance {
+                        amount,
+                        balance: *balance,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Store the state of an instance, and record that it was written.
+    fn write_state(&mut self, address: ContractAddress, mut state: trie::MutableState) {
+        let frozen = state.freeze(&mut loader(), &mut trie::SizeCollector::default());
+        if let Some(instance) = self.instances.get_mut(&address) {
+            instance.state = frozen;
+        }
+        *self.writes.entry(address).or_default() += 1;
+    }
+}
+
+/// Executor of smart contracts that runs locally against the state of a
+/// block. See the [module documentation](self) for an overview.
+///
+/// Modules, instances and accounts are fetched from the node the first time
+/// they are needed, after which their local copy is used. Changes made by
+/// [`update`](Self::update) and by direct edits apply only to the local
+/// copies.
+pub struct LocalExecutor {
+    client:     v2::Client,
+    block_hash: BlockHash,
+    slot_time:  Timestamp,
+    energy:     Option<Energy>,
+    modules:    BTreeMap<ModuleReference, Arc<Vec<u8>>>,
+    local:      LocalState,
+}
+
+impl LocalExecutor {
+    /// Construct an executor that executes against the state of the given
+    /// block. The slot time of the block is used as the current time.
+    pub async fn new(
+        mut client: v2::Client,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Self, LocalExecutionError> {
+        let block_info = client.get_block_info(bi).await?;
+        let slot_time = Timestamp {
+            millis: block_info.response.block_slot_time.timestamp_millis() as u64,
+        };
+        Ok(Self {
+            client,
+            block_hash: block_info.block_hash,
+            slot_time,
+            energy: None,
+            modules: BTreeMap::new(),
+            local: LocalState::default(),
+        })
+    }
+
+    /// Set the energy available to each top-level invocation. If `None`,
+    /// which is the default, the energy is unlimited.
+    pub fn set_energy(self, energy: Option<Energy>) -> Self { Self { energy, ..self } }
+
+    /// Set the current time observed by contracts.
+    pub fn set_slot_time(self, slot_time: Timestamp) -> Self { Self { slot_time, ..self } }
+
+    /// The hash of the block whose state is used.
+    pub fn block_hash(&self) -> BlockHash { self.block_hash }
+
+    /// Get the local copy of an instance, fetching it from the node if
+    /// needed. The returned instance can be edited.
+    pub async fn instance_mut(
+        &mut self,
+        address: ContractAddress,
+    ) -> Result<&mut LocalInstance, LocalExecutionError> {
+        if !self.load_instance(address).await? {
+            return Err(LocalExecutionError::InstanceNotFound(address));
+        }
+        Ok(self
+            .local
+            .instances
+            .get_mut(&address)
+            .expect("Instance was just loaded."))
+    }
+
+    /// Get the local copy of the balance of an account, fetching it from the
+    /// node if needed. The returned balance can be edited.
+    pub async fn account_mut(
+        &mut self,
+        address: AccountAddress,
+    ) -> Result<&mut LocalAccountBalance, LocalExecutionError> {
+        if self.account(address).await?.is_none() {
+            return Err(LocalExecutionError::AccountNotFound(address));
+        }
+        Ok(self
+            .local
+            .accounts
+            .get_mut(&address)
+            .expect("Account was just loaded."))
+    }
+
+    /// Invoke a receive function locally. Any changes to the state of
+    /// instances and balances are discarded afterwards.
+    ///
+    /// If the invoker in the context is `None`, the zero account address is
+    /// used, as the node does.
+    pub async fn invoke(
+        &mut self,
+        context: &ContractContext,
+    ) -> Result<LocalInvocation, LocalExecutionError> {
+        let local = self.local.clone();
+        let result = self.update(context).await;
+        self.local = local;
+        result
+    }
+
+    /// Invoke a receive function locally, keeping the changes to the state of
+    /// instances and balances if the invocation succeeds. This can be used to
+    /// simulate a sequence of updates.
+    ///
+    /// If the invoker in the context is `None`, the zero account address is
+    /// used, as the node does.
+    pub async fn update(
+        &mut self,
+        context: &ContractContext,
+    ) -> Result<LocalInvocation, LocalExecutionError> {
+        let invoker = context
+            .invoker
+            .unwrap_or(Address::Account(AccountAddress([0u8; 32])));
+        let invoker_account = match invoker {
+            Address::Account(account) => account,
+            Address::Contract(address) => self.instance_mut(address).await?.owner,
+        };
+        let frame = Frame {
+            invoker:    invoker_account,
+            sender:     invoker,
+            address:    context.contract,
+            entrypoint: context
+                .method
+                .as_receive_name()
+                .entrypoint_name()
+                .to_owned(),
+            parameter:  context.parameter.clone(),
+            amount:     context.amount,
+        };
+        let initial_energy = self.energy.map_or(UNLIMITED_ENERGY, |energy| {
+            energy.energy.saturating_mul(INTERPRETER_ENERGY_PER_ENERGY)
+        });
+        let mut energy = InterpreterEnergy::from(initial_energy);
+        let mut trace = Vec::new();
+        if !self.load_instance(frame.address).await? {
+            return Err(LocalExecutionError::InstanceNotFound(frame.address));
+        }
+        let outcome = self.run(frame, &mut trace, &mut energy).await?;
+        let used = initial_energy.saturating_sub(energy.energy);
+        Ok(LocalInvocation {
+            outcome,
+            trace,
+            used_energy: Energy::from(used.div_ceil(INTERPRETER_ENERGY_PER_ENERGY)),
+        })
+    }
+
+    /// Ensure the instance is loaded. Returns `false` if it does not exist.
+    async fn load_instance(
+        &mut self,
+        address: ContractAddress,
+    ) -> Result<bool, LocalExecutionError> {
+        if self.local.instances.contains_key(&address) {
+            return Ok(true);
+        }
+        let info = match self
+            .client
+            .get_instance_info(address, self.block_hash)
+            .await
+        {
+            Ok(info) => info.response,
+            Err(e) if e.is_not_found() => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let InstanceInfo::V1 {
+            owner,
+            amount,
+            methods,
+            name,
+            source_module,
+        } = info
+        else {
+            return Err(LocalExecutionError::UnsupportedInstanceVersion(address));
+        };
+        let entries: Vec<(Vec<u8>, Vec<u8>)> = self
+            .client
+            .get_instance_state(address, self.block_hash)
+            .await?
+            .response
+            .try_collect()
+            .await
+            .map_err(v2::QueryError::from)?;
+        self.local.instances.insert(address, LocalInstance {
+            owner,
+            name,
+            module: source_module,
+            entrypoints: methods,
+            balance: amount,
+            state: trie::PersistentState::from_iter(entries.into_iter()),
+        });
+        Ok(true)
+    }
+
+    /// Get the source of a V1 module. Returns `None` if it does not exist.
+    async fn module(
+        &mut self,
+        module_ref: ModuleReference,
+    ) -> Result<Option<Arc<Vec<u8>>>, LocalExecutionError> {
+        if let Some(source) = self.modules.get(&module_ref) {
+            return Ok(Some(source.clone()));
+        }
+        let module = match self
+            .client
+            .get_module_source(&module_ref, self.block_hash)
+            .await
+        {
+            Ok(module) => module.response,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if module.version != WasmVersion::V1 {
+            return Err(LocalExecutionError::UnsupportedModuleVersion(module_ref));
+        }
+        let source = Arc::new(module.source.as_ref().clone());
+        self.modules.insert(module_ref, source.clone());
+        Ok(Some(source))
+    }
+
+    /// Get the balance of an account. Returns `None` if it does not exist.
+    async fn account(
+        &mut self,
+        address: AccountAddress,
+    ) -> Result<Option<LocalAccountBalance>, LocalExecutionError> {
+        if let Some(balance) = self.local.accounts.get(&address) {
+            return Ok(Some(*balance));
+        }
+        let info = match self
+            .client
+            .get_account_info(&AccountIdentifier::Address(address), self.block_hash)
+            .await
+        {
+            Ok(info) => info.response,
+            Err(e) if e.is_not_found() => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let staked = info
+            .account_stake
+            .as_ref()
+            .map_or(Amount::zero(), |stake| stake.staked_amount());
+        let balance = LocalAccountBalance {
+            total: info.account_amount,
+            staked,
+            locked: info.account_release_schedule.total,
+        };
+        self.local.accounts.insert(address, balance);
+        Ok(Some(balance))
+    }
+
+    /// Load the address and deduct the amount from its balance.
+    async fn debit(&mut self, address: Address, amount: Amount) -> Result<(), LocalExecutionError> {
+        match address {
+            Address::Account(account) => {
+                self.account_mut(account).await?;
+            }
+            Address::Contract(contract) => {
+                self.instance_mut(contract).await?;
+            }
+        }
+        self.local.debit(address, amount)
+    }
+
+    /// Execute a receive invocation of a loaded instance, transferring the
+    /// amount from the sender. The effects of the invocation, including the
+    /// transfer and nested calls, are rolled back unless it succeeds.
+    fn run<'a>(
+        &'a mut self,
+        frame: Frame,
+        trace: &'a mut Vec<ContractTraceElement>,
+        energy: &'a mut InterpreterEnergy,
+    ) -> BoxFuture<'a, Result<LocalOutcome, LocalExecutionError>> {
+        async move {
+            let local = self.local.clone();
+            self.debit(frame.sender, frame.amount).await?;
+            let outcome = self.execute(frame, trace, energy).await?;
+            if !outcome.is_success() {
+                self.local = local;
+            }
+            Ok(outcome)
+        }
+        .boxed()
+    }
+
+    async fn execute(
+        &mut self,
+        frame: Frame,
+        trace: &mut Vec<ContractTraceElement>,
+        energy: &mut InterpreterEnergy,
+    ) -> Result<LocalOutcome, LocalExecutionError> {
+        let address = frame.address;
+        let Some(instance) = self.local.instances.get_mut(&address) else {
+            return Err(LocalExecutionError::InstanceNotFound(address));
+        };
+        instance.balance += frame.amount;
+        let instance = instance.clone();
+        let Some(source) = self.module(instance.module).await? else {
+            return Err(LocalExecutionError::UnsupportedModuleVersion(
+                instance.module,
+            ));
+        };
+
+        let receive_name = OwnedReceiveName::construct_unchecked(
+            instance.name.as_contract_name(),
+            frame.entrypoint.as_entrypoint_name(),
+        );
+        // Invoke the fallback entrypoint if the entrypoint does not exist.
+        let receive_name = if instance.entrypoints.contains(&receive_name) {
+            receive_name
+        } else {
+            OwnedReceiveName::construct_unchecked(
+                instance.name.as_contract_name(),
+                EntrypointName::new_unchecked(""),
+            )
+        };
+        let receive_ctx = v1::ReceiveContext {
+            common:     v0::ReceiveContext {
+                metadata:        ChainMetadata {
+                    slot_time: self.slot_time,
+                },
+                invoker:         frame.invoker,
+                self_address:    address,
+                self_balance:    instance.balance,
+                sender:          frame.sender,
+                owner:           instance.owner,
+                sender_policies: NO_POLICIES.to_vec(),
+            },
+            entrypoint: frame.entrypoint.clone(),
+        };
+        let mut state = instance.state.thaw();
+        let instance_state = v1::InstanceState::new(loader(), state.get_inner(&mut loader()));
+        let mut result = v1::invoke_receive_with_metering_from_source(
+            ValidationConfig::V1,
+            &source,
+            receive_ctx,
+            v1::ReceiveInvocation {
+                amount:       frame.amount,
+                receive_name: receive_name.as_receive_name(),
+                parameter:    frame.parameter.as_ref(),
+                energy:       *energy,
+            },
+            instance_state,
+            receive_params(),
+        )?;
+        // The events logged before each interrupt are part of the trace
+        // elements of the interrupt, so only the remaining events are part of
+        // the final update.
+        loop {
+            match result {
+                v1::ReceiveResult::Success {
+                    logs,
+                    state_changed,
+                    return_value,
+                    remaining_energy,
+                } => {
+                    *energy = remaining_energy;
+                    if state_changed {
+                        self.local.write_state(address, state);
+                    }
+                    trace.push(ContractTraceElement::Updated {
+                        data: InstanceUpdatedEvent {
+                            contract_version: WasmVersion::V1,
+                            address,
+                            instigator: frame.sender,
+                            amount: frame.amount,
+                            message: frame.parameter,
+                            receive_name,
+                            events: logs.logs.into_iter().map(ContractEvent::from).collect(),
+                        },
+                    });
+                    return Ok(LocalOutcome::Success {
+                        return_value: Some(return_value),
+                    });
+                }
+                v1::ReceiveResult::Interrupt {
+                    remaining_energy,
+                    state_changed,
+                    logs,
+                    config,
+                    interrupt,
+                } => {
+                    *energy = remaining_energy;
+                    if state_changed {
+                        self.local.write_state(address, state);
+                    }
+                    let traced = matches!(
+                        interrupt,
+                        v1::Interrupt::Transfer { .. }
+                            | v1::Interrupt::Call { .. }
+                            | v1::Interrupt::Upgrade { .. }
+                    );
+                    if traced {
+                        trace.push(ContractTraceElement::Interrupted {
+                            address,
+                            events: logs.logs.into_iter().map(ContractEvent::from).collect(),
+                        });
+                    }
+                    let writes_before =
+                        self.local.writes.get(&address).copied().unwrap_or_default();
+                    let response = self
+                        .handle_interrupt(address, frame.invoker, interrupt, trace, energy)
+                        .await?;
+                    let writes_after = self.local.writes.get(&address).copied().unwrap_or_default();
+                    if traced {
+                        trace.push(ContractTraceElement::Resumed {
+                            address,
+                            success: matches!(response, InvokeResponse::Success { .. }),
+                        });
+                    }
+                    state = self.local.instances[&address].state.thaw();
+                    result = v1::resume_receive(
+                        config,
+                        response,
+                        *energy,
+                        &mut state,
+                        writes_before != writes_after,
+                        loader(),
+                    )?;
+                }
+                v1::ReceiveResult::Reject {
+                    reason,
+                    return_value,
+                    remaining_energy,
+                } => {
+                    *energy = remaining_energy;
+                    return Ok(LocalOutcome::Reject {
+                        reason,
+                        return_value: Some(return_value),
+                    });
+                }
+                v1::ReceiveResult::Trap {
+                    error,
+                    remaining_energy,
+                } => {
+                    *energy = remaining_energy;
+                    return Ok(LocalOutcome::Trap {
+                        error: format!("{error:#}"),
+                    });
+                }
+                v1::ReceiveResult::OutOfEnergy => {
+                    *energy = InterpreterEnergy::from(0);
+                    return Ok(LocalOutcome::OutOfEnergy);
+                }
+            }
+        }
+    }
+
+    /// Handle an interrupt of the instance at the given address, returning
+    /// the response to resume the instance with.
+    async fn handle_interrupt(
+        &mut self,
+        address: ContractAddress,
+        invoker: AccountAddress,
+        interrupt: v1::Interrupt,
+        trace: &mut Vec<ContractTraceElement>,
+        energy: &mut InterpreterEnergy,
+    ) -> Result<InvokeResponse, LocalExecutionError> {
+        let failure = |kind| Ok(InvokeResponse::Failure { kind });
+        let balance = self.local.instances[&address].balance;
+        match interrupt {
+            v1::Interrupt::Transfer { to, amount } => {
+                if amount > balance {
+                    return failure(InvokeFailure::AmountTooLarge);
+                }
+                if self.account(to).await?.is_none() {
+                    return failure(InvokeFailure::NonExistentAccount);
+                }
+                self.debit(Address::Contract(address), amount).await?;
+                self.account_mut(to).await?.total += amount;
+                trace.push(ContractTraceElement::Transferred {
+                    from: address,
+                    amount,
+                    to,
+                });
+                Ok(InvokeResponse::Success {
+                    new_balance: self.local.instances[&address].balance,
+                    data:        None,
+                })
+            }
+            v1::Interrupt::Call {
+                address: target,
+                parameter,
+                name,
+                amount,
+            } => {
+                if amount > balance {
+                    return failure(InvokeFailure::AmountTooLarge);
+                }
+                if !self.load_instance(target).await? {
+                    return failure(InvokeFailure::NonExistentContract);
+                }
+                let target_instance = &self.local.instances[&target];
+                let has_entrypoint = [name.as_entrypoint_name(), EntrypointName::new_unchecked("")]
+                    .into_iter()
+                    .any(|entrypoint| {
+                        target_instance.entrypoints.contains(
+                            &OwnedReceiveName::construct_unchecked(
+                                target_instance.name.as_contract_name(),
+                                entrypoint,
+                            ),
+                        )
+                    });
+                if !has_entrypoint {
+                    return failure(InvokeFailure::NonExistentEntrypoint);
+                }
+                let frame = Frame {
+                    invoker,
+                    sender: Address::Contract(address),
+                    address: target,
+                    entrypoint: name,
+                    parameter: OwnedParameter::new_unchecked(parameter),
+                    amount,
+                };
+                match self.run(frame, trace, energy).await? {
+                    LocalOutcome::Success { return_value } => Ok(InvokeResponse::Success {
+                        new_balance: self.local.instances[&address].balance,
+                        data:        return_value,
+                    }),
+                    LocalOutcome::Reject {
+                        reason,
+                        return_value,
+                    } => failure(InvokeFailure::ContractReject {
+                        code: reason,
+                        data: return_value.unwrap_or_default(),
+                    }),
+                    LocalOutcome::Trap { .. } | LocalOutcome::OutOfEnergy => {
+                        failure(InvokeFailure::RuntimeError)
+                    }
+                }
+            }
+            v1::Interrupt::Upgrade { module_ref } => {
+                match self.module(module_ref).await {
+                    Ok(Some(_)) => {}
+                    Ok(None) | Err(LocalExecutionError::UnsupportedModuleVersion(_)) => {
+                        return failure(InvokeFailure::UpgradeInvalidModuleRef { module_ref });
+                    }
+                    Err(e) => return Err(e),
+                }
+                let instance = self
+                    .local
+                    .instances
+                    .get_mut(&address)
+                    .expect("Interrupted instance is loaded.");
+                let from = instance.module;
+                instance.module = module_ref;
+                trace.push(ContractTraceElement::Upgraded {
+                    address,
+                    from,
+                    to: module_ref,
+                });
+                Ok(InvokeResponse::Success {
+                    new_balance: balance,
+                    data:        None,
+                })
+            }
+            v1::Interrupt::QueryAccountBalance { address: account } => {
+                let Some(account_balance) = self.account(account).await? else {
+                    return failure(InvokeFailure::NonExistentAccount);
+                };
+                Ok(InvokeResponse::Success {
+                    new_balance: balance,
+                    data:        Some(to_bytes(&(
+                        account_balance.total,
+                        account_balance.staked,
+                        account_balance.locked,
+                    ))),
+                })
+            }
+            v1::Interrupt::QueryContractBalance { address: contract } => {
+                if !self.load_instance(contract).await? {
+                    return failure(InvokeFailure::NonExistentContract);
+                }
+                Ok(InvokeResponse::Success {
+                    new_balance: balance,
+                    data:        Some(to_bytes(&self.local.instances[&contract].balance)),
+                })
+            }
+            v1::Interrupt::QueryContractModuleReference { address: contract } => {
+                if !self.load_instance(contract).await? {
+                    return failure(InvokeFailure::NonExistentContract);
+                }
+                Ok(InvokeResponse::Success {
+                    new_balance: balance,
+                    data:        Some(to_bytes(&self.local.instances[&contract].module)),
+                })
+            }
+            v1::Interrupt::QueryContractName { address: contract } => {
+                if !self.load_instance(contract).await? {
+                    return failure(InvokeFailure::NonExistentContract);
+                }
+                Ok(InvokeResponse::Success {
+                    new_balance: balance,
+                    data:        Some(
+                        self.local.instances[&contract]
+                            .name
+                            .as_contract_name()
+                            .get_chain_name()
+                            .as_bytes()
+                            .to_vec(),
+                    ),
+                })
+            }
+            v1::Interrupt::QueryExchangeRates => Err(LocalExecutionError::UnsupportedOperation(
+                "query_exchange_rates",
+            )),
+            v1::Interrupt::CheckAccountSignature { .. } => Err(
+                LocalExecutionError::UnsupportedOperation("check_account_signature"),
+            ),
+            v1::Interrupt::QueryAccountKeys { .. } => Err(
+                LocalExecutionError::UnsupportedOperation("get_account_public_keys"),
+            ),
+        }
+    }
+}
+
+/// A loader for the in-memory state tries. The state of instances is never
+/// persisted to disk, so there is nothing to load from.
+fn loader() -> trie::Loader<&'static [u8]> { trie::Loader::new(&[][..]) }
+
+/// The parameters for executing receive functions. These match the current
+/// protocol, except that the size of logs and return values is not limited.
+fn receive_params() -> v1::ReceiveParams {
+    v1::ReceiveParams {
+        max_parameter_size:                  u16::MAX.into(),
+        limit_logs_and_return_values:        false,
+        support_queries:                     true,
+        support_account_signature_checks:    true,
+        support_contract_inspection_queries: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: AccountAddress = AccountAddress([1u8; 32]);
+    const CONTRACT: ContractAddress = ContractAddress {
+        index:    1,
+        subindex: 0,
+    };
+
+    fn ccd(micro_ccd: u64) -> Amount { Amount::from_micro_ccd(micro_ccd) }
+
+    /// A state with an account with 100 microCCD, of which 30 are staked and
+    /// 50 locked, and an instance with 10 microCCD.
+    fn local_state() -> LocalState {
+        let mut local = LocalState::default();
+        local.accounts.insert(ACCOUNT, LocalAccountBalance {
+            total:  ccd(100),
+            staked: ccd(30),
+            locked: ccd(50),
+        });
+        local.instances.insert(CONTRACT, LocalInstance {
+            owner:       ACCOUNT,
+            name:        OwnedContractName::new_unchecked("init_counter".into()),
+            module:      ModuleReference::new([0u8; 32]),
+            entrypoints: BTreeSet::new(),
+            balance:     ccd(10),
+            state:       trie::PersistentState::from_iter(std::iter::empty()),
+        });
+        local
+    }
+
+    #[test]
+    /// Test that staked and locked amounts are not available.
+    fn available_balance() {
+        let balance = LocalAccountBalance {
+            total:  ccd(100),
+            staked: ccd(30),
+            locked: ccd(50),
+        };
+        assert_eq!(balance.available(), ccd(20));
+        let balance = LocalAccountBalance {
+            total:  ccd(100),
+            staked: ccd(60),
+            locked: ccd(50),
+        };
+        assert_eq!(balance.available(), Amount::zero());
+    }
+
+    #[test]
+    /// Test debiting accounts and instances.
+    fn debit() {
+        let mut local = local_state();
+        local
+            .debit(Address::Account(ACCOUNT), ccd(15))
+            .expect("The amount is available.");
+        assert_eq!(local.accounts[&ACCOUNT].total, ccd(85));
+        assert!(matches!(
+            local.debit(Address::Account(ACCOUNT), ccd(6)),
+            Err(LocalExecutionError::InsufficientBalance { amount, balance })
+                if amount == ccd(6) && balance == ccd(5)
+        ));
+        assert_eq!(local.accounts[&ACCOUNT].total, ccd(85));
+
+        local
+            .debit(Address::Contract(CONTRACT), ccd(10))
+            .expect("The instance has the balance.");
+        assert_eq!(local.instances[&CONTRACT].balance, Amount::zero());
+        assert!(matches!(
+            local.debit(Address::Contract(CONTRACT), ccd(1)),
+            Err(LocalExecutionError::InsufficientBalance { .. })
+        ));
+
+        let unknown = ContractAddress::new(2, 0);
+        assert!(matches!(
+            local.debit(Address::Contract(unknown), Amount::zero()),
+            Err(LocalExecutionError::InstanceNotFound(address)) if address == unknown
+        ));
+        assert!(matches!(
+            local.debit(Address::Account(AccountAddress([2u8; 32])), Amount::zero()),
+            Err(LocalExecutionError::AccountNotFound(_))
+        ));
+    }
+
+    #[test]
+    /// Test that restoring a clone of the state rolls back balances and the
+    /// writes of instance states.
+    fn rollback() {
+        let mut local = local_state();
+        let checkpoint = local.clone();
+        local
+            .debit(Address::Account(ACCOUNT), ccd(20))
+            .expect("The amount is available.");
+        local
+            .debit(Address::Contract(CONTRACT), ccd(10))
+            .expect("The instance has the balance.");
+        let state = trie::PersistentState::from_iter(std::iter::once((vec![0u8], vec![1u8])));
+        local.write_state(CONTRACT, state.thaw());
+        assert_eq!(local.writes.get(&CONTRACT), Some(&1));
+
+        local = checkpoint;
+        assert_eq!(local.accounts[&ACCOUNT].total, ccd(100));
+        assert_eq!(local.instances[&CONTRACT].balance, ccd(10));
+        assert_eq!(local.writes.get(&CONTRACT), None);
+    }
+}
@@ -8,3 +8,10 @@ pub use concordium_smart_contract_engine as engine;
 
 /// Functionality that is common to on and off-chain smart contracts.
 pub use concordium_base::contracts_common as common;
+
+/// Local, off-chain execution of smart contracts against the state of the
+/// chain.
+pub mod local;
+
+/// Inspection of smart contract modules.
+pub mod module_info;
@@ -0,0 +1,577 @@
+//! Inspection of smart contract modules.
+//!
+//! A [`ModuleInfo`] reports the contracts and entrypoints of a module, the
+//! schema embedded in it, the host functions it imports, its custom sections
+//! and its size. It can be constructed from a [`WasmModule`], e.g., as returned
+//! by [`get_module_source`](v2::Client::get_module_source), from a local
+//! `.wasm.v1` file, or by querying the node. The [`Display`](fmt::Display)
+//! implementation prints a human readable report, with the types of the
+//! schema pretty-printed.
+use crate::{
+    contract_client::{codegen::entrypoint_schemas, get_embedded_schema},
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{
+    contracts_common::schema::{Fields, SizeLength, Type, VersionedModuleSchema},
+    smart_contracts::{ModuleReference, WasmModule, WasmVersion},
+};
+use concordium_wasm::{
+    parse,
+    types::{ExportDescription, ExportSection, ImportSection},
+};
+use std::{collections::BTreeMap, fmt, path::Path};
+
+/// An error that occurred while inspecting a module.
+#[derive(thiserror::Error, Debug)]
+pub enum ModuleInfoError {
+    #[error("Query failed: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Could not read the module: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Malformed Wasm module: {0:#}")]
+    Malformed(#[from] anyhow::Error),
+}
+
+/// A report on a smart contract module.
+#[derive(Debug, Clone)]
+pub struct ModuleInfo {
+    /// The reference of the module.
+    pub module_ref:      ModuleReference,
+    /// The version of the module.
+    pub version:         WasmVersion,
+    /// The size of the Wasm module in bytes.
+    pub size:            usize,
+    /// The contracts in the module, ordered by name.
+    pub contracts:       Vec<ContractInfo>,
+    /// The schema embedded in the module, if any.
+    pub schema:          Option<VersionedModuleSchema>,
+    /// The functions imported by the module, i.e., the host functions used by
+    /// the contracts.
+    pub imports:         Vec<ImportedFunction>,
+    /// The custom sections of the module.
+    pub custom_sections: Vec<CustomSectionInfo>,
+}
+
+/// A contract in a module.
+#[derive(Debug, Clone)]
+pub struct ContractInfo {
+    /// The name of the contract, without the `init_` prefix.
+    pub name:           String,
+    /// Whether the module exports the init function of the contract.
+    pub has_init:       bool,
+    /// The type of the parameter of the init function in the schema, if any.
+    pub init_parameter: Option<Type>,
+    /// The entrypoints of the contract, ordered by name. This does not include
+    /// the fallback entrypoint.
+    pub entrypoints:    Vec<EntrypointInfo>,
+    /// Whether the contract has a fallback entrypoint.
+    pub has_fallback:   bool,
+    /// The type of the events of the contract in the schema, if any.
+    pub event:          Option<Type>,
+}
+
+/// An entrypoint of a contract.
+#[derive(Debug, Clone)]
+pub struct EntrypointInfo {
+    /// The name of the entrypoint.
+    pub name:         String,
+    /// The type of the parameter in the schema, if any.
+    pub parameter:    Option<Type>,
+    /// The type of the return value in the schema, if any.
+    pub return_value: Option<Type>,
+    /// The type of the error in the schema, if any.
+    pub error:        Option<Type>,
+}
+
+/// A function imported by a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedFunction {
+    /// The name of the module the function is imported from, which is
+    /// `concordium` for host functions.
+    pub module: String,
+    /// The name of the function.
+    pub name:   String,
+}
+
+/// A custom section of a module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomSectionInfo {
+    /// The name of the section, e.g., `concordium-schema`.
+    pub name: String,
+    /// The size of the contents of the section in bytes.
+    pub size: usize,
+}
+
+impl ModuleInfo {
+    /// Inspect the module.
+    pub fn new(module: &WasmModule) -> Result<Self, ModuleInfoError> {
+        let source = module.source.as_ref();
+        let sections = parse_sections(source)?;
+        let schema = get_embedded_schema(module);
+
+        let mut contracts = BTreeMap::<String, ContractInfo>::new();
+        for export in &sections.function_exports {
+            if let Some(name) = export.strip_prefix("init_") {
+                contract_entry(&mut contracts, name).has_init = true;
+            } else if let Some((name, entrypoint)) = export.split_once('.') {
+                let contract = contract_entry(&mut contracts, name);
+                if entrypoint.is_empty() {
+                    contract.has_fallback = true;
+                } else {
+                    contract.entrypoints.push(EntrypointInfo {
+                        name:         entrypoint.to_string(),
+                        parameter:    None,
+                        return_value: None,
+                        error:        None,
+                    });
+                }
+            }
+        }
+
+        let mut contracts: Vec<_> = contracts.into_values().collect();
+        for contract in &mut contracts {
+            contract.entrypoints.sort_by(|a, b| a.name.cmp(&b.name));
+            let Some(schema) = &schema else {
+                continue;
+            };
+            contract.init_parameter = schema.get_init_param_schema(&contract.name).ok();
+            contract.event = schema.get_event_schema(&contract.name).ok();
+            let Some(schemas) = entrypoint_schemas(schema, &contract.name) else {
+                continue;
+            };
+            for ep in schemas {
+                if let Some(entrypoint) =
+                    contract.entrypoints.iter_mut().find(|e| e.name == ep.name)
+                {
+                    entrypoint.parameter = ep.parameter;
+                    entrypoint.return_value = ep.return_value;
+                    entrypoint.error = ep.error;
+                }
+            }
+        }
+
+        Ok(Self {
+            module_ref: module.get_module_ref(),
+            version: module.version,
+            size: source.len(),
+            contracts,
+            schema,
+            imports: sections.imports,
+            custom_sections: sections.custom,
+        })
+    }
+
+    /// Inspect the module at the given path, e.g., a `.wasm.v1` file produced
+    /// by `cargo concordium`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ModuleInfoError> {
+        Self::new(&WasmModule::from_file(path.as_ref())?)
+    }
+
+    /// Inspect the module with the given reference, as it is in the given
+    /// block.
+    pub async fn query(
+        client: &mut v2::Client,
+        module_ref: &ModuleReference,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Self, ModuleInfoError> {
+        let module = client.get_module_source(module_ref, bi).await?.response;
+        Self::new(&module)
+    }
+
+    /// The version of the embedded schema, from 0 to 3, if the module has a
+    /// schema.
+    pub fn schema_version(&self) -> Option<u8> {
+        self.schema.as_ref().map(|schema| match schema {
+            VersionedModuleSchema::V0(_) => 0,
+            VersionedModuleSchema::V1(_) => 1,
+            VersionedModuleSchema::V2(_) => 2,
+            VersionedModuleSchema::V3(_) => 3,
+        })
+    }
+
+    /// Get the contract with the given name.
+    pub fn contract(&self, name: &str) -> Option<&ContractInfo> {
+        self.contracts.iter().find(|contract| contract.name == name)
+    }
+
+    /// Whether the module has a custom section with the given name.
+    pub fn has_custom_section(&self, name: &str) -> bool {
+        self.custom_sections
+            .iter()
+            .any(|section| section.name == name)
+    }
+}
+
+impl fmt::Display for ModuleInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Module {} ({:?}, {} bytes)",
+            self.module_ref, self.version, self.size
+        )?;
+        match self.schema_version() {
+            Some(version) => writeln!(f, "Schema: V{version}")?,
+            None => writeln!(f, "Schema: none")?,
+        }
+        for contract in &self.contracts {
+            writeln!(f, "Contract {}:", contract.name)?;
+            if contract.has_init {
+                write!(f, "  init")?;
+                write_type(f, "parameter", &contract.init_parameter)?;
+                writeln!(f)?;
+            }
+            for entrypoint in &contract.entrypoints {
+                write!(f, "  {}", entrypoint.name)?;
+                write_type(f, "parameter", &entrypoint.parameter)?;
+                write_type(f, "return value", &entrypoint.return_value)?;
+                write_type(f, "error", &entrypoint.error)?;
+                writeln!(f)?;
+            }
+            if contract.has_fallback {
+                writeln!(f, "  (fallback)")?;
+            }
+            if let Some(event) = &contract.event {
+                writeln!(f, "  events: {}", PrettyType(event))?;
+            }
+        }
+        writeln!(f, "Imports:")?;
+        for import in &self.imports {
+            writeln!(f, "  {}.{}", import.module, import.name)?;
+        }
+        writeln!(f, "Custom sections:")?;
+        for section in &self.custom_sections {
+            writeln!(f, "  {} ({} bytes)", section.name, section.size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Get the contract with the given name, adding it if it is not there.
+fn contract_entry<'a>(
+    contracts: &'a mut BTreeMap<String, ContractInfo>,
+    name: &str,
+) -> &'a mut ContractInfo {
+    contracts
+        .entry(name.to_string())
+        .or_insert_with(|| ContractInfo {
+            name:           name.to_string(),
+            has_init:       false,
+            init_parameter: None,
+            entrypoints:    Vec::new(),
+            has_fallback:   false,
+            event:          None,
+        })
+}
+
+fn write_type(f: &mut fmt::Formatter<'_>, label: &str, ty: &Option<Type>) -> fmt::Result {
+    match ty {
+        Some(ty) => write!(f, "\n    {label}: {}", PrettyType(ty)),
+        None => Ok(()),
+    }
+}
+
+/// Displays a schema type in a Rust-like syntax.
+pub struct PrettyType<'a>(pub &'a Type);
+
+impl fmt::Display for PrettyType<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Type::Unit => f.write_str("()"),
+            Type::Bool => f.write_str("bool"),
+            Type::U8 => f.write_str("u8"),
+            Type::U16 => f.write_str("u16"),
+            Type::U32 => f.write_str("u32"),
+            Type::U64 => f.write_str("u64"),
+            Type::U128 => f.write_str("u128"),
+            Type::I8 => f.write_str("i8"),
+            Type::I16 => f.write_str("i16"),
+            Type::I32 => f.write_str("i32"),
+            Type::I64 => f.write_str("i64"),
+            Type::I128 => f.write_str("i128"),
+            Type::Amount => f.write_str("Amount"),
+            Type::AccountAddress => f.write_str("AccountAddress"),
+            Type::ContractAddress => f.write_str("ContractAddress"),
+            Type::Timestamp => f.write_str("Timestamp"),
+            Type::Duration => f.write_str("Duration"),
+            Type::Pair(a, b) => write!(f, "({}, {})", PrettyType(a), PrettyType(b)),
+            Type::List(len, ty) => write!(f, "Vec<{}>{}", PrettyType(ty), Len(*len)),
+            Type::Set(len, ty) => write!(f, "Set<{}>{}", PrettyType(ty), Len(*len)),
+            Type::Map(len, k, v) => {
+                write!(f, "Map<{}, {}>{}", PrettyType(k), PrettyType(v), Len(*len))
+            }
+            Type::Array(n, ty) => write!(f, "[{}; {n}]", PrettyType(ty)),
+            Type::Struct(fields) => write!(f, "struct {}", PrettyFields(fields)),
+            Type::Enum(variants) => {
+                f.write_str("enum { ")?;
+                for (i, (name, fields)) in variants.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{name}{}", PrettyFields(fields))?;
+                }
+                f.write_str(" }")
+            }
+            Type::TaggedEnum(variants) => {
+                f.write_str("enum { ")?;
+                for (i, (tag, (name, fields))) in variants.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{name}{} = {tag}", PrettyFields(fields))?;
+                }
+                f.write_str(" }")
+            }
+            Type::String(len) => write!(f, "String{}", Len(*len)),
+            Type::ContractName(len) => write!(f, "ContractName{}", Len(*len)),
+            Type::ReceiveName(len) => write!(f, "ReceiveName{}", Len(*len)),
+            Type::ULeb128(n) => write!(f, "ULeb128<{n}>"),
+            Type::ILeb128(n) => write!(f, "ILeb128<{n}>"),
+            Type::ByteList(len) => write!(f, "Vec<u8>{}", Len(*len)),
+            Type::ByteArray(n) => write!(f, "[u8; {n}]"),
+        }
+    }
+}
+
+/// Displays the fields of a struct or enum variant.
+struct PrettyFields<'a>(&'a Fields);
+
+impl fmt::Display for PrettyFields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Fields::Named(fields) => {
+                f.write_str(" { ")?;
+                for (i, (name, ty)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{name}: {}", PrettyType(ty))?;
+                }
+                f.write_str(" }")
+            }
+            Fields::Unnamed(fields) => {
+                f.write_str("(")?;
+                for (i, ty) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", PrettyType(ty))?;
+                }
+                f.write_str(")")
+            }
+            Fields::None => Ok(()),
+        }
+    }
+}
+
+/// Displays the size of the length prefix of a collection.
+struct Len(SizeLength);
+
+impl fmt::Display for Len {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = match self.0 {
+            SizeLength::U8 => 1,
+            SizeLength::U16 => 2,
+            SizeLength::U32 => 4,
+            SizeLength::U64 => 8,
+        };
+        write!(f, " (length: {bytes} bytes)")
+    }
+}
+
+/// The parts of a Wasm module relevant for the report.
+struct Sections {
+    imports:          Vec<ImportedFunction>,
+    function_exports: Vec<String>,
+    custom:           Vec<CustomSectionInfo>,
+}
+
+/// Parse the import, export and custom sections of a Wasm module. The
+/// remaining sections are skipped without being validated.
+fn parse_sections(source: &[u8]) -> Result<Sections, ModuleInfoError> {
+    let skeleton = parse::parse_skeleton(source)?;
+    let imports: ImportSection = parse::parse_sec_with_default(parse::EMPTY_CTX, &skeleton.import)?;
+    let exports: ExportSection = parse::parse_sec_with_default(parse::EMPTY_CTX, &skeleton.export)?;
+    let custom = skeleton
+        .custom
+        .iter()
+        .map(|section| {
+            let section = parse::parse_custom(section)?;
+            Ok(CustomSectionInfo {
+                name: section.name.name,
+                size: section.contents.len(),
+            })
+        })
+        .collect::<Result<_, ModuleInfoError>>()?;
+    Ok(Sections {
+        imports: imports
+            .imports
+            .into_iter()
+            .map(|import| ImportedFunction {
+                module: import.mod_name.name,
+                name:   import.item_name.name,
+            })
+            .collect(),
+        function_exports: exports
+            .exports
+            .into_iter()
+            .filter(|export| matches!(export.description, ExportDescription::Func { .. }))
+            .map(|export| export.name.name)
+            .collect(),
+        custom,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(id: u8, contents: Vec<u8>) -> Vec<u8> {
+        let mut out = vec![id, contents.len() as u8];
+        out.extend(contents);
+        out
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut out = vec![name.len() as u8];
+        out.extend(name.as_bytes());
+        out
+    }
+
+    /// A module with a single function, which imports a host function and
+    /// exports the function as the init function, an entrypoint and the
+    /// fallback entrypoint of a contract.
+    fn module() -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend([1, 0, 0, 0]);
+        // A single function type without parameters and results.
+        out.extend(section(1, vec![1, 0x60, 0, 0]));
+        let mut imports = vec![1];
+        imports.extend(name("concordium"));
+        imports.extend(name("accept"));
+        imports.extend([0, 0]);
+        out.extend(section(2, imports));
+        out.extend(section(3, vec![1, 0]));
+        let mut exports = vec![4];
+        for export in ["init_counter", "counter.increment", "counter."] {
+            exports.extend(name(export));
+            exports.extend([0, 1]);
+        }
+        exports.extend(name("memory"));
+        exports.extend([2, 0]);
+        out.extend(section(7, exports));
+        out.extend(section(10, vec![1, 2, 0, 0x0b]));
+        let mut custom = name("test-section");
+        custom.extend([1, 2, 3]);
+        out.extend(section(0, custom));
+        out
+    }
+
+    #[test]
+    /// Test that the contracts, imports and custom sections of a module are
+    /// reported.
+    fn inspect_module() -> anyhow::Result<()> {
+        let source = module();
+        let info = ModuleInfo::new(&WasmModule {
+            version: WasmVersion::V1,
+            source:  source.clone().into(),
+        })?;
+        assert_eq!(info.size, source.len());
+        assert!(info.schema.is_none());
+        assert_eq!(info.imports, vec![ImportedFunction {
+            module: "concordium".into(),
+            name:   "accept".into(),
+        }]);
+        assert_eq!(info.custom_sections, vec![CustomSectionInfo {
+            name: "test-section".into(),
+            size: 3,
+        }]);
+        assert!(info.has_custom_section("test-section"));
+        assert!(!info.has_custom_section("concordium-schema"));
+
+        assert_eq!(info.contracts.len(), 1);
+        let contract = info.contract("counter").expect("Contract exists.");
+        assert!(contract.has_init);
+        assert!(contract.has_fallback);
+        let entrypoints: Vec<_> = contract.entrypoints.iter().map(|e| &e.name[..]).collect();
+        assert_eq!(entrypoints, vec!["increment"]);
+        assert!(info
+            .to_string()
+            .contains("Contract counter:\n  init\n  increment\n  (fallback)\n"));
+        Ok(())
+    }
+
+    #[test]
+    /// Test that malformed modules are rejected.
+    fn reject_malformed_module() {
+        let parse = |source: Vec<u8>| {
+            ModuleInfo::new(&WasmModule {
+                version: WasmVersion::V1,
+                source:  source.into(),
+            })
+        };
+        let mut source = module();
+        source[0] = b'x';
+        assert!(matches!(parse(source), Err(ModuleInfoError::Malformed(_))));
+
+        let mut source = module();
+        source.truncate(source.len() - 1);
+        assert!(matches!(parse(source), Err(ModuleInfoError::Malformed(_))));
+
+        // A section length with bits that do not fit in 32 bits.
+        let mut source = module()[..8].to_vec();
+        source.extend([0, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        assert!(matches!(parse(source), Err(ModuleInfoError::Malformed(_))));
+    }
+
+    #[test]
+    /// Test printing schema types.
+    fn pretty_types() {
+        let pretty = |ty: Type| PrettyType(&ty).to_string();
+        assert_eq!(pretty(Type::Unit), "()");
+        assert_eq!(
+            pretty(Type::Pair(
+                Box::new(Type::U8),
+                Box::new(Type::ByteArray(32))
+            )),
+            "(u8, [u8; 32])"
+        );
+        assert_eq!(pretty(Type::Array(3, Box::new(Type::I64))), "[i64; 3]");
+        assert_eq!(
+            pretty(Type::String(SizeLength::U16)),
+            "String (length: 2 bytes)"
+        );
+        assert_eq!(pretty(Type::ULeb128(5)), "ULeb128<5>");
+        assert_eq!(
+            pretty(Type::Struct(Fields::Named(vec![
+                ("owner".into(), Type::AccountAddress),
+                (
+                    "tokens".into(),
+                    Type::Map(
+                        SizeLength::U32,
+                        Box::new(Type::U64),
+                        Box::new(Type::List(SizeLength::U8, Box::new(Type::Bool)))
+                    )
+                ),
+            ]))),
+            "struct { owner: AccountAddress, tokens: Map<u64, Vec<bool> (length: 1 bytes)> \
+             (length: 4 bytes) }"
+        );
+        assert_eq!(
+            pretty(Type::Enum(vec![
+                ("None".into(), Fields::None),
+                ("Some".into(), Fields::Unnamed(vec![Type::U8, Type::Amount])),
+            ])),
+            "enum { None, Some(u8, Amount) }"
+        );
+        assert_eq!(
+            pretty(Type::TaggedEnum(BTreeMap::from([
+                (1, ("A".into(), Fields::None)),
+                (
+                    7,
+                    ("B".into(), Fields::Named(vec![("x".into(), Type::U16)]))
+                ),
+            ]))),
+            "enum { A = 1, B { x: u16 } = 7 }"
+        );
+    }
+}
@@ -1107,28 +1107,7 @@ impl BlockItemSummary {
 
     pub fn affected_contracts(&self) -> Vec<ContractAddress> {
         if let BlockItemSummaryDetails::AccountTransaction(at) = &self.details {
-            match &at.effects {
-                AccountTransactionEffects::ContractInitialized { data } => vec![data.address],
-                AccountTransactionEffects::ContractUpdateIssued { effects } => {
-                    let mut seen = HashSet::new();
-                    let mut addresses = Vec::new();
-                    for effect in effects {
-                        match effect {
-                            ContractTraceElement::Updated { data } => {
-                                if seen.insert(data.address) {
-                                    addresses.push(data.address);
-                                }
-                            }
-                            ContractTraceElement::Transferred { .. } => (),
-                            ContractTraceElement::Interrupted { .. } => (),
-                            ContractTraceElement::Resumed { .. } => (),
-                            ContractTraceElement::Upgraded { .. } => (),
-                        }
-                    }
-                    addresses
-                }
-                _ => Vec::new(),
-            }
+            at.affected_contracts()
         } else {
             Vec::new()
         }
@@ -1198,72 +1177,7 @@ impl BlockItemSummary {
     /// Return the list of addresses affected by the block summary.
     pub fn affected_addresses(&self) -> Vec<AccountAddress> {
         if let BlockItemSummaryDetails::AccountTransaction(at) = &self.details {
-            match &at.effects {
-                AccountTransactionEffects::None { .. } => vec![at.sender],
-                AccountTransactionEffects::ModuleDeployed { .. } => vec![at.sender],
-                AccountTransactionEffects::ContractInitialized { .. } => vec![at.sender],
-                AccountTransactionEffects::ContractUpdateIssued { effects } => {
-                    let mut seen = BTreeSet::new();
-                    seen.insert(at.sender);
-                    let mut addresses = vec![at.sender];
-                    for effect in effects {
-                        match effect {
-                            ContractTraceElement::Updated { .. } => (),
-                            ContractTraceElement::Transferred { to, .. } => {
-                                if seen.insert(*to) {
-                                    addresses.push(*to);
-                                }
-                            }
-                            ContractTraceElement::Interrupted { .. } => (),
-                            ContractTraceElement::Resumed { .. } => (),
-                            ContractTraceElement::Upgraded { .. } => (),
-                        }
-                    }
-                    addresses
-                }
-                AccountTransactionEffects::AccountTransfer { to, .. } => {
-                    if *to == at.sender {
-                        vec![at.sender]
-                    } else {
-                        vec![at.sender, *to]
-                    }
-                }
-                AccountTransactionEffects::AccountTransferWithMemo { to, .. } => {
-                    if *to == at.sender {
-                        vec![at.sender]
-                    } else {
-                        vec![at.sender, *to]
-                    }
-                }
-                AccountTransactionEffects::BakerAdded { .. } => vec![at.sender],
-                AccountTransactionEffects::BakerRemoved { .. } => vec![at.sender],
-                AccountTransactionEffects::BakerStakeUpdated { .. } => vec![at.sender],
-                AccountTransactionEffects::BakerRestakeEarningsUpdated { .. } => vec![at.sender],
-                AccountTransactionEffects::BakerKeysUpdated { .. } => vec![at.sender],
-                AccountTransactionEffects::EncryptedAmountTransferred { removed, added } => {
-                    vec![removed.account, added.receiver]
-                }
-                AccountTransactionEffects::EncryptedAmountTransferredWithMemo {
-                    removed,
-                    added,
-                    ..
-                } => vec![removed.account, added.receiver],
-                AccountTransactionEffects::TransferredToEncrypted { data } => vec![data.account],
-                AccountTransactionEffects::TransferredToPublic { removed, .. } => {
-                    vec![removed.account]
-                }
-                AccountTransactionEffects::TransferredWithSchedule { to, .. } => {
-                    vec![at.sender, *to]
-                }
-                AccountTransactionEffects::TransferredWithScheduleAndMemo { to, .. } => {
-                    vec![at.sender, *to]
-                }
-                AccountTransactionEffects::CredentialKeysUpdated { .. } => vec![at.sender],
-                AccountTransactionEffects::CredentialsUpdated { .. } => vec![at.sender],
-                AccountTransactionEffects::DataRegistered { .. } => vec![at.sender],
-                AccountTransactionEffects::BakerConfigured { .. } => vec![at.sender],
-                AccountTransactionEffects::DelegationConfigured { .. } => vec![at.sender],
-            }
+            at.affected_addresses()
         } else {
             Vec::new()
         }
@@ -1753,6 +1667,103 @@ impl AccountTransactionDetails {
 
     /// Return [`Some`] if the transaction has been rejected.
     pub fn is_rejected(&self) -> Option<&RejectReason> { self.effects.is_rejected() }
+
+    /// Return the list of contract instances affected by the transaction,
+    /// i.e., created or updated by it.
+    pub fn affected_contracts(&self) -> Vec<ContractAddress> {
+        match &self.effects {
+            AccountTransactionEffects::ContractInitialized { data } => vec![data.address],
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                let mut seen = HashSet::new();
+                let mut addresses = Vec::new();
+                for effect in effects {
+                    match effect {
+                        ContractTraceElement::Updated { data } => {
+                            if seen.insert(data.address) {
+                                addresses.push(data.address);
+                            }
+                        }
+                        ContractTraceElement::Transferred { .. } => (),
+                        ContractTraceElement::Interrupted { .. } => (),
+                        ContractTraceElement::Resumed { .. } => (),
+                        ContractTraceElement::Upgraded { .. } => (),
+                    }
+                }
+                addresses
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Return the list of accounts affected by the transaction.
+    pub fn affected_addresses(&self) -> Vec<AccountAddress> {
+        match &self.effects {
+            AccountTransactionEffects::None { .. } => vec![self.sender],
+            AccountTransactionEffects::ModuleDeployed { .. } => vec![self.sender],
+            AccountTransactionEffects::ContractInitialized { .. } => vec![self.sender],
+            AccountTransactionEffects::ContractUpdateIssued { effects } => {
+                let mut seen = BTreeSet::new();
+                seen.insert(self.sender);
+                let mut addresses = vec![self.sender];
+                for effect in effects {
+                    match effect {
+                        ContractTraceElement::Updated { .. } => (),
+                        ContractTraceElement::Transferred { to, .. } => {
+                            if seen.insert(*to) {
+                                addresses.push(*to);
+                            }
+                        }
+                        ContractTraceElement::Interrupted { .. } => (),
+                        ContractTraceElement::Resumed { .. } => (),
+                        ContractTraceElement::Upgraded { .. } => (),
+                    }
+                }
+                addresses
+            }
+            AccountTransactionEffects::AccountTransfer { to, .. } => {
+                if *to == self.sender {
+                    vec![self.sender]
+                } else {
+                    vec![self.sender, *to]
+                }
+            }
+            AccountTransactionEffects::AccountTransferWithMemo { to, .. } => {
+                if *to == self.sender {
+                    vec![self.sender]
+                } else {
+                    vec![self.sender, *to]
+                }
+            }
+            AccountTransactionEffects::BakerAdded { .. } => vec![self.sender],
+            AccountTransactionEffects::BakerRemoved { .. } => vec![self.sender],
+            AccountTransactionEffects::BakerStakeUpdated { .. } => vec![self.sender],
+            AccountTransactionEffects::BakerRestakeEarningsUpdated { .. } => vec![self.sender],
+            AccountTransactionEffects::BakerKeysUpdated { .. } => vec![self.sender],
+            AccountTransactionEffects::EncryptedAmountTransferred { removed, added } => {
+                vec![removed.account, added.receiver]
+            }
+            AccountTransactionEffects::EncryptedAmountTransferredWithMemo {
+                removed,
+                added,
+                ..
+            } => vec![removed.account, added.receiver],
+            AccountTransactionEffects::TransferredToEncrypted { data } => vec![data.account],
+            AccountTransactionEffects::TransferredToPublic { removed, .. } => {
+                vec![removed.account]
+            }
+            AccountTransactionEffects::TransferredWithSchedule { to, .. } => {
+                vec![self.sender, *to]
+            }
+            AccountTransactionEffects::TransferredWithScheduleAndMemo { to, .. } => {
+                vec![self.sender, *to]
+            }
+            AccountTransactionEffects::CredentialKeysUpdated { .. } => vec![self.sender],
+            AccountTransactionEffects::CredentialsUpdated { .. } => vec![self.sender],
+            AccountTransactionEffects::DataRegistered { .. } => vec![self.sender],
+            AccountTransactionEffects::BakerConfigured { .. } => vec![self.sender],
+            AccountTransactionEffects::DelegationConfigured { .. } => vec![self.sender],
+        }
+    }
 }
 
 impl AccountTransactionEffects {
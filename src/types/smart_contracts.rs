@@ -162,7 +162,7 @@ mod instance_parser {
     }
 }
 
-#[derive(SerdeSerialize, SerdeDeserialize, Clone)]
+#[derive(SerdeSerialize, SerdeDeserialize, Debug, Clone)]
 /// Data needed to invoke the contract.
 pub struct ContractContext {
     /// Invoker of the contract. If this is not supplied then the contract will
@@ -0,0 +1,302 @@
+//! Batched invocations of contract instances.
+//!
+//! An [`InvokeBatch`] collects invocations, e.g., of `balanceOf` and
+//! `tokenMetadata` on many contracts, and [`Client::invoke_batch`] runs them
+//! against the same block state. The invocations are pipelined through a
+//! single [`DryRun`](super::dry_run::DryRun) session, which avoids a separate
+//! request for each invocation. If dry-running is not available on the node,
+//! or the session ends before all invocations are done, e.g., because the
+//! energy quota of the session is exhausted, the remaining invocations are
+//! made using [`invoke_instance`](Client::invoke_instance) in parallel.
+use super::{
+    dry_run::{DryRunError, DryRunResult, ErrorResult, InvokeInstanceSuccess, WithRemainingQuota},
+    BlockIdentifier, Client, IntoBlockIdentifier, QueryResult,
+};
+use crate::types::{
+    hashes::BlockHash,
+    smart_contracts::{ContractContext, InvokeContractResult},
+};
+use futures::{StreamExt, TryStreamExt};
+
+/// The default maximum number of parallel invocations made by
+/// [`Client::invoke_batch`] when falling back to
+/// [`invoke_instance`](Client::invoke_instance).
+pub const DEFAULT_MAX_PARALLEL: usize = 8;
+
+/// A batch of invocations to be run with [`Client::invoke_batch`].
+#[derive(Clone)]
+pub struct InvokeBatch {
+    contexts:     Vec<ContractContext>,
+    max_parallel: usize,
+}
+
+impl Default for InvokeBatch {
+    fn default() -> Self { Self::new() }
+}
+
+impl InvokeBatch {
+    /// Construct an empty batch.
+    pub fn new() -> Self {
+        Self {
+            contexts:     Vec::new(),
+            max_parallel: DEFAULT_MAX_PARALLEL,
+        }
+    }
+
+    /// Set the maximum number of parallel invocations made when falling back
+    /// to [`invoke_instance`](Client::invoke_instance). Defaults to
+    /// [`DEFAULT_MAX_PARALLEL`].
+    pub fn set_max_parallel(self, max_parallel: usize) -> Self {
+        Self {
+            max_parallel: max_parallel.max(1),
+            ..self
+        }
+    }
+
+    /// Add an invocation to the batch. The returned index is the index of the
+    /// result in [`InvokeBatchResult::results`].
+    pub fn push(&mut self, context: ContractContext) -> usize {
+        self.contexts.push(context);
+        self.contexts.len() - 1
+    }
+
+    /// The number of invocations in the batch.
+    pub fn len(&self) -> usize { self.contexts.len() }
+
+    /// Whether the batch is empty.
+    pub fn is_empty(&self) -> bool { self.contexts.is_empty() }
+}
+
+impl FromIterator<ContractContext> for InvokeBatch {
+    fn from_iter<T: IntoIterator<Item = ContractContext>>(iter: T) -> Self {
+        Self {
+            contexts: iter.into_iter().collect(),
+            ..Self::new()
+        }
+    }
+}
+
+/// The results of [`Client::invoke_batch`].
+#[derive(Debug, Clone)]
+pub struct InvokeBatchResult {
+    /// The block whose state the invocations were run against.
+    pub block_hash:    BlockHash,
+    /// The results of the invocations, in the order they were added to the
+    /// batch.
+    pub results:       Vec<InvokeContractResult>,
+    /// The number of invocations that were run in the dry-run session. The
+    /// remaining invocations were made using
+    /// [`invoke_instance`](Client::invoke_instance).
+    pub dry_run_count: usize,
+}
+
+impl Client {
+    /// Run the invocations in the batch against the state at the end of the
+    /// given block. See the [module documentation](super::batch) for details.
+    ///
+    /// The results are the same as those of
+    /// [`invoke_instance`](Client::invoke_instance), except that the energy
+    /// available to each invocation in the dry-run session is limited by the
+    /// remaining energy quota of the session if no energy is specified in the
+    /// context. An error is only returned if querying the node fails, in which
+    /// case no results are returned.
+    pub async fn invoke_batch(
+        &mut self,
+        batch: &InvokeBatch,
+        bi: impl IntoBlockIdentifier,
+    ) -> QueryResult<InvokeBatchResult> {
+        let bi: BlockIdentifier = bi.into_block_identifier();
+        let mut results: Vec<Option<InvokeContractResult>> =
+            batch.contexts.iter().map(|_| None).collect();
+        let mut dry_run_count = 0;
+
+        let block_hash = match self.dry_run(bi).await {
+            Ok(WithRemainingQuota {
+                inner: (mut dry_run, loaded),
+                ..
+            }) => {
+                // Send all requests before waiting for any responses.
+                let mut pending = Vec::with_capacity(batch.len());
+                for context in &batch.contexts {
+                    match dry_run.begin_invoke_instance(context).await {
+                        Ok(response) => pending.push(response),
+                        // The session has ended, so no further requests can be
+                        // made.
+                        Err(_) => break,
+                    }
+                }
+                dry_run.close();
+                for (result, response) in results.iter_mut().zip(pending) {
+                    *result = dry_run_result(response.await);
+                    if result.is_some() {
+                        dry_run_count += 1;
+                    }
+                }
+                loaded.block_hash
+            }
+            // Dry-running is not available, so the block is resolved to make
+            // all invocations against the same state.
+            Err(_) => self.get_block_info(bi).await?.block_hash,
+        };
+
+        let fallback: Vec<_> = futures::stream::iter(missing_indices(&results))
+            .map(|i| {
+                let mut client = self.clone();
+                let context = &batch.contexts[i];
+                async move {
+                    let response = client.invoke_instance(block_hash, context).await?;
+                    QueryResult::Ok((i, response.response))
+                }
+            })
+            .buffer_unordered(batch.max_parallel)
+            .try_collect()
+            .await?;
+
+        Ok(InvokeBatchResult {
+            block_hash,
+            results: fill_results(results, fallback),
+            dry_run_count,
+        })
+    }
+}
+
+/// Convert the response to an invocation in the dry-run session to the result
+/// of the invocation, or [`None`] if the invocation must be retried using
+/// [`invoke_instance`](Client::invoke_instance).
+fn dry_run_result(response: DryRunResult<InvokeInstanceSuccess>) -> Option<InvokeContractResult> {
+    match response {
+        Ok(WithRemainingQuota { inner, .. }) => Some(InvokeContractResult::Success {
+            return_value: inner.return_value,
+            events:       inner.events,
+            used_energy:  inner.used_energy,
+        }),
+        Err(DryRunError::OperationFailed {
+            result:
+                ErrorResult::InvokeFailure {
+                    return_value,
+                    used_energy,
+                    reason,
+                },
+            ..
+        }) => Some(InvokeContractResult::Failure {
+            return_value,
+            reason,
+            used_energy,
+        }),
+        Err(_) => None,
+    }
+}
+
+/// The indices of the invocations that have no result yet.
+fn missing_indices(results: &[Option<InvokeContractResult>]) -> Vec<usize> {
+    results
+        .iter()
+        .enumerate()
+        .filter_map(|(i, result)| result.is_none().then_some(i))
+        .collect()
+}
+
+/// Fill in the results of the invocations made using
+/// [`invoke_instance`](Client::invoke_instance), which are given with the
+/// index of the invocation in the batch and in any order.
+fn fill_results(
+    mut results: Vec<Option<InvokeContractResult>>,
+    fallback: Vec<(usize, InvokeContractResult)>,
+) -> Vec<InvokeContractResult> {
+    for (i, result) in fallback {
+        results[i] = Some(result);
+    }
+    results
+        .into_iter()
+        .enumerate()
+        .map(|(i, result)| {
+            result.unwrap_or_else(|| panic!("Invocation {i} of the batch has no result."))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{smart_contracts::ReturnValue, Energy, RejectReason};
+
+    fn success(used_energy: u64) -> InvokeContractResult {
+        InvokeContractResult::Success {
+            return_value: None,
+            events:       Vec::new(),
+            used_energy:  Energy {
+                energy: used_energy,
+            },
+        }
+    }
+
+    fn used_energy(result: &InvokeContractResult) -> u64 {
+        match result {
+            InvokeContractResult::Success { used_energy, .. } => used_energy.energy,
+            InvokeContractResult::Failure { used_energy, .. } => used_energy.energy,
+        }
+    }
+
+    #[test]
+    /// Test which responses of the dry-run session are taken as results, and
+    /// which are retried.
+    fn dry_run_results() {
+        let result = dry_run_result(Ok(WithRemainingQuota {
+            inner:           InvokeInstanceSuccess {
+                return_value: None,
+                events:       Vec::new(),
+                used_energy:  Energy { energy: 10 },
+            },
+            quota_remaining: Energy { energy: 100 },
+        }));
+        assert!(matches!(
+            result,
+            Some(InvokeContractResult::Success { used_energy, .. }) if used_energy.energy == 10
+        ));
+        let result = dry_run_result(Err(DryRunError::OperationFailed {
+            result:          ErrorResult::InvokeFailure {
+                return_value: Some(ReturnValue { value: vec![1] }),
+                used_energy:  Energy { energy: 20 },
+                reason:       RejectReason::OutOfEnergy,
+            },
+            quota_remaining: Energy { energy: 100 },
+        }));
+        assert!(matches!(
+            result,
+            Some(InvokeContractResult::Failure {
+                reason: RejectReason::OutOfEnergy,
+                ..
+            })
+        ));
+        assert!(dry_run_result(Err(DryRunError::OperationFailed {
+            result:          ErrorResult::NoState,
+            quota_remaining: Energy { energy: 0 },
+        }))
+        .is_none());
+        assert!(dry_run_result(Err(DryRunError::CallError(
+            tonic::Status::resource_exhausted("quota exhausted")
+        )))
+        .is_none());
+    }
+
+    #[test]
+    /// Test that results of fallback invocations, returned in any order, are
+    /// placed at the index of their invocation in the batch.
+    fn results_by_index() {
+        let results = vec![Some(success(0)), None, Some(success(2)), None, None];
+        let missing = missing_indices(&results);
+        assert_eq!(missing, [1, 3, 4]);
+        let fallback = vec![(4, success(4)), (1, success(1)), (3, success(3))];
+        let results = fill_results(results, fallback);
+        let energies: Vec<_> = results.iter().map(used_energy).collect();
+        assert_eq!(energies, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invocation 1 of the batch has no result.")]
+    /// Test that a missing result is not silently dropped, which would shift
+    /// the indices of the following results.
+    fn missing_result() {
+        fill_results(vec![Some(success(0)), None, Some(success(2))], Vec::new());
+    }
+}
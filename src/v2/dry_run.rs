@@ -8,19 +8,24 @@ use super::{
 use crate::{
     types::{
         smart_contracts::{ContractContext, InstanceInfo, ReturnValue},
-        AccountInfo, AccountTransactionDetails, RejectReason,
+        AccountInfo, AccountStakingInfo, AccountTransactionDetails, Nonce, RejectReason,
     },
     v2::{generated::DryRunStateQuery, Require},
 };
 use concordium_base::{
     base::{Energy, ProtocolVersion},
     common::types::{CredentialIndex, KeyIndex, Timestamp},
-    contracts_common::{AccountAddress, Amount, ContractAddress},
+    contracts_common::{AccountAddress, Address, Amount, ContractAddress},
     hashes::BlockHash,
-    smart_contracts::ContractTraceElement,
-    transactions::{EncodedPayload, PayloadLike},
+    smart_contracts::{ContractTraceElement, ModuleReference},
+    transactions::{EncodedPayload, Payload, PayloadLike},
 };
 use futures::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub mod fork;
+pub mod scenario;
+pub mod session;
 
 mod shared_receiver {
     use futures::{stream::Stream, StreamExt};
@@ -641,6 +646,98 @@ impl TryFrom<Option<Result<generated::DryRunResponse, tonic::Status>>>
     }
 }
 
+/// The state of an account before or after a transaction, as reported by
+/// [`DryRun::run_transaction_with_diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccountState {
+    /// The total balance of the account.
+    pub balance:           Amount,
+    /// The balance that is not staked or locked.
+    pub available_balance: Amount,
+    /// The next nonce of the account.
+    pub nonce:             Nonce,
+    /// The staking information of the account, if it is a baker or delegator.
+    pub stake:             Option<AccountStakingInfo>,
+}
+
+impl From<&AccountInfo> for AccountState {
+    fn from(info: &AccountInfo) -> Self {
+        Self {
+            balance:           info.account_amount,
+            available_balance: info.available_balance,
+            nonce:             info.account_nonce,
+            stake:             info.account_stake.clone(),
+        }
+    }
+}
+
+/// The state of a contract instance before or after a transaction, as
+/// reported by [`DryRun::run_transaction_with_diff`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContractState {
+    /// The balance of the instance.
+    pub balance: Amount,
+    /// The module of the instance.
+    pub module:  ModuleReference,
+}
+
+impl From<&InstanceInfo> for ContractState {
+    fn from(info: &InstanceInfo) -> Self {
+        Self {
+            balance: info.amount(),
+            module:  info.source_module(),
+        }
+    }
+}
+
+/// The change of an account affected by a transaction.
+#[derive(Debug, Clone)]
+pub struct AccountDiff {
+    /// The address of the account.
+    pub address: AccountAddress,
+    /// The state before the transaction. This is `None` if the account was not
+    /// anticipated to be affected, see [`DryRun::run_transaction_with_diff`].
+    pub before:  Option<AccountState>,
+    /// The state after the transaction.
+    pub after:   Option<AccountState>,
+}
+
+impl AccountDiff {
+    /// Whether the state of the account changed. This is `true` if the state
+    /// before the transaction is unknown.
+    pub fn is_changed(&self) -> bool { self.before != self.after }
+}
+
+/// The change of a contract instance affected by a transaction.
+#[derive(Debug, Clone)]
+pub struct ContractDiff {
+    /// The address of the instance.
+    pub address: ContractAddress,
+    /// The state before the transaction. This is `None` if the instance was
+    /// created by the transaction, or was not anticipated to be affected,
+    /// see [`DryRun::run_transaction_with_diff`].
+    pub before:  Option<ContractState>,
+    /// The state after the transaction.
+    pub after:   Option<ContractState>,
+}
+
+impl ContractDiff {
+    /// Whether the balance or module of the instance changed. This is `true`
+    /// if the state before the transaction is unknown.
+    pub fn is_changed(&self) -> bool { self.before != self.after }
+}
+
+/// The successful result of [`DryRun::run_transaction_with_diff`].
+#[derive(Debug, Clone)]
+pub struct TransactionExecutedWithDiff {
+    /// The result of executing the transaction.
+    pub executed:  TransactionExecuted,
+    /// The changes to the accounts affected by the transaction.
+    pub accounts:  Vec<AccountDiff>,
+    /// The changes to the contract instances affected by the transaction.
+    pub contracts: Vec<ContractDiff>,
+}
+
 pub type DryRunResult<T> = Result<WithRemainingQuota<T>, DryRunError>;
 
 /// A dry-run session.
@@ -1199,6 +1296,168 @@ impl DryRun {
         self.begin_run_transaction(transaction).await?.await
     }
 
+    /// Dry-run a transaction as [`DryRun::run_transaction`], and report how it
+    /// changes the accounts and contract instances it affects.
+    ///
+    /// The affected accounts and instances are determined from the result of
+    /// the transaction, see [`AccountTransactionDetails::affected_addresses`]
+    /// and [`AccountTransactionDetails::affected_contracts`]. Their state
+    /// before the transaction is queried before it is executed, so they are
+    /// anticipated from the payload: the sender, the recipient of a transfer,
+    /// and for contract updates the accounts and instances that occur in the
+    /// trace of [invoking](DryRun::invoke_instance) the contract with the same
+    /// parameters. The state before the transaction is `None` for affected
+    /// accounts and instances that were not anticipated.
+    ///
+    /// In addition to the cost of running the transaction, this performs an
+    /// invocation for contract updates, and queries the state of each
+    /// anticipated account and instance before, and each affected account and
+    /// instance after the transaction, at a cost of 200 each.
+    pub async fn run_transaction_with_diff(
+        &mut self,
+        transaction: DryRunTransaction,
+    ) -> DryRunResult<TransactionExecutedWithDiff> {
+        let (anticipated_accounts, anticipated_contracts) =
+            self.anticipate_affected(&transaction).await?;
+        let mut accounts_before = BTreeMap::new();
+        for address in anticipated_accounts {
+            accounts_before.insert(address, self.account_state(address).await?.inner);
+        }
+        let mut contracts_before = BTreeMap::new();
+        for address in anticipated_contracts {
+            contracts_before.insert(address, self.contract_state(address).await?.inner);
+        }
+
+        let executed = self.run_transaction(transaction).await?;
+        let mut quota_remaining = executed.quota_remaining;
+        let executed = executed.inner;
+
+        let mut accounts = Vec::new();
+        for address in executed.details.affected_addresses() {
+            let after = self.account_state(address).await?;
+            quota_remaining = after.quota_remaining;
+            accounts.push(AccountDiff {
+                address,
+                before: accounts_before.remove(&address).flatten(),
+                after: after.inner,
+            });
+        }
+        let mut contracts = Vec::new();
+        for address in executed.details.affected_contracts() {
+            let after = self.contract_state(address).await?;
+            quota_remaining = after.quota_remaining;
+            contracts.push(ContractDiff {
+                address,
+                before: contracts_before.remove(&address).flatten(),
+                after: after.inner,
+            });
+        }
+        Ok(WithRemainingQuota {
+            inner: TransactionExecutedWithDiff {
+                executed,
+                accounts,
+                contracts,
+            },
+            quota_remaining,
+        })
+    }
+
+    /// Determine the accounts and instances that are expected to be affected
+    /// by the transaction. See [`DryRun::run_transaction_with_diff`].
+    async fn anticipate_affected(
+        &mut self,
+        transaction: &DryRunTransaction,
+    ) -> Result<(BTreeSet<AccountAddress>, BTreeSet<ContractAddress>), DryRunError> {
+        let mut accounts = BTreeSet::from([transaction.sender]);
+        let mut contracts = BTreeSet::new();
+        match transaction.payload.decode() {
+            Ok(Payload::Transfer { to_address, .. })
+            | Ok(Payload::TransferWithMemo { to_address, .. }) => {
+                accounts.insert(to_address);
+            }
+            Ok(Payload::TransferWithSchedule { to, .. })
+            | Ok(Payload::TransferWithScheduleAndMemo { to, .. }) => {
+                accounts.insert(to);
+            }
+            Ok(Payload::Update { payload }) => {
+                contracts.insert(payload.address);
+                let context = ContractContext {
+                    invoker:   Some(Address::Account(transaction.sender)),
+                    contract:  payload.address,
+                    amount:    payload.amount,
+                    method:    payload.receive_name,
+                    parameter: payload.message,
+                    energy:    Some(transaction.energy_amount),
+                };
+                match self.invoke_instance(&context).await {
+                    Ok(success) => {
+                        for event in success.inner.events {
+                            match event {
+                                ContractTraceElement::Updated { data } => {
+                                    contracts.insert(data.address);
+                                }
+                                ContractTraceElement::Transferred { to, .. } => {
+                                    accounts.insert(to);
+                                }
+                                ContractTraceElement::Interrupted { .. }
+                                | ContractTraceElement::Resumed { .. }
+                                | ContractTraceElement::Upgraded { .. } => {}
+                            }
+                        }
+                    }
+                    // The transaction will most likely be rejected, but the
+                    // instance itself is still queried.
+                    Err(DryRunError::OperationFailed { .. }) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            _ => {}
+        }
+        Ok((accounts, contracts))
+    }
+
+    /// Get the state of an account, or `None` if it does not exist.
+    async fn account_state(
+        &mut self,
+        address: AccountAddress,
+    ) -> DryRunResult<Option<AccountState>> {
+        match self.get_account_info(&address.into()).await {
+            Ok(info) => Ok(WithRemainingQuota {
+                inner:           Some(AccountState::from(&info.inner)),
+                quota_remaining: info.quota_remaining,
+            }),
+            Err(DryRunError::OperationFailed {
+                result: ErrorResult::AccountNotFound,
+                quota_remaining,
+            }) => Ok(WithRemainingQuota {
+                inner: None,
+                quota_remaining,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the state of an instance, or `None` if it does not exist.
+    async fn contract_state(
+        &mut self,
+        address: ContractAddress,
+    ) -> DryRunResult<Option<ContractState>> {
+        match self.get_instance_info(&address).await {
+            Ok(info) => Ok(WithRemainingQuota {
+                inner:           Some(ContractState::from(&info.inner)),
+                quota_remaining: info.quota_remaining,
+            }),
+            Err(DryRunError::OperationFailed {
+                result: ErrorResult::InstanceNotFound,
+                quota_remaining,
+            }) => Ok(WithRemainingQuota {
+                inner: None,
+                quota_remaining,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Close the request stream. Any subsequent dry-run requests will result in
     /// a `CANCELLED` status code. Closing the request stream allows the
     /// server to free resources associated with the dry-run session. It is
@@ -0,0 +1,324 @@
+//! A harness for testing against the state of a live chain.
+//!
+//! A [`ForkTest`] wraps a [`DryRun`] session started from a block of a live
+//! chain, typically mainnet or testnet. Since dry-run transactions are not
+//! signed, any account can be impersonated, and it can be funded by minting
+//! CCD to it. Local modules can be deployed and initialized, and interact
+//! with the contracts that exist on chain. Nothing is ever sent to the chain.
+//!
+//! Tests are intended to be run with `#[tokio::test]`. The node to use is
+//! read from the [`NODE_ENV_VAR`] environment variable by
+//! [`ForkTest::from_env`], which returns `None` if the variable is not set,
+//! so tests can be skipped in environments without access to a node.
+//!
+//! ```no_run
+//! # use concordium_rust_sdk::{
+//! #     base::contracts_common::{AccountAddress, Amount, OwnedContractName, OwnedParameter},
+//! #     types::smart_contracts::WasmModule,
+//! #     v2::{self, dry_run::fork::*},
+//! # };
+//! #[tokio::test]
+//! async fn swap_against_live_dex() -> Result<(), ForkError> {
+//!     let Some(mut fork) = ForkTest::from_env(v2::BlockIdentifier::LastFinal).await? else {
+//!         // No node is configured.
+//!         return Ok(());
+//!     };
+//!     let alice = AccountAddress([1; 32]);
+//!     fork.fund(&alice, Amount::from_ccd(1000)).await?;
+//!     let module = WasmModule::from_file("my_contract.wasm.v1".as_ref()).unwrap();
+//!     let module_ref = fork.deploy_module(alice, module).await?;
+//!     let address = fork
+//!         .init_contract(
+//!             alice,
+//!             module_ref,
+//!             OwnedContractName::new_unchecked("init_my_contract".into()),
+//!             OwnedParameter::empty(),
+//!             Amount::zero(),
+//!         )
+//!         .await?;
+//!     // ... update the contract, and assert on the trace.
+//!     Ok(())
+//! }
+//! ```
+use super::{BlockStateLoaded, DryRun, DryRunError, DryRunTransaction, TransactionExecuted};
+use crate::{
+    types::{
+        smart_contracts::{InstanceUpdatedEvent, WasmModule},
+        AccountTransactionEffects, RejectReason,
+    },
+    v2::{self, IntoBlockIdentifier},
+};
+use concordium_base::{
+    base::Energy,
+    common::types::Timestamp,
+    contracts_common::{
+        AccountAddress, Address, Amount, ContractAddress, EntrypointName, OwnedContractName,
+        OwnedParameter, OwnedReceiveName,
+    },
+    smart_contracts::{ContractTraceElement, ModuleReference},
+    transactions::{InitContractPayload, Payload, PayloadLike, UpdateContractPayload},
+};
+
+/// The environment variable that [`ForkTest::from_env`] reads the URL of the
+/// node from, e.g., `http://localhost:20000`.
+pub const NODE_ENV_VAR: &str = "CONCORDIUM_NODE";
+
+/// The default energy limit of transactions sent by a [`ForkTest`].
+pub const DEFAULT_ENERGY: Energy = Energy { energy: 3_000_000 };
+
+/// An error in a [`ForkTest`].
+#[derive(thiserror::Error, Debug)]
+pub enum ForkError {
+    #[error("Could not connect to the node: {0}")]
+    Connection(#[from] tonic::transport::Error),
+    #[error("Query failed: {0}")]
+    Query(#[from] v2::QueryError),
+    #[error("Dry-run failed: {0}")]
+    DryRun(#[from] DryRunError),
+    #[error("The transaction was rejected: {reason:?}")]
+    Rejected { reason: RejectReason },
+    #[error("The transaction had unexpected effects: {effects:?}")]
+    UnexpectedEffects { effects: AccountTransactionEffects },
+}
+
+/// A dry-run session on the state of a live chain, with helpers for testing.
+/// See the [module documentation](self) for an overview.
+pub struct ForkTest {
+    dry_run:   DryRun,
+    /// The block whose state the test started from.
+    pub block: BlockStateLoaded,
+    energy:    Energy,
+}
+
+impl ForkTest {
+    /// Start a test from the state of the given block using the node whose
+    /// URL is in the [`NODE_ENV_VAR`] environment variable. Returns `None` if
+    /// the variable is not set, in which case the test should be skipped.
+    pub async fn from_env(bi: impl IntoBlockIdentifier) -> Result<Option<Self>, ForkError> {
+        let Ok(endpoint) = std::env::var(NODE_ENV_VAR) else {
+            return Ok(None);
+        };
+        let mut client = v2::Client::new(endpoint).await?;
+        Ok(Some(Self::new(&mut client, bi).await?))
+    }
+
+    /// Start a test from the state of the given block.
+    pub async fn new(
+        client: &mut v2::Client,
+        bi: impl IntoBlockIdentifier,
+    ) -> Result<Self, ForkError> {
+        let (dry_run, block) = client.dry_run(bi).await?.inner;
+        Ok(Self {
+            dry_run,
+            block,
+            energy: DEFAULT_ENERGY,
+        })
+    }
+
+    /// Set the energy limit used for transactions. Defaults to
+    /// [`DEFAULT_ENERGY`].
+    pub fn set_energy(self, energy: Energy) -> Self { Self { energy, ..self } }
+
+    /// Access the underlying dry-run session, e.g., to query accounts and
+    /// instances.
+    pub fn dry_run(&mut self) -> &mut DryRun { &mut self.dry_run }
+
+    /// Mint the amount to the account.
+    pub async fn fund(
+        &mut self,
+        account: &AccountAddress,
+        amount: Amount,
+    ) -> Result<(), ForkError> {
+        self.dry_run.mint_to_account(account, amount).await?;
+        Ok(())
+    }
+
+    /// Set the current time used for subsequent transactions.
+    pub async fn set_time(&mut self, timestamp: Timestamp) -> Result<(), ForkError> {
+        self.dry_run.set_timestamp(timestamp).await?;
+        Ok(())
+    }
+
+    /// Execute a transaction with the given payload as the sender. The
+    /// transaction is not signed, so the sender can be any existing account.
+    /// The result is returned also if the transaction is rejected.
+    pub async fn send(
+        &mut self,
+        sender: AccountAddress,
+        payload: &impl PayloadLike,
+    ) -> Result<TransactionExecuted, ForkError> {
+        let transaction = DryRunTransaction::new(sender, self.energy, payload);
+        Ok(self.dry_run.run_transaction(transaction).await?.inner)
+    }
+
+    /// Execute a transaction, returning the effects if it was not rejected.
+    async fn send_expecting_success(
+        &mut self,
+        sender: AccountAddress,
+        payload: &Payload,
+    ) -> Result<AccountTransactionEffects, ForkError> {
+        let executed = self.send(sender, payload).await?;
+        if let Some(reason) = executed.details.is_rejected() {
+            return Err(ForkError::Rejected {
+                reason: reason.clone(),
+            });
+        }
+        Ok(executed.details.effects)
+    }
+
+    /// Deploy a module as the sender, returning its reference. If the module
+    /// already exists on chain, deploying it is rejected, and the reference
+    /// of the existing module is returned.
+    pub async fn deploy_module(
+        &mut self,
+        sender: AccountAddress,
+        module: WasmModule,
+    ) -> Result<ModuleReference, ForkError> {
+        match self
+            .send_expecting_success(sender, &Payload::DeployModule { module })
+            .await
+        {
+            Ok(AccountTransactionEffects::ModuleDeployed { module_ref }) => Ok(module_ref),
+            Ok(effects) => Err(ForkError::UnexpectedEffects { effects }),
+            Err(ForkError::Rejected {
+                reason: RejectReason::ModuleHashAlreadyExists { contents },
+            }) => Ok(contents),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Initialize an instance of a contract as the sender, returning its
+    /// address.
+    pub async fn init_contract(
+        &mut self,
+        sender: AccountAddress,
+        mod_ref: ModuleReference,
+        init_name: OwnedContractName,
+        param: OwnedParameter,
+        amount: Amount,
+    ) -> Result<ContractAddress, ForkError> {
+        let payload = Payload::InitContract {
+            payload: InitContractPayload {
+                amount,
+                mod_ref,
+                init_name,
+                param,
+            },
+        };
+        match self.send_expecting_success(sender, &payload).await? {
+            AccountTransactionEffects::ContractInitialized { data } => Ok(data.address),
+            effects => Err(ForkError::UnexpectedEffects { effects }),
+        }
+    }
+
+    /// Update a contract instance as the sender, returning the trace of the
+    /// update.
+    pub async fn update_contract(
+        &mut self,
+        sender: AccountAddress,
+        address: ContractAddress,
+        receive_name: OwnedReceiveName,
+        message: OwnedParameter,
+        amount: Amount,
+    ) -> Result<Trace, ForkError> {
+        let payload = Payload::Update {
+            payload: UpdateContractPayload {
+                amount,
+                address,
+                receive_name,
+                message,
+            },
+        };
+        match self.send_expecting_success(sender, &payload).await? {
+            AccountTransactionEffects::ContractUpdateIssued { effects } => Ok(Trace(effects)),
+            effects => Err(ForkError::UnexpectedEffects { effects }),
+        }
+    }
+}
+
+/// The trace of a contract update, with assertions for use in tests. The
+/// `assert_*` methods panic if the assertion does not hold.
+#[derive(Debug, Clone)]
+pub struct Trace(pub Vec<ContractTraceElement>);
+
+impl Trace {
+    /// Get the updates of the instance at the given entrypoint, in the order
+    /// they completed.
+    pub fn updates(
+        &self,
+        address: ContractAddress,
+        entrypoint: EntrypointName,
+    ) -> impl Iterator<Item = &InstanceUpdatedEvent> + '_ {
+        let entrypoint = entrypoint.to_owned();
+        self.0.iter().filter_map(move |element| match element {
+            ContractTraceElement::Updated { data }
+                if data.address == address
+                    && data.receive_name.as_receive_name().entrypoint_name()
+                        == entrypoint.as_entrypoint_name() =>
+            {
+                Some(data)
+            }
+            _ => None,
+        })
+    }
+
+    /// Get the transfers from instances to accounts in the trace.
+    pub fn transfers(
+        &self,
+    ) -> impl Iterator<Item = (ContractAddress, Amount, AccountAddress)> + '_ {
+        self.0.iter().filter_map(|element| match element {
+            ContractTraceElement::Transferred { from, amount, to } => Some((*from, *amount, *to)),
+            _ => None,
+        })
+    }
+
+    /// Assert that the entrypoint of the instance was updated, returning the
+    /// first such update.
+    pub fn assert_updated(
+        &self,
+        address: ContractAddress,
+        entrypoint: EntrypointName,
+    ) -> &InstanceUpdatedEvent {
+        self.updates(address, entrypoint).next().unwrap_or_else(|| {
+            panic!("Expected {address} to be updated at entrypoint {entrypoint}, trace: {self:?}")
+        })
+    }
+
+    /// Assert that the entrypoint of the instance was updated by the given
+    /// sender with the given amount.
+    pub fn assert_called_by(
+        &self,
+        address: ContractAddress,
+        entrypoint: EntrypointName,
+        sender: Address,
+        amount: Amount,
+    ) {
+        assert!(
+            self.updates(address, entrypoint)
+                .any(|update| update.instigator == sender && update.amount == amount),
+            "Expected {address} to be called at entrypoint {entrypoint} by {sender} with \
+             {amount}, trace: {self:?}"
+        );
+    }
+
+    /// Assert that the amount was transferred from the instance to the
+    /// account.
+    pub fn assert_transferred(&self, from: ContractAddress, amount: Amount, to: AccountAddress) {
+        assert!(
+            self.transfers()
+                .any(|transfer| transfer == (from, amount, to)),
+            "Expected a transfer of {amount} from {from} to {to}, trace: {self:?}"
+        );
+    }
+
+    /// Assert that the instance was upgraded to the module.
+    pub fn assert_upgraded(&self, address: ContractAddress, module: ModuleReference) {
+        assert!(
+            self.0.iter().any(|element| matches!(
+                element,
+                ContractTraceElement::Upgraded { address: a, to, .. } if *a == address && *to == module
+            )),
+            "Expected {address} to be upgraded to {module}, trace: {self:?}"
+        );
+    }
+}
@@ -0,0 +1,555 @@
+//! Declarative dry-run scenarios.
+//!
+//! A [`DryRunScenario`] is a list of [steps](ScenarioStep) that are executed
+//! in order in a single [`DryRun`] session, such as loading the state of a
+//! block, minting CCD to an account, advancing the time, running transactions
+//! and asserting their outcomes. Scenarios can be (de)serialized using `serde`,
+//! so that they can be stored as, e.g., JSON or YAML files.
+//!
+//! Running a scenario produces a [`ScenarioReport`] describing the outcome of
+//! each step.
+use super::{DryRun, DryRunError, DryRunTransaction, ErrorResult};
+use crate::{
+    types::{smart_contracts::ContractContext, RejectReason},
+    v2::{self, AccountIdentifier, BlockIdentifier},
+};
+use concordium_base::{
+    base::Energy,
+    common::{types::Timestamp, SerdeDeserialize, SerdeSerialize},
+    contracts_common::{AccountAddress, Amount},
+    transactions::Payload,
+};
+
+/// A scenario to execute in a dry-run session.
+#[derive(SerdeSerialize, SerdeDeserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunScenario {
+    /// A human-readable name of the scenario.
+    #[serde(default)]
+    pub name:                Option<String>,
+    /// The steps to execute, in order.
+    pub steps:               Vec<ScenarioStep>,
+    /// Whether to continue executing the remaining steps after a step fails.
+    /// Steps are never executed after the dry-run session has been terminated
+    /// by the server. Defaults to `false`.
+    #[serde(default)]
+    pub continue_on_failure: bool,
+}
+
+/// A single step of a [`DryRunScenario`].
+#[derive(SerdeSerialize, SerdeDeserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ScenarioStep {
+    /// Load the state of the given block. This must be the first step of a
+    /// scenario.
+    #[serde(rename_all = "camelCase")]
+    LoadBlock {
+        /// The block, in the format supported by the
+        /// [`FromStr`](std::str::FromStr) implementation of
+        /// [`BlockIdentifier`].
+        #[serde(with = "crate::internal::block_identifier_string")]
+        block: BlockIdentifier,
+    },
+    /// Mint the amount to the account.
+    #[serde(rename_all = "camelCase")]
+    Mint {
+        account: AccountAddress,
+        amount:  Amount,
+    },
+    /// Set the current time used for executing transactions.
+    #[serde(rename_all = "camelCase")]
+    SetTime { time: chrono::DateTime<chrono::Utc> },
+    /// Advance the current time used for executing transactions by the given
+    /// duration, in milliseconds.
+    #[serde(rename_all = "camelCase")]
+    AdvanceTime {
+        #[serde(with = "crate::internal::duration_millis")]
+        duration: chrono::Duration,
+    },
+    /// Run a transaction with the payload from the sender. No signatures are
+    /// needed.
+    #[serde(rename_all = "camelCase")]
+    RunTransaction {
+        sender:  AccountAddress,
+        energy:  Energy,
+        payload: Payload,
+        /// The expected outcome of the transaction. If not present the step
+        /// only fails if the transaction could not be executed at all.
+        #[serde(default)]
+        expect:  Option<Expectation>,
+    },
+    /// Invoke a contract entrypoint, without affecting the state.
+    #[serde(rename_all = "camelCase")]
+    InvokeInstance {
+        context: ContractContext,
+        /// The expected outcome of the invocation. If not present the step
+        /// only fails if the invocation could not be performed at all.
+        #[serde(default)]
+        expect:  Option<Expectation>,
+    },
+    /// Assert the public balance of the account.
+    #[serde(rename_all = "camelCase")]
+    AssertBalance {
+        account: AccountAddress,
+        amount:  Amount,
+    },
+}
+
+/// The expected outcome of a transaction or contract invocation.
+#[derive(SerdeSerialize, SerdeDeserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Expectation {
+    /// Whether the transaction or invocation is expected to succeed.
+    pub success:       bool,
+    /// If present, the reason the transaction or invocation is expected to be
+    /// rejected with.
+    #[serde(default)]
+    pub reject_reason: Option<RejectReason>,
+    /// If present, the maximum energy the transaction or invocation is
+    /// expected to use.
+    #[serde(default)]
+    pub max_energy:    Option<Energy>,
+}
+
+impl Expectation {
+    /// Check the actual outcome against the expectation, and return a
+    /// description of the mismatch if any.
+    fn check(&self, reject_reason: Option<&RejectReason>, energy: Energy) -> Result<(), String> {
+        match (self.success, reject_reason) {
+            (true, Some(reason)) => {
+                return Err(format!(
+                    "Expected success, but was rejected with {reason:?}."
+                ))
+            }
+            (false, None) => return Err("Expected rejection, but succeeded.".into()),
+            (false, Some(actual)) => {
+                if let Some(expected) = &self.reject_reason {
+                    // Reject reasons are compared via their JSON representation.
+                    if serde_json::to_value(expected).ok() != serde_json::to_value(actual).ok() {
+                        return Err(format!(
+                            "Expected rejection with {expected:?}, but was rejected with \
+                             {actual:?}."
+                        ));
+                    }
+                }
+            }
+            (true, None) => (),
+        }
+        if let Some(max_energy) = self.max_energy {
+            if energy > max_energy {
+                return Err(format!(
+                    "Expected to use at most {max_energy} energy, but used {energy}."
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The report produced by running a [`DryRunScenario`].
+#[derive(SerdeSerialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScenarioReport {
+    /// The name of the scenario.
+    pub name:  Option<String>,
+    /// The reports of the individual steps, in the order of the steps of the
+    /// scenario.
+    pub steps: Vec<StepReport>,
+}
+
+impl ScenarioReport {
+    /// Whether all steps of the scenario passed.
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| matches!(step.outcome, StepOutcome::Passed { .. }))
+    }
+}
+
+/// The report of a single [`ScenarioStep`].
+#[derive(SerdeSerialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StepReport {
+    /// The index of the step in the scenario.
+    pub index:           usize,
+    /// The outcome of the step.
+    pub outcome:         StepOutcome,
+    /// The energy quota remaining in the dry-run session after the step, if
+    /// the step was executed.
+    pub quota_remaining: Option<Energy>,
+}
+
+/// The outcome of a single [`ScenarioStep`].
+#[derive(SerdeSerialize, Debug, Clone)]
+#[serde(tag = "result", rename_all = "camelCase")]
+pub enum StepOutcome {
+    /// The step was executed, and all expectations were met.
+    #[serde(rename_all = "camelCase")]
+    Passed {
+        /// The energy used by the transaction or invocation, if the step
+        /// executed one.
+        energy_used:  Option<Energy>,
+        /// The hex-encoded return value of the transaction or invocation, if
+        /// any.
+        return_value: Option<String>,
+    },
+    /// The step failed.
+    #[serde(rename_all = "camelCase")]
+    Failed { reason: String },
+    /// The step was not executed because a previous step failed.
+    Skipped,
+}
+
+/// The result of executing a single step.
+struct StepResult {
+    outcome:         StepOutcome,
+    quota_remaining: Option<Energy>,
+    /// Whether the session was terminated by the server.
+    terminated:      bool,
+}
+
+impl StepResult {
+    fn passed(quota_remaining: Energy) -> Self { Self::passed_with(quota_remaining, None, None) }
+
+    fn passed_with(
+        quota_remaining: Energy,
+        energy_used: Option<Energy>,
+        return_value: Option<&[u8]>,
+    ) -> Self {
+        Self {
+            outcome:         StepOutcome::Passed {
+                energy_used,
+                return_value: return_value.map(hex::encode),
+            },
+            quota_remaining: Some(quota_remaining),
+            terminated:      false,
+        }
+    }
+
+    fn failed(reason: String, quota_remaining: Option<Energy>) -> Self {
+        Self {
+            outcome: StepOutcome::Failed { reason },
+            quota_remaining,
+            terminated: false,
+        }
+    }
+
+    fn error(err: DryRunError) -> Self {
+        match err {
+            DryRunError::CallError(status) => Self {
+                outcome:         StepOutcome::Failed {
+                    reason: format!("The dry-run session was terminated: {status}"),
+                },
+                quota_remaining: None,
+                terminated:      true,
+            },
+            DryRunError::OperationFailed {
+                result,
+                quota_remaining,
+            } => Self::failed(result.to_string(), Some(quota_remaining)),
+        }
+    }
+}
+
+impl DryRunScenario {
+    /// Run the scenario in a new dry-run session. This only fails if the
+    /// dry-run session cannot be started. Failures of individual steps are
+    /// reported in the [`ScenarioReport`].
+    pub async fn run(&self, client: &mut v2::Client) -> v2::QueryResult<ScenarioReport> {
+        let mut dry_run = client.begin_dry_run().await?;
+        let report = self.run_in(&mut dry_run).await;
+        dry_run.close();
+        Ok(report)
+    }
+
+    /// Run the scenario in an existing dry-run session. Note that the outcome
+    /// of the scenario depends on the state of the session if the scenario
+    /// does not start by loading a block.
+    pub async fn run_in(&self, dry_run: &mut DryRun) -> ScenarioReport {
+        let mut steps = Vec::with_capacity(self.steps.len());
+        // The current time in the session, if known.
+        let mut current_time: Option<Timestamp> = None;
+        let mut stop = false;
+        for (index, step) in self.steps.iter().enumerate() {
+            if stop {
+                steps.push(StepReport {
+                    index,
+                    outcome: StepOutcome::Skipped,
+                    quota_remaining: None,
+                });
+                continue;
+            }
+            let result = run_step(dry_run, step, &mut current_time).await;
+            let failed = matches!(result.outcome, StepOutcome::Failed { .. });
+            stop = result.terminated || (failed && !self.continue_on_failure);
+            steps.push(StepReport {
+                index,
+                outcome: result.outcome,
+                quota_remaining: result.quota_remaining,
+            });
+        }
+        ScenarioReport {
+            name: self.name.clone(),
+            steps,
+        }
+    }
+}
+
+/// Execute a single step of a scenario.
+async fn run_step(
+    dry_run: &mut DryRun,
+    step: &ScenarioStep,
+    current_time: &mut Option<Timestamp>,
+) -> StepResult {
+    match step {
+        ScenarioStep::LoadBlock { block } => match dry_run.load_block_state(*block).await {
+            Ok(loaded) => {
+                *current_time = Some(loaded.inner.current_timestamp);
+                StepResult::passed(loaded.quota_remaining)
+            }
+            Err(e) => StepResult::error(e),
+        },
+        ScenarioStep::Mint { account, amount } => {
+            match dry_run.mint_to_account(account, *amount).await {
+                Ok(minted) => StepResult::passed(minted.quota_remaining),
+                Err(e) => StepResult::error(e),
+            }
+        }
+        ScenarioStep::SetTime { time } => {
+            let Ok(millis) = u64::try_from(time.timestamp_millis()) else {
+                return StepResult::failed(format!("Time {time} is before the epoch."), None);
+            };
+            set_time(dry_run, Timestamp { millis }, current_time).await
+        }
+        ScenarioStep::AdvanceTime { duration } => {
+            let Some(current) = *current_time else {
+                return StepResult::failed("The current time is not known.".into(), None);
+            };
+            let Some(millis) = u64::try_from(duration.num_milliseconds())
+                .ok()
+                .and_then(|d| current.millis.checked_add(d))
+            else {
+                return StepResult::failed(format!("Invalid duration {duration}."), None);
+            };
+            set_time(dry_run, Timestamp { millis }, current_time).await
+        }
+        ScenarioStep::RunTransaction {
+            sender,
+            energy,
+            payload,
+            expect,
+        } => {
+            let transaction = DryRunTransaction::new(*sender, *energy, payload);
+            match dry_run.run_transaction(transaction).await {
+                Ok(executed) => {
+                    let result = executed.inner;
+                    if let Some(expect) = expect {
+                        if let Err(reason) =
+                            expect.check(result.details.is_rejected(), result.energy_cost)
+                        {
+                            return StepResult::failed(reason, Some(executed.quota_remaining));
+                        }
+                    }
+                    StepResult::passed_with(
+                        executed.quota_remaining,
+                        Some(result.energy_cost),
+                        result.return_value.as_deref(),
+                    )
+                }
+                Err(e) => StepResult::error(e),
+            }
+        }
+        ScenarioStep::InvokeInstance { context, expect } => {
+            match dry_run.invoke_instance(context).await {
+                Ok(success) => {
+                    if let Some(expect) = expect {
+                        if let Err(reason) = expect.check(None, success.inner.used_energy) {
+                            return StepResult::failed(reason, Some(success.quota_remaining));
+                        }
+                    }
+                    StepResult::passed_with(
+                        success.quota_remaining,
+                        Some(success.inner.used_energy),
+                        success.inner.return_value.as_ref().map(|rv| &rv.value[..]),
+                    )
+                }
+                Err(DryRunError::OperationFailed {
+                    result:
+                        ErrorResult::InvokeFailure {
+                            return_value,
+                            used_energy,
+                            reason,
+                        },
+                    quota_remaining,
+                }) => match expect {
+                    Some(expect) => match expect.check(Some(&reason), used_energy) {
+                        Ok(()) => StepResult::passed_with(
+                            quota_remaining,
+                            Some(used_energy),
+                            return_value.as_ref().map(|rv| &rv.value[..]),
+                        ),
+                        Err(reason) => StepResult::failed(reason, Some(quota_remaining)),
+                    },
+                    None => StepResult::failed(
+                        format!("Invocation failed: {reason:?}"),
+                        Some(quota_remaining),
+                    ),
+                },
+                Err(e) => StepResult::error(e),
+            }
+        }
+        ScenarioStep::AssertBalance { account, amount } => {
+            match dry_run
+                .get_account_info(&AccountIdentifier::Address(*account))
+                .await
+            {
+                Ok(info) if info.inner.account_amount == *amount => {
+                    StepResult::passed(info.quota_remaining)
+                }
+                Ok(info) => StepResult::failed(
+                    format!(
+                        "Expected balance {amount}, but the balance is {}.",
+                        info.inner.account_amount
+                    ),
+                    Some(info.quota_remaining),
+                ),
+                Err(e) => StepResult::error(e),
+            }
+        }
+    }
+}
+
+/// Set the current time of the session, and record it if successful.
+async fn set_time(
+    dry_run: &mut DryRun,
+    time: Timestamp,
+    current_time: &mut Option<Timestamp>,
+) -> StepResult {
+    match dry_run.set_timestamp(time).await {
+        Ok(set) => {
+            *current_time = Some(time);
+            StepResult::passed(set.quota_remaining)
+        }
+        Err(e) => StepResult::error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::{
+        base::AbsoluteBlockHeight,
+        contracts_common::{ContractAddress, OwnedReceiveName},
+    };
+
+    fn energy(energy: u64) -> Energy { Energy { energy } }
+
+    fn expectation(success: bool, reject_reason: Option<RejectReason>) -> Expectation {
+        Expectation {
+            success,
+            reject_reason,
+            max_energy: None,
+        }
+    }
+
+    #[test]
+    /// Test checking whether a transaction succeeded or was rejected as
+    /// expected.
+    fn check_outcome() {
+        let reason = RejectReason::OutOfEnergy;
+        assert!(expectation(true, None).check(None, energy(10)).is_ok());
+        assert!(expectation(true, None)
+            .check(Some(&reason), energy(10))
+            .is_err());
+        assert!(expectation(false, None).check(None, energy(10)).is_err());
+        assert!(expectation(false, None)
+            .check(Some(&reason), energy(10))
+            .is_ok());
+        assert!(expectation(false, Some(RejectReason::OutOfEnergy))
+            .check(Some(&reason), energy(10))
+            .is_ok());
+        assert!(expectation(false, Some(RejectReason::RuntimeFailure))
+            .check(Some(&reason), energy(10))
+            .is_err());
+    }
+
+    #[test]
+    /// Test checking the bound on the energy used.
+    fn check_energy() {
+        let expect = Expectation {
+            max_energy: Some(energy(100)),
+            ..expectation(true, None)
+        };
+        assert!(expect.check(None, energy(100)).is_ok());
+        assert!(expect.check(None, energy(101)).is_err());
+        // The energy is also checked for expected rejections.
+        let expect = Expectation {
+            max_energy: Some(energy(100)),
+            ..expectation(false, None)
+        };
+        assert!(expect
+            .check(Some(&RejectReason::OutOfEnergy), energy(101))
+            .is_err());
+    }
+
+    #[test]
+    /// Test that scenarios can be stored as JSON and read back.
+    fn serde_roundtrip() {
+        let account = AccountAddress([1u8; 32]);
+        let scenario = DryRunScenario {
+            name:                Some("Transfer".into()),
+            steps:               vec![
+                ScenarioStep::LoadBlock {
+                    block: BlockIdentifier::AbsoluteHeight(AbsoluteBlockHeight { height: 10 }),
+                },
+                ScenarioStep::Mint {
+                    account,
+                    amount: Amount::from_micro_ccd(1000),
+                },
+                ScenarioStep::AdvanceTime {
+                    duration: chrono::Duration::try_milliseconds(1500).unwrap(),
+                },
+                ScenarioStep::RunTransaction {
+                    sender:  account,
+                    energy:  energy(500),
+                    payload: Payload::Transfer {
+                        to_address: AccountAddress([2u8; 32]),
+                        amount:     Amount::from_micro_ccd(10),
+                    },
+                    expect:  Some(Expectation {
+                        max_energy: Some(energy(501)),
+                        ..expectation(false, Some(RejectReason::OutOfEnergy))
+                    }),
+                },
+                ScenarioStep::InvokeInstance {
+                    context: ContractContext::new(
+                        ContractAddress::new(1, 0),
+                        OwnedReceiveName::new_unchecked("counter.view".into()),
+                    ),
+                    expect:  None,
+                },
+                ScenarioStep::AssertBalance {
+                    account,
+                    amount: Amount::from_micro_ccd(990),
+                },
+            ],
+            continue_on_failure: true,
+        };
+        let json = serde_json::to_value(&scenario).expect("Scenarios serialize.");
+        assert_eq!(json["steps"][0]["type"], "loadBlock");
+        assert_eq!(json["steps"][0]["block"], "@10");
+        assert_eq!(json["steps"][2]["duration"], 1500);
+        assert_eq!(json["continueOnFailure"], true);
+
+        let parsed: DryRunScenario =
+            serde_json::from_value(json.clone()).expect("Scenarios deserialize.");
+        assert_eq!(parsed.steps.len(), scenario.steps.len());
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+
+        // Optional fields can be omitted.
+        let parsed: DryRunScenario =
+            serde_json::from_value(serde_json::json!({ "steps": [] })).unwrap();
+        assert_eq!(parsed.name, None);
+        assert!(!parsed.continue_on_failure);
+    }
+}
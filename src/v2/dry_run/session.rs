@@ -0,0 +1,529 @@
+//! Managed dry-run sessions.
+//!
+//! A [`DryRun`] session is limited by a timeout and an energy quota set by the
+//! server. Once either is exhausted, the server terminates the session and
+//! all further operations fail with [`DryRunError::CallError`].
+//!
+//! A [`ManagedDryRun`] tracks the remaining quota and time of the underlying
+//! session, and opens a fresh session when the next operation would exceed
+//! either limit. The operations that modify the state of the session (loading
+//! a block state, minting, setting the timestamp and running transactions) are
+//! recorded, and replayed in the fresh session, so that a simulation can
+//! continue past the limits of a single session.
+use super::{
+    BlockStateLoaded, DryRun, DryRunError, DryRunResult, DryRunTransaction, InvokeInstanceSuccess,
+    MintedToAccount, TimestampSet, TransactionExecuted,
+};
+use crate::{
+    types::{smart_contracts::ContractContext, AccountInfo, InstanceInfo},
+    v2::{self, AccountIdentifier, BlockIdentifier},
+};
+use concordium_base::{
+    base::Energy,
+    common::types::Timestamp,
+    contracts_common::{AccountAddress, Amount, ContractAddress},
+    hashes::BlockHash,
+};
+use futures::{future::BoxFuture, FutureExt};
+use std::time::{Duration, Instant};
+
+/// Energy cost of [`DryRun::load_block_state`].
+const LOAD_BLOCK_STATE_COST: u64 = 2000;
+/// Energy cost of [`DryRun::get_account_info`] and
+/// [`DryRun::get_instance_info`].
+const QUERY_COST: u64 = 200;
+/// Base energy cost of [`DryRun::invoke_instance`].
+const INVOKE_INSTANCE_COST: u64 = 200;
+/// Energy cost of [`DryRun::set_timestamp`].
+const SET_TIMESTAMP_COST: u64 = 50;
+/// Energy cost of [`DryRun::mint_to_account`].
+const MINT_TO_ACCOUNT_COST: u64 = 400;
+/// Base energy cost of [`DryRun::run_transaction`].
+const RUN_TRANSACTION_COST: u64 = 400;
+
+/// An operation that modifies the state of a dry-run session, and must be
+/// replayed when a fresh session is opened.
+#[derive(Debug, Clone)]
+enum SetupStep {
+    /// Load the state of the block with the given hash. The hash is used
+    /// instead of the original block identifier so that the same state is
+    /// loaded when replaying, even if the original identifier was relative,
+    /// e.g., [`BlockIdentifier::LastFinal`].
+    LoadBlockState(BlockHash),
+    Mint {
+        account: AccountAddress,
+        amount:  Amount,
+    },
+    SetTimestamp(Timestamp),
+    RunTransaction(DryRunTransaction),
+}
+
+impl SetupStep {
+    /// Replay the step in the given session, returning the remaining quota.
+    async fn replay(&self, session: &mut DryRun) -> Result<Energy, DryRunError> {
+        let quota_remaining = match self {
+            SetupStep::LoadBlockState(hash) => {
+                session
+                    .load_block_state(BlockIdentifier::Given(*hash))
+                    .await?
+                    .quota_remaining
+            }
+            SetupStep::Mint { account, amount } => {
+                session
+                    .mint_to_account(account, *amount)
+                    .await?
+                    .quota_remaining
+            }
+            SetupStep::SetTimestamp(timestamp) => {
+                session.set_timestamp(*timestamp).await?.quota_remaining
+            }
+            SetupStep::RunTransaction(transaction) => {
+                session
+                    .run_transaction(transaction.clone())
+                    .await?
+                    .quota_remaining
+            }
+        };
+        Ok(quota_remaining)
+    }
+}
+
+/// A dry-run session that is transparently renewed before the server-imposed
+/// timeout or energy quota is exhausted.
+///
+/// The operations mirror those of [`DryRun`]. Before each operation its energy
+/// cost is estimated, and if it would exceed the remaining quota, or the
+/// session is about to time out, the current session is closed and a fresh
+/// session is opened in which all previous state-modifying operations are
+/// replayed. If an operation nevertheless fails because a limit was hit, the
+/// session is renewed and the operation retried once.
+///
+/// Note that the energy used by replaying counts towards the quota of the
+/// fresh session. If the replay alone would exhaust the quota, the operation
+/// fails with a `RESOURCE_EXHAUSTED` [`DryRunError::CallError`].
+pub struct ManagedDryRun {
+    client:          v2::Client,
+    /// The current session.
+    session:         DryRun,
+    /// The time at which the current session was opened.
+    started:         Instant,
+    /// The remaining energy quota of the current session.
+    quota_remaining: Energy,
+    /// The state-modifying operations performed so far, in order.
+    steps:           Vec<SetupStep>,
+    /// Time before the server timeout at which the session is renewed.
+    time_margin:     Duration,
+    /// Energy that is kept in reserve in addition to the estimated cost of
+    /// each operation.
+    energy_margin:   Energy,
+    /// The number of times the session has been renewed.
+    renewals:        u32,
+}
+
+impl ManagedDryRun {
+    /// Open a new managed dry-run session using the given client. As for
+    /// [`DryRun`], a block state must be loaded using
+    /// [`load_block_state`](Self::load_block_state) before other operations
+    /// can succeed.
+    ///
+    /// By default, the session is renewed 1 second before the server timeout,
+    /// and no energy is reserved beyond the estimated cost of each operation.
+    pub async fn new(mut client: v2::Client) -> v2::QueryResult<Self> {
+        let started = Instant::now();
+        let session = client.begin_dry_run().await?;
+        let quota_remaining = session.energy_quota();
+        Ok(Self {
+            client,
+            session,
+            started,
+            quota_remaining,
+            steps: Vec::new(),
+            time_margin: Duration::from_secs(1),
+            energy_margin: Energy::from(0),
+            renewals: 0,
+        })
+    }
+
+    /// Set the time before the server-imposed timeout at which the session is
+    /// renewed. This should account for the latency of the operations.
+    pub fn set_time_margin(self, time_margin: Duration) -> Self {
+        Self {
+            time_margin,
+            ..self
+        }
+    }
+
+    /// Set the amount of energy that should remain in the quota in addition
+    /// to the estimated cost of an operation for the operation to be performed
+    /// in the current session.
+    pub fn set_energy_margin(self, energy_margin: Energy) -> Self {
+        Self {
+            energy_margin,
+            ..self
+        }
+    }
+
+    /// The remaining energy quota of the current session.
+    pub fn quota_remaining(&self) -> Energy { self.quota_remaining }
+
+    /// The time remaining until the current session times out.
+    pub fn time_remaining(&self) -> Duration {
+        self.session
+            .timeout()
+            .saturating_sub(self.started.elapsed())
+    }
+
+    /// The number of times a fresh session has been opened.
+    pub fn renewals(&self) -> u32 { self.renewals }
+
+    /// Load the state from the specified block. This discards all state
+    /// modifications made so far, so they are no longer replayed on renewal.
+    ///
+    /// See [`DryRun::load_block_state`].
+    pub async fn load_block_state(
+        &mut self,
+        bi: impl v2::IntoBlockIdentifier,
+    ) -> DryRunResult<BlockStateLoaded> {
+        let bi = bi.into_block_identifier();
+        let result = self
+            .execute(LOAD_BLOCK_STATE_COST, move |session| {
+                async move { session.load_block_state(bi).await }.boxed()
+            })
+            .await?;
+        record_step(
+            &mut self.steps,
+            SetupStep::LoadBlockState(result.inner.block_hash),
+        );
+        Ok(result)
+    }
+
+    /// Get the account information for a specified account in the current
+    /// state.
+    ///
+    /// See [`DryRun::get_account_info`].
+    pub async fn get_account_info(&mut self, acc: &AccountIdentifier) -> DryRunResult<AccountInfo> {
+        let acc = *acc;
+        self.execute(QUERY_COST, move |session| {
+            async move { session.get_account_info(&acc).await }.boxed()
+        })
+        .await
+    }
+
+    /// Get the details of a specified smart contract instance in the current
+    /// state.
+    ///
+    /// See [`DryRun::get_instance_info`].
+    pub async fn get_instance_info(
+        &mut self,
+        address: &ContractAddress,
+    ) -> DryRunResult<InstanceInfo> {
+        let address = *address;
+        self.execute(QUERY_COST, move |session| {
+            async move { session.get_instance_info(&address).await }.boxed()
+        })
+        .await
+    }
+
+    /// Invoke an entrypoint on a smart contract instance in the current state.
+    /// Any changes this would make to the state will be rolled back, so the
+    /// invocation is not replayed on renewal.
+    ///
+    /// The cost of the invocation is estimated from
+    /// [`energy`](ContractContext::energy) of the context. If it is not set,
+    /// only the base cost is accounted for, and the session is renewed only if
+    /// the invocation fails because a limit is exceeded.
+    ///
+    /// See [`DryRun::invoke_instance`].
+    pub async fn invoke_instance(
+        &mut self,
+        context: &ContractContext,
+    ) -> DryRunResult<InvokeInstanceSuccess> {
+        let cost = INVOKE_INSTANCE_COST + context.energy.map_or(0, |energy| energy.energy);
+        self.execute(cost, |session| {
+            let context = context.clone();
+            async move { session.invoke_instance(&context).await }.boxed()
+        })
+        .await
+    }
+
+    /// Update the current timestamp for subsequent operations.
+    ///
+    /// See [`DryRun::set_timestamp`].
+    pub async fn set_timestamp(&mut self, timestamp: Timestamp) -> DryRunResult<TimestampSet> {
+        let result = self
+            .execute(SET_TIMESTAMP_COST, move |session| {
+                async move { session.set_timestamp(timestamp).await }.boxed()
+            })
+            .await?;
+        record_step(&mut self.steps, SetupStep::SetTimestamp(timestamp));
+        Ok(result)
+    }
+
+    /// Mint a specified amount and award it to a specified account.
+    ///
+    /// See [`DryRun::mint_to_account`].
+    pub async fn mint_to_account(
+        &mut self,
+        account_address: &AccountAddress,
+        mint_amount: Amount,
+    ) -> DryRunResult<MintedToAccount> {
+        let account = *account_address;
+        let result = self
+            .execute(MINT_TO_ACCOUNT_COST, move |session| {
+                async move { session.mint_to_account(&account, mint_amount).await }.boxed()
+            })
+            .await?;
+        record_step(&mut self.steps, SetupStep::Mint {
+            account,
+            amount: mint_amount,
+        });
+        Ok(result)
+    }
+
+    /// Dry-run a transaction, updating the state of the session accordingly.
+    /// The transaction is replayed on renewal, also if it was rejected, since
+    /// a rejected transaction still charges the sender.
+    ///
+    /// See [`DryRun::run_transaction`].
+    pub async fn run_transaction(
+        &mut self,
+        transaction: DryRunTransaction,
+    ) -> DryRunResult<TransactionExecuted> {
+        let cost = RUN_TRANSACTION_COST + transaction.energy_amount.energy;
+        let result = self
+            .execute(cost, |session| {
+                let transaction = transaction.clone();
+                async move { session.run_transaction(transaction).await }.boxed()
+            })
+            .await?;
+        record_step(&mut self.steps, SetupStep::RunTransaction(transaction));
+        Ok(result)
+    }
+
+    /// Close the current session. Subsequent operations will open a fresh
+    /// session and replay the state-modifying operations performed so far.
+    pub fn close(&mut self) {
+        self.session.close();
+        // Force renewal on the next operation.
+        self.quota_remaining = Energy::from(0);
+    }
+
+    /// Whether the current session is expected to have sufficient quota and
+    /// time left for an operation with the given estimated cost.
+    fn has_capacity(&self, cost: u64) -> bool {
+        has_capacity(
+            self.quota_remaining,
+            self.energy_margin,
+            self.time_remaining(),
+            self.time_margin,
+            cost,
+        )
+    }
+
+    /// Run an operation, renewing the session before or after it as needed.
+    async fn execute<A>(
+        &mut self,
+        cost: u64,
+        operation: impl Fn(&mut DryRun) -> BoxFuture<'_, DryRunResult<A>>,
+    ) -> DryRunResult<A> {
+        if !self.has_capacity(cost) {
+            self.renew(cost).await?;
+        }
+        let result = operation(&mut self.session).await;
+        match self.track(result) {
+            Err(DryRunError::CallError(status)) if is_limit_exceeded(&status) => {
+                self.renew(cost).await?;
+                let result = operation(&mut self.session).await;
+                self.track(result)
+            }
+            result => result,
+        }
+    }
+
+    /// Record the remaining quota reported by the result of an operation.
+    fn track<A>(&mut self, result: DryRunResult<A>) -> DryRunResult<A> {
+        match &result {
+            Ok(success) => self.quota_remaining = success.quota_remaining,
+            Err(DryRunError::OperationFailed {
+                quota_remaining, ..
+            }) => self.quota_remaining = *quota_remaining,
+            Err(DryRunError::CallError(_)) => self.quota_remaining = Energy::from(0),
+        }
+        result
+    }
+
+    /// Close the current session, open a fresh one, and replay all recorded
+    /// steps in it. Fails if the fresh session would not have sufficient
+    /// quota left for an operation of the given cost after replaying.
+    async fn renew(&mut self, cost: u64) -> Result<(), DryRunError> {
+        self.session.close();
+        self.quota_remaining = Energy::from(0);
+        let started = Instant::now();
+        let mut session = self.client.begin_dry_run().await.map_err(|e| match e {
+            v2::QueryError::RPCError(v2::RPCError::CallError(status)) => status,
+            e => tonic::Status::unknown(e.to_string()),
+        })?;
+        self.renewals += 1;
+        let mut quota_remaining = session.energy_quota();
+        for step in &self.steps {
+            quota_remaining = step.replay(&mut session).await.map_err(|e| {
+                tonic::Status::aborted(format!(
+                    "Replaying {step:?} in a fresh dry-run session failed: {e}"
+                ))
+            })?;
+        }
+        self.session = session;
+        self.started = started;
+        self.quota_remaining = quota_remaining;
+        if !self.has_capacity(cost) {
+            return Err(tonic::Status::resource_exhausted(
+                "Insufficient quota or time left in a fresh dry-run session after replaying the \
+                 previous operations.",
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Record a state-modifying operation to be replayed on renewal. Loading a
+/// block state discards the effects of all previous operations, so they are
+/// no longer replayed.
+fn record_step(steps: &mut Vec<SetupStep>, step: SetupStep) {
+    if let SetupStep::LoadBlockState(_) = step {
+        steps.clear();
+    }
+    steps.push(step);
+}
+
+/// Whether a session with the given remaining quota and time has capacity for
+/// an operation with the given estimated cost, keeping the given margins.
+fn has_capacity(
+    quota_remaining: Energy,
+    energy_margin: Energy,
+    time_remaining: Duration,
+    time_margin: Duration,
+    cost: u64,
+) -> bool {
+    let required = cost.saturating_add(energy_margin.energy);
+    quota_remaining.energy >= required && time_remaining > time_margin
+}
+
+/// Whether the status indicates that the quota or timeout of the session was
+/// exceeded.
+fn is_limit_exceeded(status: &tonic::Status) -> bool {
+    matches!(
+        status.code(),
+        tonic::Code::ResourceExhausted | tonic::Code::DeadlineExceeded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_base::transactions::Payload;
+
+    #[test]
+    /// Test the quota and time thresholds at which a session is renewed.
+    fn capacity_thresholds() {
+        let secs = Duration::from_secs;
+        let energy = |energy| Energy { energy };
+        // The quota must cover the cost of the operation.
+        assert!(has_capacity(
+            energy(2000),
+            energy(0),
+            secs(10),
+            secs(1),
+            LOAD_BLOCK_STATE_COST
+        ));
+        assert!(!has_capacity(
+            energy(1999),
+            energy(0),
+            secs(10),
+            secs(1),
+            LOAD_BLOCK_STATE_COST
+        ));
+        // The energy margin is kept in reserve.
+        assert!(has_capacity(
+            energy(600),
+            energy(200),
+            secs(10),
+            secs(1),
+            MINT_TO_ACCOUNT_COST
+        ));
+        assert!(!has_capacity(
+            energy(599),
+            energy(200),
+            secs(10),
+            secs(1),
+            MINT_TO_ACCOUNT_COST
+        ));
+        assert!(!has_capacity(
+            energy(QUERY_COST),
+            energy(u64::MAX),
+            secs(10),
+            secs(1),
+            QUERY_COST
+        ));
+        // The session is renewed before the time margin is reached.
+        assert!(has_capacity(
+            energy(2000),
+            energy(0),
+            Duration::from_millis(1001),
+            secs(1),
+            QUERY_COST
+        ));
+        assert!(!has_capacity(
+            energy(2000),
+            energy(0),
+            secs(1),
+            secs(1),
+            QUERY_COST
+        ));
+        assert!(!has_capacity(
+            energy(2000),
+            energy(0),
+            Duration::ZERO,
+            Duration::ZERO,
+            QUERY_COST
+        ));
+    }
+
+    #[test]
+    /// Test that operations are replayed in order, starting from the last
+    /// loaded block state.
+    fn replay_order() {
+        let account = AccountAddress([1u8; 32]);
+        let mint = SetupStep::Mint {
+            account,
+            amount: Amount::from_micro_ccd(1000),
+        };
+        let payload = Payload::Transfer {
+            to_address: AccountAddress([2u8; 32]),
+            amount:     Amount::from_micro_ccd(10),
+        };
+        let transaction = DryRunTransaction::new(account, Energy::from(500), &payload);
+        let mut steps = Vec::new();
+        record_step(&mut steps, mint.clone());
+        record_step(
+            &mut steps,
+            SetupStep::LoadBlockState(BlockHash::new([1u8; 32])),
+        );
+        record_step(&mut steps, mint);
+        record_step(&mut steps, SetupStep::SetTimestamp(Timestamp { millis: 5 }));
+        record_step(&mut steps, SetupStep::RunTransaction(transaction));
+        assert!(matches!(steps[..], [
+            SetupStep::LoadBlockState(hash),
+            SetupStep::Mint { .. },
+            SetupStep::SetTimestamp(Timestamp { millis: 5 }),
+            SetupStep::RunTransaction(_)
+        ] if hash == BlockHash::new([1u8; 32])));
+
+        record_step(
+            &mut steps,
+            SetupStep::LoadBlockState(BlockHash::new([2u8; 32])),
+        );
+        assert!(matches!(steps[..], [SetupStep::LoadBlockState(hash)]
+            if hash == BlockHash::new([2u8; 32])));
+    }
+}
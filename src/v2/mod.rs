@@ -53,6 +53,7 @@ pub use tonic::{
 use self::dry_run::WithRemainingQuota;
 
 mod conversions;
+pub mod batch;
 pub mod dry_run;
 #[path = "generated/concordium.v2.rs"]
 #[allow(
@@ -61,6 +62,7 @@ pub mod dry_run;
     clippy::derive_partial_eq_without_eq
 )]
 mod generated;
+pub mod preflight;
 pub mod proto_schema_version;
 
 /// A client for gRPC API v2 of the Concordium node. Can be used to control the
@@ -0,0 +1,388 @@
+//! Pre-flight validation of block items before they are sent to the node.
+//!
+//! [`Client::preflight`] checks a fully signed account transaction against
+//! the last finalized state of the chain, and reports every problem that
+//! would prevent it from being accepted or executed successfully.
+use super::{
+    dry_run::{DryRunError, DryRunTransaction, ErrorResult, TransactionExecuted},
+    AccountIdentifier, BlockIdentifier, Client, QueryResult,
+};
+use crate::types::{
+    hashes::BlockHash,
+    transactions::{
+        compute_transaction_sign_hash, verify_signature_transaction_sign_hash, AccountTransaction,
+        BlockItem, Payload, PayloadLike, TransactionTime,
+    },
+    Nonce, RejectReason,
+};
+use concordium_base::contracts_common::{AccountAddress, Amount};
+
+/// The outcome of [`Client::preflight`].
+#[derive(Debug)]
+pub struct PreflightVerdict {
+    /// The block whose state the transaction was checked against.
+    pub block_hash: Option<BlockHash>,
+    /// All problems found with the transaction. If this is empty, the
+    /// transaction is expected to be accepted by the node and executed
+    /// successfully.
+    pub problems:   Vec<PreflightProblem>,
+    /// The result of dry-running the transaction, if it could be dry-run.
+    pub dry_run:    Option<TransactionExecuted>,
+}
+
+impl PreflightVerdict {
+    /// Whether no problems were found with the transaction.
+    pub fn is_ok(&self) -> bool { self.problems.is_empty() }
+}
+
+/// A problem found by [`Client::preflight`].
+#[derive(thiserror::Error, Debug)]
+pub enum PreflightProblem {
+    /// Only account transactions can be checked.
+    #[error("The block item is not an account transaction.")]
+    NotAccountTransaction,
+    /// The sender account does not exist.
+    #[error("The sender account {sender} does not exist.")]
+    AccountNotFound { sender: AccountAddress },
+    /// The signatures do not satisfy the access structure of the sender
+    /// account, i.e., some signature is invalid, or the signature threshold is
+    /// not met.
+    #[error("The signatures are not valid for the sender account.")]
+    InvalidSignatures,
+    /// The nonce has already been used by a previous transaction of the
+    /// sender.
+    #[error(
+        "The nonce {} has already been used. The next nonce is {}.",
+        .nonce.nonce,
+        .next_nonce.nonce
+    )]
+    NonceAlreadyUsed {
+        nonce:      Nonce,
+        next_nonce: Nonce,
+    },
+    /// The nonce is higher than the next nonce of the sender. The transaction
+    /// can only be executed after the transactions with the preceding nonces.
+    #[error(
+        "The nonce {} is ahead of the next nonce {}.",
+        .nonce.nonce,
+        .next_nonce.nonce
+    )]
+    NonceGap {
+        nonce:      Nonce,
+        next_nonce: Nonce,
+    },
+    /// The transaction has expired according to the node's clock.
+    #[error(
+        "The transaction expired at {} seconds since the Unix epoch, the node's time is \
+         {node_time}.",
+        .expiry.seconds
+    )]
+    Expired {
+        expiry:    TransactionTime,
+        node_time: chrono::DateTime<chrono::Utc>,
+    },
+    /// The available balance of the sender does not cover the maximum cost of
+    /// the transaction together with the amount it transfers.
+    #[error(
+        "The available balance {available} is insufficient, {required} is required to cover the \
+         energy and transferred amount."
+    )]
+    InsufficientBalance {
+        required:  Amount,
+        available: Amount,
+    },
+    /// The payload of the transaction could not be deserialized.
+    #[error("The transaction payload is malformed.")]
+    MalformedPayload,
+    /// The transaction would be rejected.
+    #[error("The transaction would be rejected: {reason:?}")]
+    Rejected { reason: RejectReason },
+    /// The dry-run could not execute the transaction.
+    #[error("The dry-run of the transaction failed: {0}")]
+    DryRunFailed(#[from] ErrorResult),
+    /// The transaction could not be dry-run, for example because the node does
+    /// not support dry-running or its resources are exhausted.
+    #[error("The transaction could not be dry-run: {0}")]
+    DryRunUnavailable(#[from] tonic::Status),
+}
+
+impl Client {
+    /// Check a signed block item before sending it with
+    /// [`send_block_item`](Client::send_block_item). The transaction is checked
+    /// against the state of the last finalized block as follows:
+    ///
+    ///  * the signatures are checked against the access structure of the sender
+    ///    account;
+    ///  * the nonce is compared to the next nonce of the sender account as
+    ///    reported by
+    ///    [`get_next_account_sequence_number`](Client::get_next_account_sequence_number);
+    ///  * the expiry time is compared to the local time of the node;
+    ///  * the available balance of the sender is checked to cover the cost of
+    ///    the energy limit of the transaction, plus any amount transferred by
+    ///    the payload;
+    ///  * the transaction is dry-run as the sender.
+    ///
+    /// All problems found are listed in the returned [`PreflightVerdict`].
+    /// An error is only returned if querying the node fails.
+    ///
+    /// Only account transactions are supported. For other block items the
+    /// verdict contains [`PreflightProblem::NotAccountTransaction`].
+    pub async fn preflight<P: PayloadLike>(
+        &mut self,
+        bi: &BlockItem<P>,
+    ) -> QueryResult<PreflightVerdict> {
+        let mut problems = Vec::new();
+        let BlockItem::AccountTransaction(at) = bi else {
+            problems.push(PreflightProblem::NotAccountTransaction);
+            return Ok(PreflightVerdict {
+                block_hash: None,
+                problems,
+                dry_run: None,
+            });
+        };
+        let header = &at.header;
+
+        let node_time = self.get_node_info().await?.local_time;
+        problems.extend(check_expiry(header.expiry, node_time));
+
+        let account_info = match self
+            .get_account_info(
+                &AccountIdentifier::Address(header.sender),
+                BlockIdentifier::LastFinal,
+            )
+            .await
+        {
+            Ok(ai) => ai,
+            Err(e) if e.is_not_found() => {
+                problems.push(PreflightProblem::AccountNotFound {
+                    sender: header.sender,
+                });
+                return Ok(PreflightVerdict {
+                    block_hash: None,
+                    problems,
+                    dry_run: None,
+                });
+            }
+            Err(e) => return Err(e),
+        };
+        let block_hash = account_info.block_hash;
+        let account_info = account_info.response;
+
+        let sign_hash = compute_transaction_sign_hash(header, &at.payload);
+        if !verify_signature_transaction_sign_hash(&account_info, &sign_hash, &at.signature) {
+            problems.push(PreflightProblem::InvalidSignatures);
+        }
+
+        let next_nonce = self
+            .get_next_account_sequence_number(&header.sender)
+            .await?
+            .nonce;
+        problems.extend(check_nonce(header.nonce, next_nonce));
+
+        let encoded = at.payload.encode();
+        let transferred = match encoded.decode() {
+            Ok(payload) => transferred_amount(&payload),
+            Err(_) => {
+                problems.push(PreflightProblem::MalformedPayload);
+                Amount::zero()
+            }
+        };
+        let chain_parameters = self.get_block_chain_parameters(block_hash).await?.response;
+        let energy_cost = chain_parameters.ccd_cost(header.energy_amount);
+        problems.extend(check_balance(
+            energy_cost,
+            transferred,
+            account_info.available_balance,
+        ));
+
+        let dry_run = match self.dry_run_account_transaction(block_hash, at).await {
+            Ok(executed) => {
+                if let Some(reason) = executed.details.is_rejected() {
+                    problems.push(PreflightProblem::Rejected {
+                        reason: reason.clone(),
+                    });
+                }
+                Some(executed)
+            }
+            Err(DryRunError::OperationFailed { result, .. }) => {
+                problems.push(result.into());
+                None
+            }
+            Err(DryRunError::CallError(status)) => {
+                problems.push(status.into());
+                None
+            }
+        };
+
+        Ok(PreflightVerdict {
+            block_hash: Some(block_hash),
+            problems,
+            dry_run,
+        })
+    }
+
+    /// Dry-run the account transaction in the state of the given block.
+    async fn dry_run_account_transaction<P: PayloadLike>(
+        &mut self,
+        block_hash: BlockHash,
+        at: &AccountTransaction<P>,
+    ) -> Result<TransactionExecuted, DryRunError> {
+        let (mut dry_run, _) = self.dry_run(block_hash).await?.inner;
+        let transaction = DryRunTransaction {
+            sender:        at.header.sender,
+            energy_amount: at.header.energy_amount,
+            payload:       at.payload.encode(),
+            signatures:    at
+                .signature
+                .signatures
+                .iter()
+                .flat_map(|(c, keys)| keys.keys().map(move |k| (*c, *k)))
+                .collect(),
+        };
+        let result = dry_run.run_transaction(transaction).await;
+        dry_run.close();
+        Ok(result?.inner)
+    }
+}
+
+/// Check that the transaction has not expired according to the node's time.
+fn check_expiry(
+    expiry: TransactionTime,
+    node_time: chrono::DateTime<chrono::Utc>,
+) -> Option<PreflightProblem> {
+    if i64::try_from(expiry.seconds).unwrap_or(i64::MAX) <= node_time.timestamp() {
+        return Some(PreflightProblem::Expired { expiry, node_time });
+    }
+    None
+}
+
+/// Check the nonce of the transaction against the next nonce of the sender.
+fn check_nonce(nonce: Nonce, next_nonce: Nonce) -> Option<PreflightProblem> {
+    if nonce < next_nonce {
+        Some(PreflightProblem::NonceAlreadyUsed { nonce, next_nonce })
+    } else if nonce > next_nonce {
+        Some(PreflightProblem::NonceGap { nonce, next_nonce })
+    } else {
+        None
+    }
+}
+
+/// Check that the available balance of the sender covers the cost of the
+/// energy and the transferred amount.
+fn check_balance(
+    energy_cost: Amount,
+    transferred: Amount,
+    available: Amount,
+) -> Option<PreflightProblem> {
+    let required = energy_cost
+        .checked_add(transferred)
+        .unwrap_or(Amount::from_micro_ccd(u64::MAX));
+    if required > available {
+        return Some(PreflightProblem::InsufficientBalance {
+            required,
+            available,
+        });
+    }
+    None
+}
+
+/// The amount of CCD transferred from the sender's public balance by the
+/// payload, not counting the transaction fee.
+fn transferred_amount(payload: &Payload) -> Amount {
+    let scheduled = |schedule: &[(_, Amount)]| {
+        schedule.iter().fold(Amount::zero(), |acc, (_, amount)| {
+            acc.checked_add(*amount).unwrap_or(acc)
+        })
+    };
+    match payload {
+        Payload::Transfer { amount, .. } => *amount,
+        Payload::TransferWithMemo { amount, .. } => *amount,
+        Payload::TransferWithSchedule { schedule, .. } => scheduled(schedule),
+        Payload::TransferWithScheduleAndMemo { schedule, .. } => scheduled(schedule),
+        Payload::TransferToEncrypted { amount } => *amount,
+        Payload::InitContract { payload } => payload.amount,
+        Payload::Update { payload } => payload.amount,
+        _ => Amount::zero(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use concordium_base::common::types::Timestamp;
+
+    fn ccd(micro_ccd: u64) -> Amount { Amount::from_micro_ccd(micro_ccd) }
+
+    #[test]
+    /// Test that transactions expire at their expiry time.
+    fn expiry() {
+        let node_time = chrono::Utc.timestamp_opt(1000, 0).unwrap();
+        let expiry = |seconds| TransactionTime { seconds };
+        assert!(check_expiry(expiry(1001), node_time).is_none());
+        assert!(matches!(
+            check_expiry(expiry(1000), node_time),
+            Some(PreflightProblem::Expired { .. })
+        ));
+        assert!(check_expiry(expiry(999), node_time).is_some());
+        assert!(check_expiry(expiry(u64::MAX), node_time).is_none());
+    }
+
+    #[test]
+    /// Test comparing the nonce with the next nonce of the sender.
+    fn nonce() {
+        let nonce = |nonce| Nonce { nonce };
+        assert!(check_nonce(nonce(5), nonce(5)).is_none());
+        assert!(matches!(
+            check_nonce(nonce(4), nonce(5)),
+            Some(PreflightProblem::NonceAlreadyUsed { nonce, next_nonce })
+                if nonce.nonce == 4 && next_nonce.nonce == 5
+        ));
+        assert!(matches!(
+            check_nonce(nonce(7), nonce(5)),
+            Some(PreflightProblem::NonceGap { nonce, next_nonce })
+                if nonce.nonce == 7 && next_nonce.nonce == 5
+        ));
+    }
+
+    #[test]
+    /// Test that the balance must cover both the energy and the transferred
+    /// amount.
+    fn balance() {
+        assert!(check_balance(ccd(10), ccd(90), ccd(100)).is_none());
+        assert!(matches!(
+            check_balance(ccd(10), ccd(91), ccd(100)),
+            Some(PreflightProblem::InsufficientBalance { required, available })
+                if required == ccd(101) && available == ccd(100)
+        ));
+        assert!(check_balance(ccd(1), ccd(u64::MAX), ccd(u64::MAX)).is_some());
+    }
+
+    #[test]
+    /// Test the amounts transferred by payloads.
+    fn transferred() {
+        let to_address = AccountAddress([1u8; 32]);
+        assert_eq!(
+            transferred_amount(&Payload::Transfer {
+                to_address,
+                amount: ccd(10),
+            }),
+            ccd(10)
+        );
+        let schedule = vec![
+            (Timestamp { millis: 1 }, ccd(10)),
+            (Timestamp { millis: 2 }, ccd(20)),
+        ];
+        assert_eq!(
+            transferred_amount(&Payload::TransferWithSchedule {
+                to: to_address,
+                schedule,
+            }),
+            ccd(30)
+        );
+        assert_eq!(
+            transferred_amount(&Payload::TransferToEncrypted { amount: ccd(5) }),
+            ccd(5)
+        );
+    }
+}